@@ -0,0 +1,510 @@
+use super::structures::*;
+
+/// Number of bins used to track the distribution of the stack while it is being simulated. The
+/// percentiles and half standard deviations are found from these bins, so their resolution sets
+/// the accuracy of those results.
+const FINE_BINS: usize = 10000;
+
+/// Running mean and central moments of a stream of values. Values are added one at a time with
+/// Welford's method, and accumulators from separate threads are combined with the pairwise
+/// formulas of Chan and Pébay, so the whole stream never needs to be held in memory.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Moments {
+    n: u64,
+    mean: f64,
+    m2: f64,
+    m3: f64,
+    m4: f64,
+}
+impl Moments {
+    pub fn push(&mut self, x: f64) {
+        let n1 = self.n as f64;
+        self.n += 1;
+        let n = self.n as f64;
+        let delta = x - self.mean;
+        let delta_n = delta / n;
+        let delta_n2 = delta_n * delta_n;
+        let term1 = delta * delta_n * n1;
+        self.mean += delta_n;
+        self.m4 += term1 * delta_n2 * (n * n - 3.0 * n + 3.0) + 6.0 * delta_n2 * self.m2
+            - 4.0 * delta_n * self.m3;
+        self.m3 += term1 * delta_n * (n - 2.0) - 3.0 * delta_n * self.m2;
+        self.m2 += term1;
+    }
+    pub fn merge(&mut self, other: &Moments) {
+        if other.n == 0 {
+            return;
+        }
+        if self.n == 0 {
+            *self = *other;
+            return;
+        }
+        let (na, nb) = (self.n as f64, other.n as f64);
+        let n = na + nb;
+        let delta = other.mean - self.mean;
+        let delta2 = delta * delta;
+        let m2 = self.m2 + other.m2 + delta2 * na * nb / n;
+        let m3 = self.m3
+            + other.m3
+            + delta2 * delta * na * nb * (na - nb) / (n * n)
+            + 3.0 * delta * (na * other.m2 - nb * self.m2) / n;
+        let m4 = self.m4
+            + other.m4
+            + delta2 * delta2 * na * nb * (na * na - na * nb + nb * nb) / (n * n * n)
+            + 6.0 * delta2 * (na * na * other.m2 + nb * nb * self.m2) / (n * n)
+            + 4.0 * delta * (na * other.m3 - nb * self.m3) / n;
+        self.n += other.n;
+        self.mean += delta * nb / n;
+        self.m2 = m2;
+        self.m3 = m3;
+        self.m4 = m4;
+    }
+    pub fn count(&self) -> u64 {
+        self.n
+    }
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+    /// Population variance of the values
+    pub fn variance(&self) -> f64 {
+        self.m2 / self.n as f64
+    }
+    /// Standard error of the mean
+    pub fn standard_error_mean(&self) -> f64 {
+        (self.variance() / self.n as f64).sqrt()
+    }
+    /// Standard error of the standard deviation. This uses the fourth moment, so it holds for
+    /// values that are not normally distributed.
+    pub fn standard_error_stddev(&self) -> f64 {
+        let n = self.n as f64;
+        let variance = self.variance();
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        let variance_of_variance = (self.m4 / n - variance * variance) / n;
+        variance_of_variance.max(0.0).sqrt() / (2.0 * variance.sqrt())
+    }
+    /// Standard error of the skewness, for values that are close to normally distributed
+    pub fn standard_error_skewness(&self) -> f64 {
+        let n = self.n as f64;
+        if n < 4.0 {
+            return f64::INFINITY;
+        }
+        (6.0 * n * (n - 1.0) / ((n - 2.0) * (n + 1.0) * (n + 3.0))).sqrt()
+    }
+    /// Standard error of the excess kurtosis, for values that are close to normally distributed
+    pub fn standard_error_kurtosis(&self) -> f64 {
+        let n = self.n as f64;
+        if n < 4.0 {
+            return f64::INFINITY;
+        }
+        2.0 * self.standard_error_skewness() * ((n * n - 1.0) / ((n - 3.0) * (n + 5.0))).sqrt()
+    }
+    pub fn skewness(&self) -> f64 {
+        (self.n as f64).sqrt() * self.m3 / self.m2.powf(1.5)
+    }
+    /// Excess kurtosis, which is zero for a normal distribution
+    pub fn kurtosis(&self) -> f64 {
+        self.n as f64 * self.m4 / (self.m2 * self.m2) - 3.0
+    }
+}
+
+/// Running covariance of one contributor with the stack.
+#[derive(Debug, Clone, Copy, Default)]
+struct CoMoment {
+    mean: f64,
+    comoment: f64,
+}
+
+/// A histogram bin, which also keeps the sum and sum of squares of its values so the spread of
+/// the stack can be found without keeping the values. Sums are taken relative to a reference point
+/// within the bin to avoid losing precision.
+#[derive(Debug, Clone, Copy, Default)]
+struct Bin {
+    count: u64,
+    sum: f64,
+    sum_sq: f64,
+}
+impl Bin {
+    fn push(&mut self, x: f64, reference: f64) {
+        let d = x - reference;
+        self.count += 1;
+        self.sum += d;
+        self.sum_sq += d * d;
+    }
+    fn merge(&mut self, other: &Bin) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.sum_sq += other.sum_sq;
+    }
+    /// The sum of squared distances of the values in this bin from `point`
+    fn sum_sq_from(&self, reference: f64, point: f64) -> f64 {
+        let offset = reference - point;
+        self.sum_sq + 2.0 * offset * self.sum + self.count as f64 * offset * offset
+    }
+}
+
+/// Collects everything needed to summarize a Monte Carlo simulation, using memory that does not
+/// depend on the number of iterations.
+#[derive(Debug, Clone)]
+pub struct StackAccumulator {
+    moments: Moments,
+    contributors: Vec<CoMoment>,
+    min: f64,
+    max: f64,
+    lower: f64,
+    upper: f64,
+    bins: Vec<Bin>,
+    underflow: Bin,
+    overflow: Bin,
+    lower_spec: f64,
+    upper_spec: f64,
+    n_out_of_spec: u64,
+    /// The Y component of the vector that closes a vector loop, whose X component is the stack
+    across: Option<Box<StackAccumulator>>,
+}
+impl StackAccumulator {
+    /// Creates an empty accumulator for a stack of `n_tols` tolerances. The stack is binned between
+    /// `lower` and `upper`; values outside of this range are still counted, at lower resolution.
+    pub fn new(n_tols: usize, lower: f64, upper: f64, parameters: &Parameters) -> Self {
+        StackAccumulator {
+            moments: Moments::default(),
+            contributors: vec![CoMoment::default(); n_tols],
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            lower,
+            upper,
+            bins: vec![Bin::default(); FINE_BINS],
+            underflow: Bin::default(),
+            overflow: Bin::default(),
+            lower_spec: parameters.lower_spec.unwrap_or(f64::NEG_INFINITY),
+            upper_spec: parameters.upper_spec.unwrap_or(f64::INFINITY),
+            n_out_of_spec: 0,
+            across: None,
+        }
+    }
+
+    /// Also accumulates the Y component of the vector that closes a vector loop, binned between
+    /// `lower` and `upper`.
+    pub fn with_across(mut self, lower: f64, upper: f64) -> Self {
+        self.across = Some(Box::new(StackAccumulator::new(
+            0,
+            lower,
+            upper,
+            &Parameters::default(),
+        )));
+        self
+    }
+
+    fn bin_width(&self) -> f64 {
+        (self.upper - self.lower) / self.bins.len() as f64
+    }
+
+    fn bin_center(&self, bin: usize) -> f64 {
+        self.lower + (bin as f64 + 0.5) * self.bin_width()
+    }
+
+    /// Adds one iteration of the simulation, given the stackup and the sample of each tolerance.
+    pub fn push(&mut self, stack: f64, samples: &[f64]) {
+        // The covariance update needs the mean of the stack both before and after this value.
+        let n = (self.moments.count() + 1) as f64;
+        let stack_mean = self.moments.mean();
+        let stack_mean_new = stack_mean + (stack - stack_mean) / n;
+        for (contributor, sample) in self.contributors.iter_mut().zip(samples) {
+            let delta = sample - contributor.mean;
+            contributor.mean += delta / n;
+            contributor.comoment += delta * (stack - stack_mean_new);
+        }
+        self.moments.push(stack);
+
+        self.min = self.min.min(stack);
+        self.max = self.max.max(stack);
+        if stack < self.lower {
+            self.underflow.push(stack, self.lower);
+        } else if stack >= self.upper {
+            self.overflow.push(stack, self.upper);
+        } else {
+            let bin = (((stack - self.lower) / self.bin_width()) as usize).min(self.bins.len() - 1);
+            let center = self.bin_center(bin);
+            self.bins[bin].push(stack, center);
+        }
+        if stack < self.lower_spec || stack > self.upper_spec {
+            self.n_out_of_spec += 1;
+        }
+    }
+
+    /// Adds the Y component of the vector that closes a vector loop, for the same iteration as the
+    /// last stackup.
+    pub fn push_across(&mut self, across: f64) {
+        if let Some(accumulator) = self.across.as_mut() {
+            accumulator.push(across, &[]);
+        }
+    }
+
+    /// Combines the results of another accumulator with the same binning into this one.
+    pub fn merge(&mut self, other: &StackAccumulator) {
+        let (na, nb) = (self.moments.count() as f64, other.moments.count() as f64);
+        if nb > 0.0 {
+            let n = na + nb;
+            let stack_delta = other.moments.mean() - self.moments.mean();
+            for (contributor, other_contributor) in
+                self.contributors.iter_mut().zip(&other.contributors)
+            {
+                let delta = other_contributor.mean - contributor.mean;
+                contributor.comoment +=
+                    other_contributor.comoment + delta * stack_delta * na * nb / n;
+                contributor.mean += delta * nb / n;
+            }
+        }
+        self.moments.merge(&other.moments);
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        for (bin, other_bin) in self.bins.iter_mut().zip(&other.bins) {
+            bin.merge(other_bin);
+        }
+        self.underflow.merge(&other.underflow);
+        self.overflow.merge(&other.overflow);
+        self.n_out_of_spec += other.n_out_of_spec;
+        if let (Some(across), Some(other_across)) = (self.across.as_mut(), &other.across) {
+            across.merge(other_across);
+        }
+    }
+
+    pub fn moments(&self) -> &Moments {
+        &self.moments
+    }
+
+    pub fn across(&self) -> Option<&StackAccumulator> {
+        self.across.as_deref()
+    }
+
+    /// Fraction of the stack variance attributed to each contributor, from the covariance of the
+    /// contributor with the stack.
+    pub fn contributions(&self) -> Vec<f64> {
+        self.contributors
+            .iter()
+            .map(|contributor| contributor.comoment / self.moments.m2)
+            .collect()
+    }
+
+    pub fn fraction_out_of_spec(&self) -> f64 {
+        self.n_out_of_spec as f64 / self.moments.count() as f64
+    }
+
+    /// Standard deviation of the values below and above the mean, respectively. The bin that
+    /// contains the mean is split, assuming its values are spread evenly across the bin.
+    pub fn semi_deviations(&self) -> (f64, f64) {
+        let mean = self.moments.mean();
+        let width = self.bin_width();
+        let (mut n_below, mut sq_below) = (
+            self.underflow.count as f64,
+            self.underflow.sum_sq_from(self.lower, mean),
+        );
+        let (mut n_above, mut sq_above) = (
+            self.overflow.count as f64,
+            self.overflow.sum_sq_from(self.upper, mean),
+        );
+        for (i, bin) in self.bins.iter().enumerate() {
+            if bin.count == 0 {
+                continue;
+            }
+            let center = self.bin_center(i);
+            let (bin_lower, bin_upper) = (center - width / 2.0, center + width / 2.0);
+            if bin_upper <= mean {
+                n_below += bin.count as f64;
+                sq_below += bin.sum_sq_from(center, mean);
+            } else if bin_lower >= mean {
+                n_above += bin.count as f64;
+                sq_above += bin.sum_sq_from(center, mean);
+            } else {
+                let count = bin.count as f64;
+                let fraction_above = (bin_upper - mean) / width;
+                n_above += count * fraction_above;
+                sq_above += count * fraction_above * (bin_upper - mean).powi(2) / 3.0;
+                n_below += count * (1.0 - fraction_above);
+                sq_below += count * (1.0 - fraction_above) * (mean - bin_lower).powi(2) / 3.0;
+            }
+        }
+        let deviation = |sq: f64, n: f64| {
+            if n > 1.0 {
+                (sq / (n - 1.0)).sqrt()
+            } else {
+                0.0
+            }
+        };
+        (deviation(sq_below, n_below), deviation(sq_above, n_above))
+    }
+
+    /// Estimates the value below which fraction `p` of the stack falls, interpolating within the
+    /// bin that contains it.
+    pub fn percentile(&self, p: f64) -> f64 {
+        let target = p * self.moments.count() as f64;
+        let interpolate = |cumulative: f64, count: u64, lower: f64, upper: f64| {
+            lower + (target - cumulative) / count as f64 * (upper - lower)
+        };
+        let mut cumulative = 0.0;
+        if self.underflow.count > 0 {
+            if cumulative + self.underflow.count as f64 >= target {
+                return interpolate(cumulative, self.underflow.count, self.min, self.lower);
+            }
+            cumulative += self.underflow.count as f64;
+        }
+        let width = self.bin_width();
+        for (i, bin) in self.bins.iter().enumerate() {
+            if bin.count == 0 {
+                continue;
+            }
+            if cumulative + bin.count as f64 >= target {
+                let bin_lower = self.lower + i as f64 * width;
+                // Never report a value outside of the range that was actually simulated.
+                return interpolate(cumulative, bin.count, bin_lower, bin_lower + width)
+                    .max(self.min)
+                    .min(self.max);
+            }
+            cumulative += bin.count as f64;
+        }
+        if self.overflow.count > 0 {
+            return interpolate(cumulative, self.overflow.count, self.upper, self.max);
+        }
+        self.max
+    }
+
+    /// A confidence interval for the percentile `p`, found from the order statistics of the
+    /// simulated stack. The number of samples below the true percentile is binomially distributed,
+    /// so the interval spans the ranks that are plausible at 95% confidence. This holds for any
+    /// distribution of the stack.
+    pub fn percentile_interval(&self, p: f64) -> Interval {
+        let n = self.moments.count() as f64;
+        let half_width = 1.96 * (p * (1.0 - p) / n).sqrt();
+        Interval {
+            lower: self.percentile((p - half_width).max(0.0)),
+            upper: self.percentile((p + half_width).min(1.0)),
+        }
+    }
+
+    /// Groups the fine bins into a histogram of about `n_bins` bins, spanning the occupied bins.
+    /// Stack values outside the range of the fine bins are counted in the tails of the histogram.
+    pub fn histogram(&self, n_bins: usize) -> Histogram {
+        let tails = Histogram {
+            below: self.underflow.count as usize,
+            above: self.overflow.count as usize,
+            ..Histogram::default()
+        };
+        let first = self.bins.iter().position(|bin| bin.count > 0);
+        let last = self.bins.iter().rposition(|bin| bin.count > 0);
+        let (first, last) = match (first, last) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return tails,
+        };
+        let occupied = last - first + 1;
+        let group = (occupied - 1) / n_bins + 1;
+        let n_groups = (occupied - 1) / group + 1;
+        let mut counts = vec![0; n_groups];
+        for (i, bin) in self.bins[first..=last].iter().enumerate() {
+            counts[i / group] += bin.count as usize;
+        }
+        let width = self.bin_width();
+        Histogram {
+            lower: self.lower + first as f64 * width,
+            upper: self.lower + (first + n_groups * group) as f64 * width,
+            counts,
+            ..tails
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use rand_chacha::ChaCha8Rng;
+
+    /// A skewed stack of a uniform and an exponential contributor, with the samples of each.
+    fn samples(n: usize) -> Vec<(f64, [f64; 2])> {
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        (0..n)
+            .map(|_| {
+                let uniform: f64 = rng.gen();
+                let exponential = -0.5 * (1.0 - rng.gen::<f64>()).ln();
+                (uniform + exponential, [uniform, exponential])
+            })
+            .collect()
+    }
+
+    fn accumulator() -> StackAccumulator {
+        let mut parameters = Parameters::default();
+        parameters.upper_spec = Some(3.0);
+        StackAccumulator::new(2, -1.0, 4.0, &parameters)
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * a.abs().max(1.0)
+    }
+
+    #[test]
+    fn merged_chunks_match_single_pass() {
+        let samples = samples(100_000);
+        let mut single = accumulator();
+        for (stack, contributors) in &samples {
+            single.push(*stack, contributors);
+        }
+        let mut merged = accumulator();
+        for chunk in samples.chunks(30_000) {
+            let mut partial = accumulator();
+            for (stack, contributors) in chunk {
+                partial.push(*stack, contributors);
+            }
+            merged.merge(&partial);
+        }
+        let (a, b) = (single.moments(), merged.moments());
+        assert_eq!(a.count(), b.count());
+        assert!(close(a.mean(), b.mean()));
+        assert!(close(a.variance(), b.variance()));
+        assert!(close(a.skewness(), b.skewness()));
+        assert!(close(a.kurtosis(), b.kurtosis()));
+        for (a, b) in single.contributions().iter().zip(merged.contributions()) {
+            assert!(close(*a, b));
+        }
+        let (a, b) = (single.semi_deviations(), merged.semi_deviations());
+        assert!(close(a.0, b.0) && close(a.1, b.1));
+        for &p in &[0.00135, 0.5, 0.99865] {
+            assert!(close(single.percentile(p), merged.percentile(p)));
+        }
+        assert_eq!(single.fraction_out_of_spec(), merged.fraction_out_of_spec());
+        assert_eq!(single.histogram(100).counts, merged.histogram(100).counts);
+    }
+
+    #[test]
+    fn percentiles_and_semi_deviations_match_the_values() {
+        let samples = samples(100_000);
+        let mut accumulator = accumulator();
+        for (stack, contributors) in &samples {
+            accumulator.push(*stack, contributors);
+        }
+        let mut values: Vec<f64> = samples.iter().map(|(stack, _)| *stack).collect();
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let n = values.len() as f64;
+        // Percentiles are interpolated within a bin, so they are as accurate as the bin width.
+        let width = 5.0 / FINE_BINS as f64;
+        for &p in &[0.00135, 0.025, 0.5, 0.975, 0.99865] {
+            let exact = values[(p * n) as usize];
+            let estimate = accumulator.percentile(p);
+            assert!(
+                (estimate - exact).abs() < 2.0 * width,
+                "{} percentile {} is not {}",
+                p,
+                estimate,
+                exact
+            );
+        }
+        let mean = values.iter().sum::<f64>() / n;
+        let semi = |side: &[f64]| {
+            let sq: f64 = side.iter().map(|x| (x - mean).powi(2)).sum();
+            (sq / (side.len() as f64 - 1.0)).sqrt()
+        };
+        let split = values.iter().position(|&x| x >= mean).unwrap();
+        let (below, above) = accumulator.semi_deviations();
+        assert!((below / semi(&values[..split]) - 1.0).abs() < 1e-3);
+        assert!((above / semi(&values[split..]) - 1.0).abs() < 1e-3);
+    }
+}
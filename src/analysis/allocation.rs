@@ -0,0 +1,540 @@
+use super::correlation;
+use super::cost::{self, CostModel};
+use super::expression::StackEquation;
+use super::structures::*;
+use serde_derive::*;
+use statrs::distribution::{ContinuousCDF, Normal};
+
+/// How the tolerance available to the stack is shared among its contributors.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum AllocationMethod {
+    /// Scales every tolerance by the same factor, keeping their current proportions.
+    #[default]
+    Proportional,
+    /// Gives every dimension the same precision, so larger dimensions get larger tolerances, in
+    /// proportion to the ISO 286 standard tolerance factor of their size.
+    EqualPrecision,
+    /// Minimizes the total cost of the contributors, using the cost model of each. Contributors
+    /// without a cost model all cost the same to make, at a cost inversely proportional to their
+    /// tolerance.
+    MinimumCost,
+}
+impl AllocationMethod {
+    pub const ALL: [AllocationMethod; 3] = [
+        AllocationMethod::Proportional,
+        AllocationMethod::EqualPrecision,
+        AllocationMethod::MinimumCost,
+    ];
+}
+impl std::fmt::Display for AllocationMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                AllocationMethod::Proportional => "Proportional",
+                AllocationMethod::EqualPrecision => "Equal Precision",
+                AllocationMethod::MinimumCost => "Minimum Cost",
+            }
+        )
+    }
+}
+
+/// What the stack must achieve against its spec limits.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AllocationTarget {
+    /// A minimum Cpk
+    Capability(f64),
+    /// A minimum fraction of assemblies inside the spec limits, from 0 to 1
+    Yield(f64),
+}
+
+/// Error returned when the tolerances cannot be allocated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationError {
+    /// There are no spec limits to allocate the tolerances against.
+    NoSpecLimits,
+    /// None of the contributors can be adjusted. Only linear dimensions with a tolerance are.
+    NoAdjustableContributors,
+    /// The target cannot be met even if the adjustable contributors had no tolerance.
+    Infeasible,
+    /// The target is met no matter how loose the adjustable contributors are.
+    Unconstrained,
+    /// The stack is a vector loop, and only the X axis of its closing vector has spec limits, so
+    /// the Y axis would be left unconstrained.
+    VectorLoop,
+    /// An adjustable contributor has a cost model that does not cost more as it is tightened, so
+    /// there is no cheapest tolerance for it.
+    CostNotDecreasing,
+}
+impl std::fmt::Display for AllocationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                AllocationError::NoSpecLimits => "Enter a spec limit to allocate tolerances",
+                AllocationError::NoAdjustableContributors =>
+                    "There are no linear tolerances to allocate",
+                AllocationError::Infeasible =>
+                    "The target cannot be met by tightening the linear tolerances",
+                AllocationError::Unconstrained =>
+                    "The target is met no matter how loose the tolerances are",
+                AllocationError::VectorLoop =>
+                    "Tolerances cannot be allocated for a vector loop, which is only checked along X",
+                AllocationError::CostNotDecreasing =>
+                    "Every cost model must cost more as its tolerance is tightened",
+            }
+        )
+    }
+}
+impl std::error::Error for AllocationError {}
+
+/// The tolerances proposed for a stack
+#[derive(Debug, Clone, PartialEq)]
+pub struct Allocation {
+    pub method: AllocationMethod,
+    pub target: AllocationTarget,
+    /// The factor each tolerance is multiplied by, in tolerance loop order, or `None` if the
+    /// contributor is not adjusted
+    pub factors: Vec<Option<f64>>,
+    /// The mean of the stack with the proposed tolerances
+    pub mean: f64,
+    /// The standard deviation of the stack with the proposed tolerances
+    pub stddev: f64,
+    /// The total cost of the contributors with a cost model, with the proposed tolerances
+    pub cost: Option<f64>,
+}
+
+/// Finds the loosest tolerances that meet the target, by scaling the tolerances of the linear
+/// contributors in the proportions set by the `method`. The stack is assumed to be normally
+/// distributed, with the mean and standard deviation found by the classic RSS method, including
+/// any correlations. Each tolerance keeps its distribution and the center of its band.
+pub fn run(
+    state: &State,
+    method: AllocationMethod,
+    target: AllocationTarget,
+) -> Result<Allocation, AllocationError> {
+    let parameters = &state.parameters;
+    if !parameters.has_spec() {
+        return Err(AllocationError::NoSpecLimits);
+    }
+    if parameters.vector_loop {
+        return Err(AllocationError::VectorLoop);
+    }
+    // The half band of each adjustable contributor at a scale of one, or for the minimum cost,
+    //  the square of the number of standard deviations in its half band over the square of its
+    //  sensitivity. The sensitivity includes the gradient of any stack expression.
+    let weights: Vec<Option<f64>> = StackEquation::of(state, &state.tolerance_loop)
+        .linearized(&state.tolerance_loop)
+        .iter()
+        .map(|tol| weight(tol, method))
+        .collect();
+    if weights.iter().all(Option::is_none) {
+        return Err(AllocationError::NoAdjustableContributors);
+    }
+    let cost_not_decreasing = weights.iter().enumerate().any(|(index, weight)| {
+        weight.is_some() && matches!(state.cost(index), Some(model) if !model.is_decreasing())
+    });
+    if method == AllocationMethod::MinimumCost && cost_not_decreasing {
+        return Err(AllocationError::CostNotDecreasing);
+    }
+    let factors = |scale: f64| -> Vec<Option<f64>> {
+        weights
+            .iter()
+            .zip(&state.tolerance_loop)
+            .enumerate()
+            .map(|(index, (weight, tol))| {
+                weight.map(|weight| {
+                    let half_band_at_scale = match method {
+                        AllocationMethod::MinimumCost => {
+                            let model = state.cost(index).unwrap_or(&DEFAULT_COST);
+                            cheapest_half_band(model, weight, scale)
+                        }
+                        _ => scale * weight,
+                    };
+                    half_band_at_scale / half_band(tol)
+                })
+            })
+            .collect()
+    };
+    let spread = |scale: f64| stack_spread(state, &factors(scale));
+    let meets_target = |scale: f64| {
+        let (mean, stddev) = spread(scale);
+        meets(mean, stddev, target, parameters)
+    };
+
+    if !meets_target(0.0) {
+        return Err(AllocationError::Infeasible);
+    }
+    // Bracket the largest scale that meets the target, then close in on it by bisection.
+    let (mut lower, mut upper) = (0.0, 1.0);
+    while meets_target(upper) {
+        lower = upper;
+        upper *= 2.0;
+        if upper > 1e12 {
+            return Err(AllocationError::Unconstrained);
+        }
+    }
+    for _ in 0..100 {
+        let middle = (lower + upper) / 2.0;
+        if meets_target(middle) {
+            lower = middle;
+        } else {
+            upper = middle;
+        }
+    }
+    let (mean, stddev) = spread(lower);
+    let factors = factors(lower);
+    let cost = scaled_loop(state, &factors)
+        .iter()
+        .enumerate()
+        .filter_map(|(index, tol)| {
+            state
+                .cost(index)
+                .map(|model| model.cost(cost::tolerance_band(tol)))
+        })
+        .fold(None, |total, cost| Some(total.unwrap_or(0.0) + cost));
+    Ok(Allocation {
+        method,
+        target,
+        factors,
+        mean,
+        stddev,
+        cost,
+    })
+}
+
+/// The cost of contributors without a cost model when allocating for the minimum cost
+const DEFAULT_COST: CostModel = CostModel::Reciprocal {
+    fixed: 0.0,
+    coefficient: 1.0,
+};
+
+/// The half band of a contributor in the cheapest allocation at `scale`, where `weight` is the
+/// square of the number of standard deviations in its half band, `k`, over the square of its
+/// sensitivity, `c`. The contributor adds `c²·half_band²/k²` to the variance of the stack, so
+/// minimizing the total cost while holding that variance constant makes the marginal cost of each
+/// contributor proportional to its half band over `weight`. The half band is found where
+/// `scale * weight * marginal_cost` meets it.
+fn cheapest_half_band(model: &CostModel, weight: f64, scale: f64) -> f64 {
+    // The cost is of the full band, twice the half band.
+    let excess = |half_band: f64| scale * weight * model.marginal_cost(2.0 * half_band) - half_band;
+    let (mut lower, mut upper) = (0.0, 1.0);
+    while excess(upper) > 0.0 && upper < 1e12 {
+        lower = upper;
+        upper *= 2.0;
+    }
+    for _ in 0..100 {
+        let middle = (lower + upper) / 2.0;
+        if excess(middle) > 0.0 {
+            lower = middle;
+        } else {
+            upper = middle;
+        }
+    }
+    (lower + upper) / 2.0
+}
+
+fn half_band(tol: &Tolerance) -> f64 {
+    match tol {
+        Tolerance::Linear(linear) => (linear.distance.tol_pos + linear.distance.tol_neg) / 2.0,
+        Tolerance::Float(_) | Tolerance::Compound(_) | Tolerance::Angular(_) => 0.0,
+    }
+}
+
+/// The relative size of the tolerance given to a contributor of the linearized stack, or `None`
+/// if it is not adjusted. A contributor that does not move the stack is left alone.
+fn weight(tol: &Tolerance, method: AllocationMethod) -> Option<f64> {
+    let linear = match tol {
+        Tolerance::Linear(linear) => linear.distance,
+        Tolerance::Float(_) | Tolerance::Compound(_) | Tolerance::Angular(_) => return None,
+    };
+    let half_band = half_band(tol);
+    if half_band <= 0.0 || linear.stddev() <= 0.0 || tol.sensitivity() == 0.0 {
+        return None;
+    }
+    Some(match method {
+        AllocationMethod::Proportional => half_band,
+        AllocationMethod::EqualPrecision => {
+            // Sizes under 1 are treated as 1, as the factor of a size of zero is zero.
+            let size = linear.dim.abs().max(1.0);
+            0.45 * size.cbrt() + 0.001 * size
+        }
+        AllocationMethod::MinimumCost => {
+            (half_band / linear.stddev()).powi(2) / tol.sensitivity().powi(2)
+        }
+    })
+}
+
+/// The tolerance loop, after multiplying each tolerance by its factor.
+fn scaled_loop(state: &State, factors: &[Option<f64>]) -> Vec<Tolerance> {
+    state
+        .tolerance_loop
+        .iter()
+        .zip(factors)
+        .map(|(tol, factor)| match (tol, factor) {
+            (Tolerance::Linear(linear), Some(factor)) => Tolerance::Linear(LinearTL {
+                distance: linear.distance.scaled(*factor),
+                ..*linear
+            }),
+            _ => *tol,
+        })
+        .collect()
+}
+
+/// The mean and standard deviation of the stack, after multiplying each tolerance by its factor.
+fn stack_spread(state: &State, factors: &[Option<f64>]) -> (f64, f64) {
+    let tolerance_loop = scaled_loop(state, factors);
+    let equation = StackEquation::of(state, &tolerance_loop);
+    let tolerance_loop = equation.linearized(&tolerance_loop);
+    let mean: f64 = equation.constant + tolerance_loop.iter().map(|tol| tol.mean()).sum::<f64>();
+    let variances: Vec<f64> = tolerance_loop.iter().map(|tol| tol.variance()).collect();
+    let stddevs = correlation::signed_stddevs(&tolerance_loop, &variances);
+    let variance =
+        variances.iter().sum::<f64>() + correlation::covariance_of(&stddevs, &state.correlations);
+    (mean, variance.max(0.0).sqrt())
+}
+
+/// Whether a normally distributed stack meets the target.
+fn meets(mean: f64, stddev: f64, target: AllocationTarget, parameters: &Parameters) -> bool {
+    let (lower, upper) = (parameters.lower_spec, parameters.upper_spec);
+    let inside =
+        !matches!(lower, Some(lsl) if mean <= lsl) && !matches!(upper, Some(usl) if mean >= usl);
+    if stddev <= 0.0 {
+        return inside;
+    }
+    match target {
+        AllocationTarget::Capability(cpk) => {
+            let cpk_lower = lower.map_or(f64::INFINITY, |lsl| (mean - lsl) / (3.0 * stddev));
+            let cpk_upper = upper.map_or(f64::INFINITY, |usl| (usl - mean) / (3.0 * stddev));
+            cpk_lower.min(cpk_upper) >= cpk
+        }
+        AllocationTarget::Yield(fraction_in) => {
+            let normal = Normal::new(mean, stddev).unwrap();
+            let fraction_out = lower.map_or(0.0, |lsl| normal.cdf(lsl))
+                + upper.map_or(0.0, |usl| 1.0 - normal.cdf(usl));
+            fraction_out <= 1.0 - fraction_in
+        }
+    }
+}
+
+impl DimTol {
+    /// Multiplies the tolerance band by `factor`, about the center of the band, keeping the shape
+    /// of the distribution. The mean shift is scaled with the band, so the process keeps its Cpk.
+    pub fn scaled(mut self, factor: f64) -> Self {
+        let center = self.band_center();
+        self.dim += center * (1.0 - factor);
+        self.tol_pos *= factor;
+        self.tol_neg *= factor;
+        self.tol_multiplier *= factor;
+        self.mean_shift *= factor;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::{linear, normal};
+    use super::*;
+
+    fn stack(tolerances: &[(f64, f64)], lower_spec: f64, upper_spec: f64) -> State {
+        let mut state = State::default();
+        state.parameters.lower_spec = Some(lower_spec);
+        state.parameters.upper_spec = Some(upper_spec);
+        for &(dim, tol) in tolerances {
+            state.add(linear(dim, tol, tol));
+        }
+        state
+    }
+
+    #[test]
+    fn proportional_meets_capability() {
+        // 10 +/-0.3 and 5 +/-0.4 stack to 15 +/-0.5 at 3 sigma, so a Cpk of 1.33 against
+        //  15 +/-0.5 needs both tolerances scaled by 1/1.33.
+        let state = stack(&[(10.0, 0.3), (5.0, 0.4)], 14.5, 15.5);
+        let allocation = run(
+            &state,
+            AllocationMethod::Proportional,
+            AllocationTarget::Capability(1.33),
+        )
+        .unwrap();
+        for factor in &allocation.factors {
+            assert!((factor.unwrap() - 1.0 / 1.33).abs() < 1e-9);
+        }
+        assert!((allocation.mean - 15.0).abs() < 1e-12);
+        assert!((allocation.stddev - 0.5 / 3.0 / 1.33).abs() < 1e-9);
+    }
+
+    #[test]
+    fn yield_target_with_one_limit() {
+        // 84.13% of a normal stack is below one standard deviation over the mean.
+        let mut state = stack(&[(10.0, 0.3), (5.0, 0.4)], 14.5, 15.3);
+        state.parameters.lower_spec = None;
+        let allocation = run(
+            &state,
+            AllocationMethod::Proportional,
+            AllocationTarget::Yield(0.841_344_746),
+        )
+        .unwrap();
+        assert!((allocation.stddev - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn equal_precision_and_minimum_cost() {
+        let state = stack(&[(8.0, 0.3), (125.0, 0.4)], 132.5, 133.5);
+        let target = AllocationTarget::Capability(1.0);
+        let tolerances = |allocation: &Allocation| -> Vec<f64> {
+            allocation
+                .factors
+                .iter()
+                .zip(&state.tolerance_loop)
+                .map(|(factor, tol)| factor.unwrap() * half_band(tol))
+                .collect()
+        };
+        // The standard tolerance factors of 8 and 125 are 0.908 and 2.375.
+        let allocation = run(&state, AllocationMethod::EqualPrecision, target).unwrap();
+        let equal_precision = tolerances(&allocation);
+        let ratio = (0.45 * 125f64.cbrt() + 0.125) / (0.45 * 2.0 + 0.008);
+        assert!((equal_precision[1] / equal_precision[0] - ratio).abs() < 1e-9);
+        assert!((allocation.stddev - 0.5 / 3.0).abs() < 1e-9);
+        // Both dimensions are at 3 sigma, so they cost the least when their tolerances are equal.
+        let allocation = run(&state, AllocationMethod::MinimumCost, target).unwrap();
+        let minimum_cost = tolerances(&allocation);
+        assert!((minimum_cost[0] - minimum_cost[1]).abs() < 1e-9);
+        assert_eq!(allocation.cost, None);
+    }
+
+    #[test]
+    fn minimum_cost_with_cost_models() {
+        // With reciprocal costs, the cheapest tolerances are in proportion to the cube root of
+        //  their coefficients, so a contributor eight times as costly to tighten gets twice the
+        //  tolerance.
+        let mut state = stack(&[], 14.5, 15.5);
+        let model = |coefficient| CostModel::Reciprocal {
+            fixed: 1.0,
+            coefficient,
+        };
+        state.add_with_cost(linear(10.0, 0.3, 0.3), Some(model(1.0)));
+        state.add_with_cost(linear(5.0, 0.4, 0.4), Some(model(8.0)));
+        let allocation = run(
+            &state,
+            AllocationMethod::MinimumCost,
+            AllocationTarget::Capability(1.0),
+        )
+        .unwrap();
+        let first = allocation.factors[0].unwrap() * 0.3;
+        let second = allocation.factors[1].unwrap() * 0.4;
+        assert!((second / first - 2.0).abs() < 1e-9);
+        assert!((allocation.stddev - 0.5 / 3.0).abs() < 1e-9);
+        let expected = 2.0 + 1.0 / (2.0 * first) + 8.0 / (2.0 * second);
+        assert!((allocation.cost.unwrap() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn minimum_cost_needs_decreasing_cost() {
+        let mut state = stack(&[(10.0, 0.3)], 14.5, 15.5);
+        let flat = CostModel::Exponential {
+            fixed: 1.0,
+            coefficient: 20.0,
+            rate: 0.0,
+        };
+        state.add_with_cost(linear(5.0, 0.4, 0.4), Some(flat));
+        let target = AllocationTarget::Capability(1.0);
+        assert_eq!(
+            run(&state, AllocationMethod::MinimumCost, target),
+            Err(AllocationError::CostNotDecreasing)
+        );
+        // Cost models are not used by the other methods.
+        assert!(run(&state, AllocationMethod::Proportional, target).is_ok());
+    }
+
+    #[test]
+    fn scaling_keeps_the_mean_shift_in_proportion() {
+        let dim_tol = normal(10.0, 0.4, 0.2).with_mean_shift(0.1).scaled(0.5);
+        assert!((dim_tol.mean_shift - 0.05).abs() < 1e-12);
+        assert!((dim_tol.tol_pos - 0.15).abs() < 1e-12);
+        // The center of the band stays put.
+        assert!((dim_tol.dim - 10.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn minimum_cost_with_sensitivity() {
+        // The first dimension moves the stack twice as far, adding four times the variance for
+        //  its tolerance. With equal reciprocal costs, the cheapest tolerances are in proportion
+        //  to the cube root of one over the square of the sensitivity.
+        let mut state = stack(&[], 24.5, 25.5);
+        let model = CostModel::Reciprocal {
+            fixed: 0.0,
+            coefficient: 1.0,
+        };
+        state.add_with_cost(
+            linear(10.0, 0.3, 0.3).with_sensitivity(2.0),
+            Some(model.clone()),
+        );
+        state.add_with_cost(linear(5.0, 0.4, 0.4), Some(model));
+        let allocation = run(
+            &state,
+            AllocationMethod::MinimumCost,
+            AllocationTarget::Capability(1.0),
+        )
+        .unwrap();
+        let first = allocation.factors[0].unwrap() * 0.3;
+        let second = allocation.factors[1].unwrap() * 0.4;
+        assert!((first / second - 0.25f64.cbrt()).abs() < 1e-9);
+        assert!((allocation.stddev - 0.5 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn contributor_without_sensitivity_is_not_adjusted() {
+        let mut state = stack(&[(10.0, 0.3)], 9.0, 11.0);
+        state.add(linear(5.0, 0.4, 0.4).with_sensitivity(0.0));
+        for &method in AllocationMethod::ALL.iter() {
+            let allocation = run(&state, method, AllocationTarget::Capability(1.0)).unwrap();
+            assert!(allocation.factors[0].is_some());
+            assert!(allocation.factors[1].is_none());
+        }
+    }
+
+    #[test]
+    fn vector_loop_is_not_allocated() {
+        let mut state = stack(&[(10.0, 0.3), (5.0, 0.4)], 14.5, 15.5);
+        state.tolerance_loop[1] = state.tolerance_loop[1].with_direction(90.0);
+        state.parameters.vector_loop = true;
+        assert_eq!(
+            run(
+                &state,
+                AllocationMethod::Proportional,
+                AllocationTarget::Capability(1.0)
+            ),
+            Err(AllocationError::VectorLoop)
+        );
+    }
+
+    #[test]
+    fn float_is_not_adjusted() {
+        let mut state = stack(&[(10.0, 0.3)], 9.0, 11.0);
+        state.add(Tolerance::Float(FloatTL::new(
+            normal(3.4, 0.0, 0.0),
+            normal(3.0, 0.0, 0.0),
+            3.0,
+        )));
+        let allocation = run(
+            &state,
+            AllocationMethod::Proportional,
+            AllocationTarget::Capability(1.0),
+        )
+        .unwrap();
+        assert!(allocation.factors[1].is_none());
+        // The float of +/-0.2 alone has a Cpk of 1/(3 * 0.2/sqrt(3)) = 2.9, so the stack cannot
+        //  reach a Cpk of 3.
+        assert_eq!(
+            run(
+                &state,
+                AllocationMethod::Proportional,
+                AllocationTarget::Capability(3.0)
+            ),
+            Err(AllocationError::Infeasible)
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use super::correlation;
+use super::expression::StackEquation;
+use super::structures::*;
+
+/// Ranks each tolerance in the loop by how much of the stack variation it is responsible for. The
+/// RSS share is the contributor's covariance with the stack over the total variance, which is its
+/// own variance plus its covariance with any contributors it is correlated with. The Monte Carlo
+/// share is the covariance of the contributor with the simulated stack over the stack variance,
+/// which also accounts for non-linear contributors like floating pins. The RSS share of a stack
+/// expression is found from the expression linearized about the mean of each contributor.
+pub fn run(state: &State, mc_results: Option<&McResults>) -> Vec<Contribution> {
+    let tolerance_loop =
+        StackEquation::of(state, &state.tolerance_loop).linearized(&state.tolerance_loop);
+    let covariances = correlation::contributor_covariances(&tolerance_loop, &state.correlations);
+    let variances: Vec<f64> = tolerance_loop
+        .iter()
+        .zip(covariances)
+        .map(|(tol, covariance)| tol.variance() + covariance)
+        .collect();
+    let total_variance: f64 = variances.iter().sum();
+
+    let mut contributions: Vec<Contribution> = variances
+        .iter()
+        .enumerate()
+        .map(|(index, variance)| Contribution {
+            index,
+            rss_share: if total_variance > 0.0 {
+                variance / total_variance
+            } else {
+                0.0
+            },
+            mc_share: mc_results.and_then(|mc| mc.contributions.get(index).cloned()),
+        })
+        .collect();
+
+    // Sort from largest to smallest, preferring the simulated share when it is available.
+    contributions.sort_by(|a, b| {
+        let a_share = a.mc_share.unwrap_or(a.rss_share);
+        let b_share = b.mc_share.unwrap_or(b.rss_share);
+        b_share
+            .partial_cmp(&a_share)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    contributions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::linear;
+    use super::*;
+
+    fn stack() -> State {
+        let mut state = State::default();
+        for &tol in &[0.3, 0.4] {
+            state.add(linear(10.0, tol, tol));
+        }
+        state
+    }
+
+    #[test]
+    fn shares_of_independent_contributors() {
+        // Standard deviations of 0.1 and 0.4/3 add variances of 0.01 and 0.0178, 36% and 64%.
+        let contributions = run(&stack(), None);
+        assert_eq!(contributions[0].index, 1);
+        assert!((contributions[0].rss_share - 0.64).abs() < 1e-12);
+        assert_eq!(contributions[1].index, 0);
+        assert!((contributions[1].rss_share - 0.36).abs() < 1e-12);
+        assert!(contributions.iter().all(|c| c.mc_share.is_none()));
+    }
+
+    #[test]
+    fn shares_of_correlated_contributors() {
+        // The covariance of the pair is split between them.
+        let mut state = stack();
+        state.correlate(Correlation {
+            first: 0,
+            second: 1,
+            coefficient: 0.5,
+        });
+        let (first, second) = (0.1f64, 0.4 / 3.0);
+        let covariance = 0.5 * first * second;
+        let total = first.powi(2) + second.powi(2) + 2.0 * covariance;
+        let contributions = run(&state, None);
+        assert!((contributions[0].rss_share - (second.powi(2) + covariance) / total).abs() < 1e-12);
+        assert!((contributions[1].rss_share - (first.powi(2) + covariance) / total).abs() < 1e-12);
+        let sum: f64 = contributions.iter().map(|c| c.rss_share).sum();
+        assert!((sum - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn simulated_shares_set_the_ranking() {
+        let mc_results = McResults {
+            contributions: vec![0.7, 0.3],
+            ..McResults::default()
+        };
+        let contributions = run(&stack(), Some(&mc_results));
+        assert_eq!(contributions[0].index, 0);
+        assert_eq!(contributions[0].mc_share, Some(0.7));
+        assert_eq!(contributions[1].mc_share, Some(0.3));
+    }
+}
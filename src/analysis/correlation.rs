@@ -0,0 +1,318 @@
+use super::structures::*;
+use statrs::distribution::{ContinuousCDF, Normal};
+use std::error::Error;
+
+/// Error returned when the correlations between contributors cannot all hold at once, such as A
+/// and B being strongly correlated, B and C being strongly correlated, but A and C being opposed.
+#[derive(Debug, Clone, Copy)]
+pub struct InvalidCorrelation;
+impl std::fmt::Display for InvalidCorrelation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The correlations between contributors are not consistent"
+        )
+    }
+}
+impl Error for InvalidCorrelation {}
+
+/// Correlates the samples of contributors with a Gaussian copula. Each correlated contributor is
+/// driven by a standard normal variable, and these are mixed by the Cholesky factor of the
+/// correlation matrix before being mapped back to a probability. Contributors keep their own
+/// distributions, but are pushed toward the same end of their tolerance band.
+///
+/// For contributors that are not normally distributed, the coefficient sets the correlation of
+/// the underlying normal variables, so the correlation of the samples is slightly weaker.
+#[derive(Debug, Clone)]
+pub struct Copula {
+    /// The dimension of the sampled point that drives each correlated contributor
+    dimensions: Vec<usize>,
+    /// Lower triangular Cholesky factor of the correlation matrix
+    cholesky: Vec<Vec<f64>>,
+    /// Reused buffer of the normal variables of each contributor
+    normals: Vec<f64>,
+}
+impl Copula {
+    /// Returns `None` if no contributors are correlated, as they can be sampled independently.
+    pub fn new(
+        tolerance_loop: &[Tolerance],
+        correlations: &[Correlation],
+    ) -> Result<Option<Self>, InvalidCorrelation> {
+        // Only contributors that are part of a correlation need to be mixed.
+        let mut correlated: Vec<usize> = correlations
+            .iter()
+            .filter(|correlation| correlation.coefficient != 0.0)
+            .flat_map(|correlation| vec![correlation.first, correlation.second])
+            .collect();
+        correlated.sort_unstable();
+        correlated.dedup();
+        if correlated.is_empty() {
+            return Ok(None);
+        }
+        let position = |index: usize| correlated.iter().position(|&i| i == index);
+        let mut matrix = identity(correlated.len());
+        for correlation in correlations {
+            check(correlation, tolerance_loop.len())?;
+            if let (Some(i), Some(j)) = (position(correlation.first), position(correlation.second))
+            {
+                matrix[i][j] = correlation.coefficient;
+                matrix[j][i] = correlation.coefficient;
+            }
+        }
+        let cholesky = cholesky(&matrix).ok_or(InvalidCorrelation)?;
+
+        // A float is correlated through the position of the pin within the round hole, which is
+        //  the last dimension it samples.
+        let mut offsets = Vec::with_capacity(tolerance_loop.len());
+        let mut offset = 0;
+        for tol in tolerance_loop {
+            offsets.push(offset + tol.dimensions() - 1);
+            offset += tol.dimensions();
+        }
+        Ok(Some(Copula {
+            dimensions: correlated.iter().map(|&index| offsets[index]).collect(),
+            cholesky,
+            normals: vec![0.0; correlated.len()],
+        }))
+    }
+
+    /// Replaces the independent probabilities of the correlated contributors in `point` with
+    /// correlated ones.
+    pub fn correlate(&mut self, point: &mut [f64]) {
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        for (z, &dimension) in self.normals.iter_mut().zip(&self.dimensions) {
+            let u = point[dimension].clamp(f64::EPSILON, 1.0 - f64::EPSILON);
+            *z = normal.inverse_cdf(u);
+        }
+        for (row, &dimension) in self.cholesky.iter().zip(&self.dimensions) {
+            let mixed: f64 = row.iter().zip(&self.normals).map(|(l, z)| l * z).sum();
+            point[dimension] = normal.cdf(mixed);
+        }
+    }
+}
+
+/// The additional variance of a stack due to the correlations between its contributors, which is
+/// twice the sum of the covariance of each correlated pair.
+pub fn covariance(tolerance_loop: &[Tolerance], correlations: &[Correlation]) -> f64 {
+    let variances: Vec<f64> = tolerance_loop.iter().map(|tol| tol.variance()).collect();
+    covariance_of(&signed_stddevs(tolerance_loop, &variances), correlations)
+}
+
+/// The additional variance of a stack due to correlations, given the signed standard deviation of
+/// each contributor.
+pub fn covariance_of(stddevs: &[f64], correlations: &[Correlation]) -> f64 {
+    unique(correlations)
+        .iter()
+        .map(|correlation| 2.0 * pair_covariance(stddevs, correlation))
+        .sum()
+}
+
+/// The standard deviation of each contributor from its variance, negative where the contributor
+/// has a negative sensitivity. Correlations are between the dimensions themselves, so the
+/// contributions to the stack of a pair with sensitivities of opposite sign are anti-correlated.
+pub fn signed_stddevs(tolerance_loop: &[Tolerance], variances: &[f64]) -> Vec<f64> {
+    tolerance_loop
+        .iter()
+        .zip(variances)
+        .map(|(tol, variance)| variance.sqrt().copysign(tol.sensitivity()))
+        .collect()
+}
+
+/// The covariance of each contributor with the other contributors it is correlated with.
+pub fn contributor_covariances(
+    tolerance_loop: &[Tolerance],
+    correlations: &[Correlation],
+) -> Vec<f64> {
+    let variances: Vec<f64> = tolerance_loop.iter().map(|tol| tol.variance()).collect();
+    let stddevs = signed_stddevs(tolerance_loop, &variances);
+    let mut covariances = vec![0.0; tolerance_loop.len()];
+    for correlation in &unique(correlations) {
+        let covariance = pair_covariance(&stddevs, correlation);
+        if let Some(first) = covariances.get_mut(correlation.first) {
+            *first += covariance;
+        }
+        if let Some(second) = covariances.get_mut(correlation.second) {
+            *second += covariance;
+        }
+    }
+    covariances
+}
+
+/// If a pair of contributors is correlated more than once, only the last correlation is used.
+fn unique(correlations: &[Correlation]) -> Vec<Correlation> {
+    let same_pair = |a: &Correlation, b: &Correlation| {
+        (a.first, a.second) == (b.first, b.second) || (a.first, a.second) == (b.second, b.first)
+    };
+    correlations
+        .iter()
+        .enumerate()
+        .filter(|(i, a)| !correlations[i + 1..].iter().any(|b| same_pair(a, b)))
+        .map(|(_, correlation)| *correlation)
+        .collect()
+}
+
+fn pair_covariance(stddevs: &[f64], correlation: &Correlation) -> f64 {
+    match (
+        stddevs.get(correlation.first),
+        stddevs.get(correlation.second),
+    ) {
+        (Some(first), Some(second)) if correlation.first != correlation.second => {
+            correlation.coefficient * first * second
+        }
+        _ => 0.0,
+    }
+}
+
+/// Whether the correlations between `n_contributors` can all hold at once.
+pub fn is_consistent(n_contributors: usize, correlations: &[Correlation]) -> bool {
+    let mut matrix = identity(n_contributors);
+    for correlation in correlations {
+        if check(correlation, n_contributors).is_err() {
+            return false;
+        }
+        matrix[correlation.first][correlation.second] = correlation.coefficient;
+        matrix[correlation.second][correlation.first] = correlation.coefficient;
+    }
+    cholesky(&matrix).is_some()
+}
+
+/// A correlation must be between two different contributors in the stack, with a coefficient
+/// between -1 and 1.
+fn check(correlation: &Correlation, n_contributors: usize) -> Result<(), InvalidCorrelation> {
+    if correlation.first == correlation.second
+        || correlation.first >= n_contributors
+        || correlation.second >= n_contributors
+        || !(-1.0..=1.0).contains(&correlation.coefficient)
+    {
+        Err(InvalidCorrelation)
+    } else {
+        Ok(())
+    }
+}
+
+fn identity(n: usize) -> Vec<Vec<f64>> {
+    (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect()
+}
+
+/// Factors a symmetric matrix into `L * L^T`, returning the lower triangular `L`, or `None` if the
+/// matrix is not positive definite. Contributors that are perfectly correlated make the matrix
+/// only semi-definite, so a small tolerance is allowed on the diagonal.
+fn cholesky(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut lower = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| lower[i][k] * lower[j][k]).sum();
+            if i == j {
+                let diagonal = matrix[i][i] - sum;
+                if diagonal < -1e-9 {
+                    return None;
+                }
+                lower[i][j] = diagonal.max(0.0).sqrt();
+            } else if lower[j][j] > 1e-9 {
+                lower[i][j] = (matrix[i][j] - sum) / lower[j][j];
+            } else if (matrix[i][j] - sum).abs() > 1e-9 {
+                return None;
+            }
+        }
+    }
+    Some(lower)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::linear;
+    use super::*;
+    use rand::prelude::*;
+    use rand_chacha::ChaCha8Rng;
+
+    fn correlation(first: usize, second: usize, coefficient: f64) -> Correlation {
+        Correlation {
+            first,
+            second,
+            coefficient,
+        }
+    }
+
+    #[test]
+    fn cholesky_factors_the_matrix() {
+        let matrix = vec![
+            vec![1.0, 0.5, 0.2],
+            vec![0.5, 1.0, -0.3],
+            vec![0.2, -0.3, 1.0],
+        ];
+        let lower = cholesky(&matrix).unwrap();
+        for i in 0..3 {
+            for j in 0..3 {
+                let product: f64 = (0..3).map(|k| lower[i][k] * lower[j][k]).sum();
+                assert!((product - matrix[i][j]).abs() < 1e-12, "({}, {})", i, j);
+            }
+            assert!(lower[i][i + 1..].iter().all(|&l| l == 0.0));
+        }
+        // A pair correlated by r factors into 1, and r with the square root of 1 - r^2.
+        let lower = cholesky(&[vec![1.0, 0.6], vec![0.6, 1.0]]).unwrap();
+        assert!((lower[1][0] - 0.6).abs() < 1e-12);
+        assert!((lower[1][1] - 0.8).abs() < 1e-12);
+        // Perfect correlation is semi-definite, and allowed.
+        assert!(cholesky(&[vec![1.0, 1.0], vec![1.0, 1.0]]).is_some());
+        assert!(cholesky(&[vec![1.0, 1.5], vec![1.5, 1.0]]).is_none());
+    }
+
+    #[test]
+    fn consistent_correlations() {
+        assert!(is_consistent(
+            3,
+            &[correlation(0, 1, 0.5), correlation(1, 2, 0.5)]
+        ));
+        // Pairs that are not given are uncorrelated, which cannot hold with two strong links.
+        assert!(!is_consistent(
+            3,
+            &[correlation(0, 1, 0.9), correlation(1, 2, 0.9)]
+        ));
+        assert!(!is_consistent(
+            3,
+            &[
+                correlation(0, 1, 0.9),
+                correlation(1, 2, 0.9),
+                correlation(0, 2, -0.9)
+            ]
+        ));
+        assert!(!is_consistent(2, &[correlation(0, 0, 0.5)]));
+        assert!(!is_consistent(2, &[correlation(0, 2, 0.5)]));
+        assert!(!is_consistent(2, &[correlation(0, 1, 1.5)]));
+    }
+
+    #[test]
+    fn copula_correlates_normal_variables() {
+        let tolerance_loop = vec![linear(10.0, 0.3, 0.3); 3];
+        let mut copula = Copula::new(&tolerance_loop, &[correlation(0, 2, 0.8)])
+            .unwrap()
+            .unwrap();
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let n = 200_000;
+        let (mut sum_xy, mut sum_xx, mut sum_yy, mut sum_xz) = (0.0, 0.0, 0.0, 0.0);
+        for _ in 0..n {
+            let mut point: Vec<f64> = (0..3).map(|_| rng.gen::<f64>()).collect();
+            copula.correlate(&mut point);
+            let x = normal.inverse_cdf(point[0]);
+            let y = normal.inverse_cdf(point[2]);
+            let z = normal.inverse_cdf(point[1]);
+            sum_xy += x * y;
+            sum_xx += x * x;
+            sum_yy += y * y;
+            sum_xz += x * z;
+        }
+        let correlated = sum_xy / (sum_xx * sum_yy).sqrt();
+        assert!(
+            (correlated - 0.8).abs() < 0.01,
+            "correlation {}",
+            correlated
+        );
+        // The uncorrelated contributor is left alone.
+        let independent = sum_xz / n as f64;
+        assert!(independent.abs() < 0.01, "correlation {}", independent);
+    }
+}
@@ -0,0 +1,227 @@
+use super::structures::*;
+use serde_derive::*;
+
+/// The cost of making a contributor as a function of its tolerance, which is the full width of its
+/// tolerance band. Tighter tolerances cost more to make.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum CostModel {
+    /// `fixed + coefficient / tolerance`
+    Reciprocal { fixed: f64, coefficient: f64 },
+    /// `fixed + coefficient * exp(-rate * tolerance)`
+    Exponential {
+        fixed: f64,
+        coefficient: f64,
+        rate: f64,
+    },
+    /// Points of (tolerance, cost) in order of increasing tolerance, joined by straight lines. The
+    /// first line is extended to tighter tolerances, and the cost is flat past the last point.
+    Piecewise(Vec<(f64, f64)>),
+}
+impl CostModel {
+    /// A piecewise model through the points, in any order, if there is at least one point and no
+    /// two share a tolerance.
+    pub fn piecewise(mut points: Vec<(f64, f64)>) -> Option<Self> {
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        let model = CostModel::Piecewise(points);
+        if model.is_valid() {
+            Some(model)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the model has finite, non-negative parameters, and a piecewise model has points
+    /// in order of strictly increasing tolerance.
+    pub fn is_valid(&self) -> bool {
+        let valid = |value: &f64| value.is_finite() && *value >= 0.0;
+        match self {
+            CostModel::Reciprocal { fixed, coefficient } => valid(fixed) && valid(coefficient),
+            CostModel::Exponential {
+                fixed,
+                coefficient,
+                rate,
+            } => valid(fixed) && valid(coefficient) && valid(rate),
+            CostModel::Piecewise(points) => {
+                !points.is_empty()
+                    && points.iter().all(|(tol, cost)| valid(tol) && valid(cost))
+                    && points.windows(2).all(|pair| pair[0].0 < pair[1].0)
+            }
+        }
+    }
+
+    /// Whether tightening the tolerance never makes the contributor cheaper, and the tightest
+    /// tolerances cost more than looser ones. Only such a model has a cheapest tolerance to trade
+    /// against the others in the stack.
+    pub fn is_decreasing(&self) -> bool {
+        match self {
+            CostModel::Reciprocal { coefficient, .. } => *coefficient > 0.0,
+            CostModel::Exponential {
+                coefficient, rate, ..
+            } => *coefficient > 0.0 && *rate > 0.0,
+            CostModel::Piecewise(points) => {
+                points.len() > 1
+                    && points[0].1 > points[1].1
+                    && points.windows(2).all(|pair| pair[0].1 >= pair[1].1)
+            }
+        }
+    }
+
+    /// The cost of making the contributor to the tolerance
+    pub fn cost(&self, tolerance: f64) -> f64 {
+        match self {
+            CostModel::Reciprocal { fixed, coefficient } => fixed + coefficient / tolerance,
+            CostModel::Exponential {
+                fixed,
+                coefficient,
+                rate,
+            } => fixed + coefficient * (-rate * tolerance).exp(),
+            CostModel::Piecewise(points) => {
+                let (first_tol, first_cost) = points[0];
+                if tolerance <= first_tol {
+                    // A table that gets dearer with looser tolerances is not extended below zero.
+                    return (first_cost - self.slope(tolerance) * (first_tol - tolerance)).max(0.0);
+                }
+                match points.windows(2).find(|pair| tolerance <= pair[1].0) {
+                    Some(pair) => pair[0].1 + self.slope(tolerance) * (tolerance - pair[0].0),
+                    None => points[points.len() - 1].1,
+                }
+            }
+        }
+    }
+
+    /// How fast the cost rises as the tolerance is tightened, which is the negative of the slope
+    /// of the cost curve at the tolerance.
+    pub fn marginal_cost(&self, tolerance: f64) -> f64 {
+        match self {
+            CostModel::Reciprocal { coefficient, .. } => coefficient / tolerance.powi(2),
+            CostModel::Exponential {
+                coefficient, rate, ..
+            } => coefficient * rate * (-rate * tolerance).exp(),
+            CostModel::Piecewise(_) => -self.slope(tolerance),
+        }
+    }
+
+    /// The slope of the line of a piecewise model that the tolerance falls on
+    fn slope(&self, tolerance: f64) -> f64 {
+        let points = match self {
+            CostModel::Piecewise(points) => points,
+            _ => return 0.0,
+        };
+        // Tolerances tighter than the first point fall on the first line.
+        match points.windows(2).find(|pair| tolerance <= pair[1].0) {
+            Some(pair) => (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0),
+            None => 0.0,
+        }
+    }
+}
+
+/// The cost of a contributor at its current tolerance
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct ContributorCost {
+    pub cost: f64,
+    /// The rise in cost per unit the tolerance is tightened
+    pub marginal_cost: f64,
+    /// The tolerance the cost is found at, the full width of the tolerance band
+    pub tolerance: f64,
+}
+
+/// Finds the cost of each contributor with a cost model, in tolerance loop order.
+pub fn run(state: &State) -> Vec<Option<ContributorCost>> {
+    state
+        .tolerance_loop
+        .iter()
+        .enumerate()
+        .map(|(index, tol)| {
+            state.cost(index).map(|model| {
+                let tolerance = tolerance_band(tol);
+                ContributorCost {
+                    cost: model.cost(tolerance),
+                    marginal_cost: model.marginal_cost(tolerance),
+                    tolerance,
+                }
+            })
+        })
+        .collect()
+}
+
+/// The total width of the tolerance bands of every dimension in the contributor. A float is
+/// costed on the tolerances of its holes and pins, not on the clearance between them.
+pub fn tolerance_band(tol: &Tolerance) -> f64 {
+    let band = |dim: &DimTol| dim.tol_pos + dim.tol_neg;
+    match tol {
+        Tolerance::Linear(linear) => band(&linear.distance),
+        Tolerance::Float(float) => band(&float.hole) + band(&float.pin),
+        Tolerance::Compound(compound) => {
+            band(&compound.hole)
+                + band(&compound.hole_pin)
+                + band(&compound.slot)
+                + band(&compound.slot_pin)
+        }
+        Tolerance::Angular(angular) => band(&angular.angle),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marginal_cost_is_slope_of_cost() {
+        let models = [
+            CostModel::Reciprocal {
+                fixed: 2.0,
+                coefficient: 0.5,
+            },
+            CostModel::Exponential {
+                fixed: 1.0,
+                coefficient: 20.0,
+                rate: 8.0,
+            },
+            CostModel::piecewise(vec![(0.4, 1.0), (0.05, 12.0), (0.1, 5.0)]).unwrap(),
+        ];
+        for model in &models {
+            for &tolerance in &[0.02, 0.07, 0.2, 0.3] {
+                let step = 1e-6;
+                let slope =
+                    (model.cost(tolerance + step) - model.cost(tolerance - step)) / (2.0 * step);
+                assert!(
+                    (model.marginal_cost(tolerance) + slope).abs() < 1e-4,
+                    "{:?} at {}",
+                    model,
+                    tolerance
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn piecewise_interpolates_and_extends() {
+        let model = CostModel::piecewise(vec![(0.1, 5.0), (0.05, 12.0), (0.4, 1.0)]).unwrap();
+        assert!((model.cost(0.05) - 12.0).abs() < 1e-12);
+        assert!((model.cost(0.075) - 8.5).abs() < 1e-12);
+        assert!((model.cost(0.25) - 3.0).abs() < 1e-12);
+        // Flat past the loosest point, and extended along the first line below the tightest
+        assert!((model.cost(1.0) - 1.0).abs() < 1e-12);
+        assert_eq!(model.marginal_cost(1.0), 0.0);
+        assert!((model.cost(0.025) - 15.5).abs() < 1e-12);
+        assert!(CostModel::piecewise(vec![(0.1, 5.0), (0.1, 6.0)]).is_none());
+        assert!(CostModel::piecewise(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn decreasing_models() {
+        let exponential = |coefficient, rate| CostModel::Exponential {
+            fixed: 1.0,
+            coefficient,
+            rate,
+        };
+        assert!(exponential(20.0, 8.0).is_decreasing());
+        assert!(!exponential(20.0, 0.0).is_decreasing());
+        assert!(!exponential(0.0, 8.0).is_decreasing());
+        let piecewise = |points| CostModel::piecewise(points).unwrap();
+        assert!(piecewise(vec![(0.05, 12.0), (0.1, 5.0), (0.2, 5.0)]).is_decreasing());
+        assert!(!piecewise(vec![(0.05, 12.0), (0.1, 5.0), (0.2, 6.0)]).is_decreasing());
+        assert!(!piecewise(vec![(0.05, 5.0), (0.1, 12.0)]).is_decreasing());
+        assert!(!piecewise(vec![(0.05, 5.0)]).is_decreasing());
+    }
+}
@@ -0,0 +1,512 @@
+use super::structures::*;
+use serde_derive::*;
+
+/// The stack as a function of its contributors, such as `a + b*cos(theta) - c/2`. Contributors
+/// are named in the expression, and identified by their position in the tolerance loop once
+/// parsed. The value of a contributor includes its sensitivity.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Expression {
+    Constant(f64),
+    /// The value of the contributor at this position in the tolerance loop
+    Contributor(usize),
+    Negate(Box<Expression>),
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+    Multiply(Box<Expression>, Box<Expression>),
+    Divide(Box<Expression>, Box<Expression>),
+    Power(Box<Expression>, Box<Expression>),
+    Function(Function, Box<Expression>),
+}
+
+/// Functions that can be used in an expression. Angles are in degrees, like the slot angle of a
+/// compound float.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Sqrt,
+    Abs,
+    Exp,
+    Ln,
+}
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "asin" => Function::Asin,
+            "acos" => Function::Acos,
+            "atan" => Function::Atan,
+            "sqrt" => Function::Sqrt,
+            "abs" => Function::Abs,
+            "exp" => Function::Exp,
+            "ln" => Function::Ln,
+            _ => return None,
+        })
+    }
+    fn apply(self, x: f64) -> f64 {
+        match self {
+            Function::Sin => x.to_radians().sin(),
+            Function::Cos => x.to_radians().cos(),
+            Function::Tan => x.to_radians().tan(),
+            Function::Asin => x.asin().to_degrees(),
+            Function::Acos => x.acos().to_degrees(),
+            Function::Atan => x.atan().to_degrees(),
+            Function::Sqrt => x.sqrt(),
+            Function::Abs => x.abs(),
+            Function::Exp => x.exp(),
+            Function::Ln => x.ln(),
+        }
+    }
+}
+
+/// Error returned when an expression cannot be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// A character that is not part of a number, name or operator
+    UnexpectedCharacter(char),
+    /// The expression ends where a value or closing bracket was expected.
+    UnexpectedEnd,
+    /// A token out of place, such as two operators in a row
+    UnexpectedToken(String),
+    /// A name that is not given to any active contributor
+    UnknownName(String),
+    /// A name that is given to more than one active contributor
+    AmbiguousName(String),
+    UnknownFunction(String),
+}
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnexpectedCharacter(c) => write!(f, "Unexpected character '{}'", c),
+            ParseError::UnexpectedEnd => write!(f, "The expression is incomplete"),
+            ParseError::UnexpectedToken(token) => write!(f, "Unexpected '{}'", token),
+            ParseError::UnknownName(name) => {
+                write!(f, "No active tolerance is named '{}'", name)
+            }
+            ParseError::AmbiguousName(name) => {
+                write!(f, "More than one active tolerance is named '{}'", name)
+            }
+            ParseError::UnknownFunction(name) => write!(f, "Unknown function '{}'", name),
+        }
+    }
+}
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                // Exponents, like 1e-3, carry their sign.
+                let exponent_sign = (c == '-' || c == '+') && input[start..i].ends_with(['e', 'E']);
+                if c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let number = &input[start..end];
+            tokens.push(Token::Number(
+                number
+                    .parse()
+                    .map_err(|_| ParseError::UnexpectedToken(number.to_string()))?,
+            ));
+        } else if is_name_start(c) {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if is_name_char(c) {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Name(input[start..end].to_string()));
+        } else if "+-*/^()".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(ParseError::UnexpectedCharacter(c));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Whether a name can start with the character. Names are letters, digits and underscores, and
+/// cannot start with a digit.
+pub fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+/// Whether a name can contain the character
+pub fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// A recursive descent parser, with the usual precedence of operators. Powers bind tighter than
+/// negation, so `-a^2` is `-(a^2)`.
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    names: &'a [&'a str],
+}
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Takes the next token if it is one of the symbols.
+    fn next_symbol(&mut self, symbols: &str) -> Option<char> {
+        match self.peek() {
+            Some(Token::Symbol(symbol)) if symbols.contains(*symbol) => {
+                let symbol = *symbol;
+                self.position += 1;
+                Some(symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<(), ParseError> {
+        match self.next() {
+            Some(Token::Symbol(c)) if c == symbol => Ok(()),
+            Some(token) => Err(ParseError::UnexpectedToken(token.to_string())),
+            None => Err(ParseError::UnexpectedEnd),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expression, ParseError> {
+        let mut left = self.product()?;
+        while let Some(symbol) = self.next_symbol("+-") {
+            let right = Box::new(self.product()?);
+            left = match symbol {
+                '+' => Expression::Add(Box::new(left), right),
+                _ => Expression::Subtract(Box::new(left), right),
+            };
+        }
+        Ok(left)
+    }
+
+    fn product(&mut self) -> Result<Expression, ParseError> {
+        let mut left = self.unary()?;
+        while let Some(symbol) = self.next_symbol("*/") {
+            let right = Box::new(self.unary()?);
+            left = match symbol {
+                '*' => Expression::Multiply(Box::new(left), right),
+                _ => Expression::Divide(Box::new(left), right),
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, ParseError> {
+        match self.next_symbol("+-") {
+            Some('-') => Ok(Expression::Negate(Box::new(self.unary()?))),
+            Some(_) => self.unary(),
+            None => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<Expression, ParseError> {
+        let base = self.primary()?;
+        match self.next_symbol("^") {
+            // Powers are right associative, so a^b^c is a^(b^c).
+            Some(_) => Ok(Expression::Power(Box::new(base), Box::new(self.unary()?))),
+            None => Ok(base),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression, ParseError> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Expression::Constant(number)),
+            Some(Token::Symbol('(')) => {
+                let inner = self.sum()?;
+                self.expect_symbol(')')?;
+                Ok(inner)
+            }
+            Some(Token::Name(name)) => {
+                if self.next_symbol("(").is_some() {
+                    let function = Function::from_name(&name)
+                        .ok_or_else(|| ParseError::UnknownFunction(name.clone()))?;
+                    let argument = self.sum()?;
+                    self.expect_symbol(')')?;
+                    Ok(Expression::Function(function, Box::new(argument)))
+                } else if let Some(index) = self.names.iter().position(|&other| other == name) {
+                    if self.names[index + 1..].contains(&name.as_str()) {
+                        Err(ParseError::AmbiguousName(name))
+                    } else {
+                        Ok(Expression::Contributor(index))
+                    }
+                } else if name == "pi" {
+                    Ok(Expression::Constant(std::f64::consts::PI))
+                } else {
+                    Err(ParseError::UnknownName(name))
+                }
+            }
+            Some(token) => Err(ParseError::UnexpectedToken(token.to_string())),
+            None => Err(ParseError::UnexpectedEnd),
+        }
+    }
+}
+
+impl Expression {
+    /// Parses an expression over the contributors named in `names`, in tolerance loop order. A
+    /// contributor with an empty name cannot be used in the expression.
+    pub fn parse(input: &str, names: &[&str]) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            position: 0,
+            names,
+        };
+        let expression = parser.sum()?;
+        match parser.next() {
+            Some(token) => Err(ParseError::UnexpectedToken(token.to_string())),
+            None => Ok(expression),
+        }
+    }
+
+    /// The value of the expression, given the value of each contributor in tolerance loop order.
+    pub fn evaluate(&self, values: &[f64]) -> f64 {
+        let evaluate = |expression: &Expression| expression.evaluate(values);
+        match self {
+            Expression::Constant(value) => *value,
+            Expression::Contributor(index) => values.get(*index).copied().unwrap_or(f64::NAN),
+            Expression::Negate(a) => -evaluate(a),
+            Expression::Add(a, b) => evaluate(a) + evaluate(b),
+            Expression::Subtract(a, b) => evaluate(a) - evaluate(b),
+            Expression::Multiply(a, b) => evaluate(a) * evaluate(b),
+            Expression::Divide(a, b) => evaluate(a) / evaluate(b),
+            Expression::Power(a, b) => evaluate(a).powf(evaluate(b)),
+            Expression::Function(function, a) => function.apply(evaluate(a)),
+        }
+    }
+
+    /// The partial derivative of the expression with respect to each contributor, at `values`,
+    /// by central differences.
+    pub fn gradient(&self, values: &[f64]) -> Vec<f64> {
+        let mut point = values.to_vec();
+        (0..values.len())
+            .map(|i| {
+                let step = 1e-6 * values[i].abs().max(1.0);
+                point[i] = values[i] + step;
+                let up = self.evaluate(&point);
+                point[i] = values[i] - step;
+                let down = self.evaluate(&point);
+                point[i] = values[i];
+                (up - down) / (2.0 * step)
+            })
+            .collect()
+    }
+}
+
+/// How the contributors of a tolerance loop combine into the stack, and the stack linearized about
+/// the mean of every contributor, as a constant plus each contributor times a coefficient. Without
+/// an expression the stack is already linear.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackEquation {
+    expression: Option<Expression>,
+    pub constant: f64,
+    pub coefficients: Vec<f64>,
+    /// The coefficients of the Y component of the vector that closes a vector loop, whose X
+    /// component is the stack
+    across: Option<Vec<f64>>,
+}
+impl StackEquation {
+    /// The equation of the stack of a state, for its tolerance loop or a variant of it such as
+    /// the centered loop.
+    pub fn of(state: &State, tolerance_loop: &[Tolerance]) -> Self {
+        if state.parameters.vector_loop {
+            StackEquation {
+                across: Some(Self::projection(tolerance_loop, Axis::Y).coefficients),
+                ..Self::projection(tolerance_loop, Axis::X)
+            }
+        } else {
+            Self::new(state.expression.as_ref(), tolerance_loop)
+        }
+    }
+
+    /// The component along an axis of the vector that closes a vector loop
+    pub fn projection(tolerance_loop: &[Tolerance], axis: Axis) -> Self {
+        StackEquation {
+            expression: None,
+            constant: 0.0,
+            coefficients: tolerance_loop
+                .iter()
+                .map(|tol| axis.component(tol.direction()))
+                .collect(),
+            across: None,
+        }
+    }
+
+    pub fn new(expression: Option<&Expression>, tolerance_loop: &[Tolerance]) -> Self {
+        match expression {
+            Some(expression) => {
+                let means: Vec<f64> = tolerance_loop.iter().map(|tol| tol.mean()).collect();
+                let coefficients = expression.gradient(&means);
+                let linear_mean: f64 = coefficients.iter().zip(&means).map(|(g, m)| g * m).sum();
+                StackEquation {
+                    expression: Some(expression.clone()),
+                    constant: expression.evaluate(&means) - linear_mean,
+                    coefficients,
+                    across: None,
+                }
+            }
+            None => StackEquation {
+                expression: None,
+                constant: 0.0,
+                coefficients: vec![1.0; tolerance_loop.len()],
+                across: None,
+            },
+        }
+    }
+
+    /// The stack, given the value of each contributor. The values are then replaced by their
+    /// linearized contribution to the stack, which is how much of the stack variation each one is
+    /// responsible for.
+    pub fn evaluate(&self, values: &mut [f64]) -> f64 {
+        let stack = self
+            .expression
+            .as_ref()
+            .map(|expression| expression.evaluate(values));
+        for (value, coefficient) in values.iter_mut().zip(&self.coefficients) {
+            *value *= coefficient;
+        }
+        stack.unwrap_or_else(|| values.iter().sum())
+    }
+
+    /// The Y component of the vector that closes a vector loop, given the value of each
+    /// contributor, or `None` if the loop is not a vector loop.
+    pub fn across(&self, values: &[f64]) -> Option<f64> {
+        self.across.as_ref().map(|coefficients| {
+            values
+                .iter()
+                .zip(coefficients)
+                .map(|(value, coefficient)| value * coefficient)
+                .sum()
+        })
+    }
+
+    /// The tolerance loop of the linearized stack, less the constant. The sensitivity of each
+    /// contributor is multiplied by its coefficient.
+    pub fn linearized(&self, tolerance_loop: &[Tolerance]) -> Vec<Tolerance> {
+        tolerance_loop
+            .iter()
+            .zip(&self.coefficients)
+            .map(|(tol, coefficient)| tol.with_sensitivity(tol.sensitivity() * coefficient))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::linear;
+    use super::*;
+
+    fn parse(input: &str) -> Result<Expression, ParseError> {
+        Expression::parse(input, &["a", "b", "theta", "c"])
+    }
+
+    #[test]
+    fn precedence() {
+        let values = [3.0, 2.0, 60.0, 4.0];
+        let cases = [
+            ("a + b*cos(theta) - c/2", 3.0 + 2.0 * 0.5 - 2.0),
+            ("-a^2", -9.0),
+            ("2^3^2", 512.0),
+            ("(a + b) * c", 20.0),
+            ("a - b - c", -3.0),
+            ("c / b / 2", 1.0),
+            ("1.5e1 + -a", 12.0),
+            ("sqrt(c) * sin(theta)", 3f64.sqrt()),
+            ("asin(0.5)", 30.0),
+            ("2 * pi", 2.0 * std::f64::consts::PI),
+        ];
+        for (input, expected) in &cases {
+            let value = parse(input).unwrap().evaluate(&values);
+            assert!((value - expected).abs() < 1e-12, "{} = {}", input, value);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("a + d"), Err(ParseError::UnknownName("d".into())));
+        assert_eq!(
+            parse("foo(a)"),
+            Err(ParseError::UnknownFunction("foo".into()))
+        );
+        assert_eq!(parse("a +"), Err(ParseError::UnexpectedEnd));
+        assert_eq!(parse("(a + b"), Err(ParseError::UnexpectedEnd));
+        assert_eq!(parse("a b"), Err(ParseError::UnexpectedToken("b".into())));
+        assert_eq!(
+            parse("a * / b"),
+            Err(ParseError::UnexpectedToken("/".into()))
+        );
+        assert_eq!(parse("a % b"), Err(ParseError::UnexpectedCharacter('%')));
+        assert_eq!(parse(""), Err(ParseError::UnexpectedEnd));
+        assert_eq!(
+            Expression::parse("a + b", &["a", "b", "a"]),
+            Err(ParseError::AmbiguousName("a".into()))
+        );
+    }
+
+    #[test]
+    fn gradient_and_linearization() {
+        let expression = parse("a + b*cos(theta) - c/2").unwrap();
+        let gradient = expression.gradient(&[3.0, 2.0, 60.0, 4.0]);
+        let expected = [
+            1.0,
+            0.5,
+            -2.0 * 60f64.to_radians().sin() * std::f64::consts::PI / 180.0,
+            -0.5,
+        ];
+        for (g, e) in gradient.iter().zip(&expected) {
+            assert!((g - e).abs() < 1e-8, "{:?}", gradient);
+        }
+
+        // The linearized stack has the same value as the expression at the means.
+        let tolerance_loop: Vec<Tolerance> = [3.0, 2.0, 60.0, 4.0]
+            .iter()
+            .map(|&dim| linear(dim, 0.1, 0.1))
+            .collect();
+        let equation = StackEquation::new(Some(&expression), &tolerance_loop);
+        let linear_mean: f64 = equation
+            .linearized(&tolerance_loop)
+            .iter()
+            .map(|tol| tol.mean())
+            .sum();
+        assert!((equation.constant + linear_mean - 2.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,22 @@
+//! Tolerances shared by the tests of the analyses. Every dimension is normally distributed, with
+//! its tolerance band at three standard deviations.
+use super::structures::*;
+
+/// A dimension of `dim` +`tol_pos`/-`tol_neg`
+pub fn normal(dim: f64, tol_pos: f64, tol_neg: f64) -> DimTol {
+    DimTol::new_normal(dim, tol_pos, tol_neg, 3.0).unwrap()
+}
+
+/// A linear contributor of `dim` +`tol_pos`/-`tol_neg`
+pub fn linear(dim: f64, tol_pos: f64, tol_neg: f64) -> Tolerance {
+    Tolerance::Linear(LinearTL::new(normal(dim, tol_pos, tol_neg)))
+}
+
+/// A pin floating in a hole, each with a symmetric tolerance
+pub fn float(hole: f64, hole_tol: f64, pin: f64, pin_tol: f64) -> Tolerance {
+    Tolerance::Float(FloatTL::new(
+        normal(hole, hole_tol, hole_tol),
+        normal(pin, pin_tol, pin_tol),
+        3.0,
+    ))
+}
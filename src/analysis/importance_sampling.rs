@@ -0,0 +1,211 @@
+use super::correlation::Copula;
+use super::expression::StackEquation;
+use super::monte_carlo::Cancelled;
+use super::structures::*;
+
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::StandardNormal;
+use statrs::distribution::{ContinuousCDF, Normal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Number of iterations used to estimate the probability beyond each spec limit. The sampling is
+/// concentrated where the stack fails, so far fewer iterations are needed than for plain Monte
+/// Carlo, no matter how rare the failures are.
+const ITERATIONS: usize = 200_000;
+
+/// Number of steps taken towards the most probable point of failure
+const DESIGN_POINT_STEPS: usize = 10;
+
+/// Number of iterations run between checks of the cancel flag
+const CANCEL_CHECK_INTERVAL: usize = 10_000;
+
+/// Estimates the fraction of assemblies outside of the spec limits, and its 95% confidence
+/// interval, by importance sampling.
+///
+/// Every tolerance is sampled through a point in the unit hypercube, which is mapped to
+/// independent standard normal variables. The normal variables are sampled about the most probable
+/// point beyond each spec limit instead of about the origin, so most samples fall near the limit,
+/// and each failure is weighted by the ratio of its probability under the true and shifted
+/// distributions. The estimate is unbiased for any stack, and is most efficient when the stack is
+/// close to linear in the normal variables, as it is for normally distributed dimensions.
+///
+/// Returns `Cancelled` once the `cancel` flag of the simulation is set.
+pub fn fraction_out_of_spec(
+    tolerance_loop: &[Tolerance],
+    equation: &StackEquation,
+    copula: Option<Copula>,
+    parameters: &Parameters,
+    cancel: &AtomicBool,
+) -> Result<(f64, Interval), Cancelled> {
+    let mut stack = Stack::new(tolerance_loop, equation, copula);
+    let limits = [
+        parameters.lower_spec.map(Limit::Lower),
+        parameters.upper_spec.map(Limit::Upper),
+    ];
+    let (mut fraction, mut variance) = (0.0, 0.0);
+    for (stream, limit) in limits.iter().enumerate() {
+        if let Some(limit) = limit {
+            let (tail_fraction, tail_variance) =
+                stack.tail_fraction(*limit, ITERATIONS, parameters.seed, stream as u64, cancel)?;
+            fraction += tail_fraction;
+            variance += tail_variance;
+        }
+    }
+    let interval = Interval::from_standard_error(fraction, variance.sqrt());
+    Ok((
+        fraction.min(1.0),
+        Interval {
+            lower: interval.lower.max(0.0),
+            upper: interval.upper.min(1.0),
+        },
+    ))
+}
+
+/// A spec limit, and the side of it where the stack fails
+#[derive(Debug, Clone, Copy)]
+enum Limit {
+    Lower(f64),
+    Upper(f64),
+}
+impl Limit {
+    fn value(&self) -> f64 {
+        match self {
+            Limit::Lower(value) | Limit::Upper(value) => *value,
+        }
+    }
+    fn is_exceeded_by(&self, stack: f64) -> bool {
+        match self {
+            Limit::Lower(lsl) => stack < *lsl,
+            Limit::Upper(usl) => stack > *usl,
+        }
+    }
+}
+
+/// Evaluates the stack as a function of independent standard normal variables, one for every
+/// independent variable sampled by the tolerances.
+struct Stack<'a> {
+    tolerance_loop: &'a [Tolerance],
+    equation: &'a StackEquation,
+    copula: Option<Copula>,
+    point: Vec<f64>,
+    samples: Vec<f64>,
+    normal: Normal,
+}
+impl<'a> Stack<'a> {
+    fn new(
+        tolerance_loop: &'a [Tolerance],
+        equation: &'a StackEquation,
+        copula: Option<Copula>,
+    ) -> Self {
+        let n_dims = tolerance_loop.iter().map(|tol| tol.dimensions()).sum();
+        Stack {
+            tolerance_loop,
+            equation,
+            copula,
+            point: vec![0.0; n_dims],
+            samples: vec![0.0; tolerance_loop.len()],
+            normal: Normal::new(0.0, 1.0).unwrap(),
+        }
+    }
+
+    fn n_dims(&self) -> usize {
+        self.point.len()
+    }
+
+    fn evaluate(&mut self, normals: &[f64]) -> f64 {
+        for (u, &z) in self.point.iter_mut().zip(normals) {
+            *u = self.normal.cdf(z).clamp(f64::EPSILON, 1.0 - f64::EPSILON);
+        }
+        if let Some(copula) = self.copula.as_mut() {
+            copula.correlate(&mut self.point);
+        }
+        let mut dims = self.point.as_slice();
+        for (sample, tol) in self.samples.iter_mut().zip(self.tolerance_loop) {
+            let (tol_point, rest) = dims.split_at(tol.dimensions());
+            *sample = tol.mc_tolerance_at(tol_point);
+            dims = rest;
+        }
+        self.equation.evaluate(&mut self.samples)
+    }
+
+    /// The gradient of the stack at `normals`, by central differences. The step is large, so the
+    ///  gradient is not thrown off by small kinks, like a pin touching the wall of its hole.
+    fn gradient(&mut self, normals: &[f64]) -> Vec<f64> {
+        let mut z = normals.to_vec();
+        (0..self.n_dims())
+            .map(|i| {
+                z[i] = normals[i] + 0.5;
+                let up = self.evaluate(&z);
+                z[i] = normals[i] - 0.5;
+                let down = self.evaluate(&z);
+                z[i] = normals[i];
+                up - down
+            })
+            .collect()
+    }
+
+    /// Finds the most probable point where the stack reaches the limit, which is the point on the
+    /// limit closest to the origin, with the Hasofer-Lind-Rackwitz-Fiessler iteration. A linear
+    /// stack is solved in a single step. Returns the origin if no such point is found, in which
+    /// case the sampling is not shifted.
+    fn design_point(&mut self, limit: f64) -> Vec<f64> {
+        let mut z = vec![0.0; self.n_dims()];
+        for _ in 0..DESIGN_POINT_STEPS {
+            let margin = self.evaluate(&z) - limit;
+            let gradient = self.gradient(&z);
+            let norm2: f64 = gradient.iter().map(|g| g * g).sum();
+            if norm2 <= 0.0 {
+                break;
+            }
+            // Step to where the linearized stack reaches the limit, along the gradient.
+            let projection: f64 = gradient.iter().zip(&z).map(|(g, z)| g * z).sum();
+            let scale = (projection - margin) / norm2;
+            z = gradient.iter().map(|g| g * scale).collect();
+        }
+        if z.iter().all(|z| z.is_finite()) {
+            z
+        } else {
+            vec![0.0; self.n_dims()]
+        }
+    }
+
+    /// The probability of the stack exceeding the limit, and the variance of the estimate.
+    fn tail_fraction(
+        &mut self,
+        limit: Limit,
+        n: usize,
+        seed: u64,
+        stream: u64,
+        cancel: &AtomicBool,
+    ) -> Result<(f64, f64), Cancelled> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        // Halton sampling uses the last stream, so keep clear of it
+        rng.set_stream(u64::MAX - 1 - stream);
+        let shift = self.design_point(limit.value());
+        let shift2: f64 = shift.iter().map(|s| s * s).sum();
+        let mut z = vec![0.0; self.n_dims()];
+        let (mut sum, mut sum2) = (0.0, 0.0);
+        for i in 0..n {
+            if i % CANCEL_CHECK_INTERVAL == 0 && cancel.load(Ordering::Relaxed) {
+                return Err(Cancelled);
+            }
+            let mut dot = 0.0;
+            for (z, &s) in z.iter_mut().zip(&shift) {
+                let e: f64 = rng.sample(StandardNormal);
+                *z = s + e;
+                dot += s * e;
+            }
+            if limit.is_exceeded_by(self.evaluate(&z)) {
+                // The ratio of the standard normal density to the shifted one at z
+                let weight = (-dot - shift2 / 2.0).exp();
+                sum += weight;
+                sum2 += weight * weight;
+            }
+        }
+        let n = n as f64;
+        let fraction = sum / n;
+        let variance = (sum2 / n - fraction * fraction).max(0.0) / n;
+        Ok((fraction, variance))
+    }
+}
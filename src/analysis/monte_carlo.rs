@@ -1,100 +1,208 @@
+use super::accumulator::StackAccumulator;
+use super::correlation::{self, Copula};
+use super::expression::StackEquation;
+use super::importance_sampling;
 use super::structures::*;
+use super::worker_pool::WorkerPool;
 
+use std::collections::BTreeMap;
 use std::error::Error;
-use std::sync::mpsc;
-use std::thread;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
 
 use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
 use rand_distr::{StandardNormal, Uniform};
-use statistical::*;
+use statrs::distribution::{ContinuousCDF, Normal};
 
+/// A snapshot of a Monte Carlo simulation that is still running
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    pub completed: usize,
+    pub total: usize,
+    pub mean: f64,
+    pub stddev: f64,
+}
+impl Progress {
+    /// Fraction of the iterations that are complete, from 0 to 1
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.completed as f32 / self.total as f32
+        }
+    }
+}
+
+/// Error returned when a simulation is stopped before it finishes
+#[derive(Debug, Clone, Copy)]
+pub struct Cancelled;
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Monte Carlo simulation was cancelled")
+    }
+}
+impl Error for Cancelled {}
+
+/// Error returned when a simulation is asked to run no iterations
+#[derive(Debug, Clone, Copy)]
+pub struct NoIterations;
+impl std::fmt::Display for NoIterations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Monte Carlo simulation has no iterations to run")
+    }
+}
+impl Error for NoIterations {}
+
+/// Runs the simulation to completion, without reporting progress.
+#[allow(dead_code)]
 pub async fn run(state: &State) -> Result<McResults, Box<dyn Error>> {
-    // Divide the desired number of iterations into chunks. This is done [1] to avoid floating point
-    //  errors (as the divisor gets large when averaging you lose precision) and [2] to prevent huge
-    //  memory use for large numbers of iterations. This can also be used to tune performance.
-    let chunk_size = 100000;
-    let chunks = state.parameters.n_iterations / chunk_size;
-    let real_iters = chunks * chunk_size;
-    let mut result = Vec::new();
-    let mut result_mean = 0f64;
-    let mut result_stddev_pos = 0f64;
-    let mut result_stddev_neg = 0f64;
-
-    for _n in 0..chunks {
-        // TODO: validate n_iterations is nicely divisible by chunk_size and n_threads.
-        // Gather samples into a stack that is `chunk_size` long for each Tolerance
-        let mut stack = compute_stackup(state.tolerance_loop.clone(), chunk_size);
-        // Sum each
-        let stack_mean: f64 = mean(&stack);
-        let stack_stddev_pos = standard_deviation(
-            &stack
-                .iter()
-                .cloned()
-                .filter(|x| x > &stack_mean)
-                .collect::<Vec<f64>>(),
-            Some(stack_mean),
-        );
-        let stack_stddev_neg = standard_deviation(
-            &stack
-                .iter()
-                .cloned()
-                .filter(|x| x < &stack_mean)
-                .collect::<Vec<f64>>(),
-            Some(stack_mean),
-        );
+    run_with_progress(state, Arc::new(AtomicBool::new(false)), |_| {}).await
+}
 
-        result.append(&mut stack);
-        result_mean += stack_mean;
-        result_stddev_neg += stack_stddev_neg;
-        result_stddev_pos += stack_stddev_pos;
+/// Runs the simulation, calling `progress` each time another chunk of iterations is complete. The
+/// simulation stops early and returns `Cancelled` once the `cancel` flag is set, and returns
+/// `NoIterations` without running if there is nothing to simulate.
+pub async fn run_with_progress<F: FnMut(Progress)>(
+    state: &State,
+    cancel: Arc<AtomicBool>,
+    mut progress: F,
+) -> Result<McResults, Box<dyn Error>> {
+    // Divide the desired number of iterations into chunks. Each chunk is simulated by a single
+    //  worker into its own accumulator, so memory use does not grow with the number of iterations.
+    //  The last chunk holds whatever is left over, so exactly `n_iterations` are run.
+    let chunk_size = CHUNK_SIZE;
+    let parameters = &state.parameters;
+    let n_iterations = parameters.iterations();
+    if n_iterations == 0 {
+        return Err(Box::new(NoIterations));
+    }
+    let chunks = n_iterations.div_ceil(chunk_size);
+    let n_tols = state.tolerance_loop.len();
+    let equation = StackEquation::of(state, &state.tolerance_loop);
+    let (lower, upper) = histogram_range(&state.tolerance_loop, &state.correlations, &equation);
+    let mut empty = StackAccumulator::new(n_tols, lower, upper, &state.parameters);
+    if parameters.vector_loop {
+        let (lower, upper) = histogram_range(
+            &state.tolerance_loop,
+            &state.correlations,
+            &StackEquation::projection(&state.tolerance_loop, Axis::Y),
+        );
+        empty = empty.with_across(lower, upper);
     }
 
-    //result_mean = result_mean / chunks as f64;
-    result_stddev_neg /= chunks as f64;
-    result_stddev_pos /= chunks as f64;
+    // Every worker in the pool evaluates whole iterations of the stack, taking the next chunk that
+    //  has not been started until there are none left.
+    let copula = Copula::new(&state.tolerance_loop, &state.correlations)?;
+    let pool = WorkerPool::global();
+    let tol_collection = Arc::new(state.tolerance_loop.clone());
+    let equation = Arc::new(equation);
+    let next_chunk = Arc::new(AtomicUsize::new(0));
+    let converged = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    for _ in 0..pool.n_workers().min(chunks) {
+        let tx_local = mpsc::Sender::clone(&tx);
+        let tol_collection = Arc::clone(&tol_collection);
+        let equation = Arc::clone(&equation);
+        let next_chunk = Arc::clone(&next_chunk);
+        let empty = empty.clone();
+        let seed = state.parameters.seed;
+        let sampling = state.parameters.sampling;
+        let mut copula = copula.clone();
+        let cancel = Arc::clone(&cancel);
+        let converged = Arc::clone(&converged);
+        pool.execute(move || loop {
+            let chunk = next_chunk.fetch_add(1, Ordering::Relaxed);
+            if chunk >= chunks
+                || cancel.load(Ordering::Relaxed)
+                || converged.load(Ordering::Relaxed)
+            {
+                return;
+            }
+            let chunk_iterations = chunk_size.min(n_iterations - chunk * chunk_size);
+            let mut accumulator = empty.clone();
+            compute_stackup(
+                &tol_collection,
+                &equation,
+                sampling,
+                copula.as_mut(),
+                chunk_iterations,
+                seed,
+                chunk as u64,
+                &mut accumulator,
+            );
+            if tx_local.send((chunk, accumulator)).is_err() {
+                return;
+            }
+        });
+    }
+    drop(tx);
 
-    result_mean = mean(&result);
+    // Workers finish in any order, so merge the chunks in chunk order. Floating point addition is
+    //  not associative, and this keeps the results identical from run to run, on any machine.
+    let mut total = empty;
+    let mut pending = BTreeMap::new();
+    let mut next_merge = 0;
+    'merge: for (chunk, accumulator) in rx {
+        pending.insert(chunk, accumulator);
+        while let Some(accumulator) = pending.remove(&next_merge) {
+            total.merge(&accumulator);
+            next_merge += 1;
+            progress(Progress {
+                completed: total.moments().count() as usize,
+                total: n_iterations,
+                mean: total.moments().mean(),
+                stddev: total.moments().variance().sqrt(),
+            });
+            // Convergence is only checked between chunks in chunk order, so a converged result
+            //  is still reproducible. Chunks finished after this point are discarded.
+            if parameters.converge && has_converged(&total, parameters) {
+                converged.store(true, Ordering::Relaxed);
+                break 'merge;
+            }
+        }
+    }
+    let converged = converged.load(Ordering::Relaxed);
+    // Workers stop taking new chunks once cancelled, so some chunks were never run.
+    if next_merge < chunks && !converged {
+        return Err(Box::new(Cancelled));
+    }
 
+    let moments = total.moments();
+    let result_mean = moments.mean();
+    let (result_stddev_neg, result_stddev_pos) = total.semi_deviations();
     let result_tol_pos = result_stddev_pos * state.parameters.assy_sigma;
     let result_tol_neg = result_stddev_neg * state.parameters.assy_sigma;
+    let stddev_error = semi_deviation_standard_error(&total);
+    let tolerance_error = tolerance_standard_error(&total, parameters);
 
-    let worst_case_dim = state.tolerance_loop.iter().fold(0.0, |acc, tol| {
-        acc + match tol {
-            Tolerance::Linear(linear) => linear.distance.dim,
-            Tolerance::Float(_) => 0.0,
-        }
-    });
-
-    let worst_case_pos = state.tolerance_loop.iter().fold(0.0, |acc, tol| {
-        acc + f64::abs(match tol {
-            Tolerance::Linear(linear) => linear.distance.tol_pos,
-            Tolerance::Float(float) => f64::max(
-                0.0,
-                ((float.hole.dim + float.hole.tol_pos) - (float.pin.dim - float.pin.tol_neg)) / 2.0,
-            ),
-        })
-    });
-
-    let worst_case_neg = state.tolerance_loop.iter().fold(0.0, |acc, tol| {
-        acc + f64::abs(match tol {
-            Tolerance::Linear(linear) => linear.distance.tol_neg,
-            Tolerance::Float(float) => f64::max(
-                0.0,
-                ((float.hole.dim + float.hole.tol_pos) - (float.pin.dim - float.pin.tol_neg)) / 2.0,
-            ),
-        })
-    });
-
-    dbg!(
-        worst_case_dim,
-        worst_case_neg,
-        worst_case_pos,
-        result_stddev_neg,
-        result_stddev_pos
-    );
+    let percentiles = Percentiles::from_fn(|p| total.percentile(p));
 
-    let worst_case_upper = worst_case_dim + worst_case_pos;
-    let worst_case_lower = worst_case_dim - worst_case_neg;
+    let spread = StackSpread {
+        mean: result_mean,
+        stddev_neg: result_stddev_neg,
+        stddev_pos: result_stddev_pos,
+        lower_percentile: percentiles.p0_135,
+        median: percentiles.p50,
+        upper_percentile: percentiles.p99_865,
+    };
+    let spec = match SpecResults::new(&state.parameters, &spread, total.fraction_out_of_spec())
+        .map(|spec| spec.with_intervals(moments.count() as usize))
+    {
+        // Few, if any, of the simulated assemblies fall outside of a capable spec, so the tails
+        //  are sampled again to estimate the fraction out of spec.
+        Some(spec) if parameters.importance_sampling => {
+            let (fraction_out, interval) = importance_sampling::fraction_out_of_spec(
+                &state.tolerance_loop,
+                &equation,
+                copula.clone(),
+                parameters,
+                &cancel,
+            )?;
+            Some(spec.with_fraction_out(fraction_out, interval))
+        }
+        spec => spec,
+    };
 
     Ok(McResults {
         mean: result_mean,
@@ -102,85 +210,346 @@ pub async fn run(state: &State) -> Result<McResults, Box<dyn Error>> {
         tolerance_neg: result_tol_neg,
         stddev_pos: result_stddev_pos,
         stddev_neg: result_stddev_neg,
-        iterations: real_iters,
-        worst_case_upper,
-        worst_case_lower,
+        iterations: moments.count() as usize,
+        contributions: total.contributions(),
+        spec,
+        histogram: total.histogram(HISTOGRAM_BINS),
+        percentiles,
+        skewness: moments.skewness(),
+        kurtosis: moments.kurtosis(),
+        mean_interval: Interval::from_standard_error(result_mean, moments.standard_error_mean()),
+        tolerance_pos_interval: Interval::from_standard_error(result_tol_pos, tolerance_error),
+        tolerance_neg_interval: Interval::from_standard_error(result_tol_neg, tolerance_error),
+        stddev_pos_interval: Interval::from_standard_error(result_stddev_pos, stddev_error),
+        stddev_neg_interval: Interval::from_standard_error(result_stddev_neg, stddev_error),
+        percentile_intervals: Percentiles::from_fn(|p| total.percentile_interval(p)),
+        skewness_interval: Interval::from_standard_error(
+            moments.skewness(),
+            moments.standard_error_skewness(),
+        ),
+        kurtosis_interval: Interval::from_standard_error(
+            moments.kurtosis(),
+            moments.standard_error_kurtosis(),
+        ),
+        converged: if parameters.converge {
+            Some(converged)
+        } else {
+            None
+        },
+        axes: match total.across() {
+            Some(across) => vec![axis_results(Axis::X, &total), axis_results(Axis::Y, across)],
+            None => Vec::new(),
+        },
     })
 }
 
+/// The simulated spread of one component of the vector that closes a vector loop
+fn axis_results(axis: Axis, accumulator: &StackAccumulator) -> AxisResults {
+    let (stddev_neg, stddev_pos) = accumulator.semi_deviations();
+    let percentiles = Percentiles::from_fn(|p| accumulator.percentile(p));
+    AxisResults {
+        axis,
+        mean: accumulator.moments().mean(),
+        stddev_neg,
+        stddev_pos,
+        lower_percentile: percentiles.p0_135,
+        upper_percentile: percentiles.p99_865,
+    }
+}
+
+/// Approximate standard error of the +/- half standard deviations. Each is found from about half
+/// of the iterations, so its error is larger than that of the full standard deviation by a factor
+/// of root two.
+fn semi_deviation_standard_error(accumulator: &StackAccumulator) -> f64 {
+    2f64.sqrt() * accumulator.moments().standard_error_stddev()
+}
+
+/// Approximate standard error of the +/- tolerance
+fn tolerance_standard_error(accumulator: &StackAccumulator, parameters: &Parameters) -> f64 {
+    parameters.assy_sigma * semi_deviation_standard_error(accumulator)
+}
+
+/// The simulation has converged once the standard errors of the mean and of the tolerance are both
+/// below the threshold.
+fn has_converged(accumulator: &StackAccumulator, parameters: &Parameters) -> bool {
+    accumulator.moments().standard_error_mean() < parameters.convergence_threshold
+        && tolerance_standard_error(accumulator, parameters) < parameters.convergence_threshold
+}
+
+/// Number of iterations simulated by a worker at a time. This can be used to tune performance.
+const CHUNK_SIZE: usize = 100000;
+
+/// Number of bins used for the histogram of the stack
+const HISTOGRAM_BINS: usize = 100;
+
+/// The range in which the stack is binned. This covers the worst case limits of the stack, and 8
+/// standard deviations of the RSS estimate on either side of the mean, for contributors with
+/// unbounded distributions. A stack expression is linearized to find the range.
+fn histogram_range(
+    tol_collection: &[Tolerance],
+    correlations: &[Correlation],
+    equation: &StackEquation,
+) -> (f64, f64) {
+    let tol_collection = equation.linearized(tol_collection);
+    let mean: f64 = equation.constant + tol_collection.iter().map(|tol| tol.mean()).sum::<f64>();
+    let stddev = (tol_collection.iter().map(|tol| tol.variance()).sum::<f64>()
+        + correlation::covariance(&tol_collection, correlations))
+    .max(0.0)
+    .sqrt();
+    let (wc_lower, wc_upper) = tol_collection.iter().fold(
+        (equation.constant, equation.constant),
+        |(lower, upper), tol| {
+            let bounds = tol.worst_case_bounds();
+            (lower + bounds.min, upper + bounds.max)
+        },
+    );
+    let lower = f64::min(wc_lower, mean - 8.0 * stddev);
+    let upper = f64::max(wc_upper, mean + 8.0 * stddev);
+    if upper - lower > f64::EPSILON * mean.abs().max(1.0) {
+        (lower, upper)
+    } else {
+        // A stack without any variation still needs a range to bin it in.
+        (mean - 0.5, mean + 0.5)
+    }
+}
+
 impl Tolerance {
     #[inline(always)]
-    pub fn mc_tolerance(&self) -> f64 {
+    pub fn mc_tolerance<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        self.sensitivity()
+            * match self {
+                Tolerance::Linear(val) => val.mc_tolerance(rng),
+                Tolerance::Float(val) => val.mc_tolerance(rng),
+                Tolerance::Compound(val) => val.mc_tolerance(rng),
+                Tolerance::Angular(val) => val.mc_tolerance(rng),
+            }
+    }
+    #[inline(always)]
+    pub fn mc_tolerance_at(&self, point: &[f64]) -> f64 {
+        self.sensitivity()
+            * match self {
+                Tolerance::Linear(val) => val.mc_tolerance_at(point),
+                Tolerance::Float(val) => val.mc_tolerance_at(point),
+                Tolerance::Compound(val) => val.mc_tolerance_at(point),
+                Tolerance::Angular(val) => val.mc_tolerance_at(point),
+            }
+    }
+    pub fn dimensions(&self) -> usize {
         match self {
-            Tolerance::Linear(val) => val.mc_tolerance(),
-            Tolerance::Float(val) => val.mc_tolerance(),
+            Tolerance::Linear(val) => val.dimensions(),
+            Tolerance::Float(val) => val.dimensions(),
+            Tolerance::Compound(val) => val.dimensions(),
+            Tolerance::Angular(val) => val.dimensions(),
         }
     }
 }
 
-/// Generate a sample for each object in the tolerance collection, n_iterations times. Then sum
-/// the results for each iteration, resulting in stackup for that iteration of the simulation.
-pub fn compute_stackup(tol_collection: Vec<Tolerance>, n_iterations: usize) -> Vec<f64> {
-    // Make a local clone of the tolerance collection so the borrow is not returned while the
-    //  threads are using the collection.
-    let tc_local = tol_collection;
-    // Store output in `samples` vector, appending each tol_collection's output
-    let n_tols = tc_local.len();
-    let mut samples: Vec<f64> = Vec::with_capacity(n_tols * n_iterations);
-    let (tx, rx) = mpsc::channel();
-    // For each tolerance object generate n samples, dividing the work between multiple threads.
-    for tol_struct in tc_local {
-        let n_threads = 4;
-        for _i in 0..n_threads {
-            // Create a thread local copy of the thread communication sender for ownership reasons.
-            let tx_local = mpsc::Sender::clone(&tx);
-            thread::spawn(move || {
-                // Make `result` thread local for better performance.
-                let mut result: Vec<f64> = Vec::new();
-                for _i in 0..n_iterations / n_threads {
-                    result.push(tol_struct.mc_tolerance());
-                }
-                tx_local.send(result).unwrap();
-            });
+/// Generate a sample for each object in the tolerance collection, n_iterations times. Each
+/// iteration's samples are combined into a stackup by the `equation`, which is added to the
+/// `accumulator`. If there is a `copula`, the samples of correlated contributors are correlated
+/// through it.
+///
+/// The samples are drawn from a random number stream chosen by the `seed` and the `chunk` being
+/// computed, so the results are identical for a given seed no matter which thread runs the chunk.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_stackup(
+    tol_collection: &[Tolerance],
+    equation: &StackEquation,
+    sampling: SamplingStrategy,
+    mut copula: Option<&mut Copula>,
+    n_iterations: usize,
+    seed: u64,
+    chunk: u64,
+    accumulator: &mut StackAccumulator,
+) {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(chunk);
+    // Reuse the samples buffer between iterations
+    let mut samples: Vec<f64> = vec![0.0; tol_collection.len()];
+    if sampling == SamplingStrategy::Random && copula.is_none() {
+        for _i in 0..n_iterations {
+            for (sample, tol_struct) in samples.iter_mut().zip(tol_collection) {
+                *sample = tol_struct.mc_tolerance(&mut rng);
+            }
+            push_stackup(equation, &mut samples, accumulator);
+        }
+        return;
+    }
+    // Otherwise, a point in the unit hypercube is chosen for each iteration, with one dimension
+    //  for every independent variable in the stack. Each tolerance is then sampled by mapping its
+    //  share of the point through the inverse CDF of its distribution.
+    let n_dims: usize = tol_collection.iter().map(|tol| tol.dimensions()).sum();
+    let mut point: Vec<f64> = vec![0.0; n_dims];
+    let mut points = match sampling {
+        SamplingStrategy::Random => PointSet::Random,
+        SamplingStrategy::LatinHypercube => {
+            PointSet::latin_hypercube(n_dims, n_iterations, &mut rng)
         }
-        for _i in 0..n_threads {
-            samples.extend_from_slice(&rx.recv().unwrap());
+        SamplingStrategy::Halton => PointSet::halton(n_dims, seed, chunk as usize * CHUNK_SIZE),
+    };
+    for i in 0..n_iterations {
+        points.fill(i, &mut point, &mut rng);
+        if let Some(copula) = copula.as_mut() {
+            copula.correlate(&mut point);
         }
+        let mut dims = point.as_slice();
+        for (sample, tol_struct) in samples.iter_mut().zip(tol_collection) {
+            let (tol_point, rest) = dims.split_at(tol_struct.dimensions());
+            *sample = tol_struct.mc_tolerance_at(tol_point);
+            dims = rest;
+        }
+        push_stackup(equation, &mut samples, accumulator);
     }
+}
 
-    let mut result: Vec<f64> = Vec::with_capacity(n_iterations);
+/// Combines the samples of one iteration into a stackup, and adds it to the `accumulator`. The
+/// Y component of a vector loop is found first, as the samples are then scaled by the equation.
+#[inline(always)]
+fn push_stackup(equation: &StackEquation, samples: &mut [f64], accumulator: &mut StackAccumulator) {
+    let across = equation.across(samples);
+    let stackup = equation.evaluate(samples);
+    accumulator.push(stackup, samples);
+    if let Some(across) = across {
+        accumulator.push_across(across);
+    }
+}
 
-    for i in 0..n_iterations {
-        let mut stackup: f64 = 0.0;
-        for j in 0..n_tols {
-            stackup += samples[i + j * n_iterations];
+/// Generates the points in the unit hypercube that the tolerances are sampled at.
+enum PointSet {
+    /// Independent random points.
+    Random,
+    /// A random permutation of the strata of each dimension, indexed by iteration.
+    LatinHypercube { strata: Vec<Vec<u32>> },
+    /// The digit permutation and random shift of each dimension of the Halton sequence, and the
+    ///  index of the first point in the chunk.
+    Halton {
+        bases: Vec<u64>,
+        permutations: Vec<Vec<u64>>,
+        shifts: Vec<f64>,
+        first_index: usize,
+    },
+}
+impl PointSet {
+    fn latin_hypercube<R: Rng + ?Sized>(n_dims: usize, n_points: usize, rng: &mut R) -> Self {
+        let strata = (0..n_dims)
+            .map(|_| {
+                let mut stratum: Vec<u32> = (0..n_points as u32).collect();
+                stratum.shuffle(rng);
+                stratum
+            })
+            .collect();
+        PointSet::LatinHypercube { strata }
+    }
+
+    /// The scrambling only depends on the seed, so every chunk continues the same sequence.
+    fn halton(n_dims: usize, seed: u64, first_index: usize) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        rng.set_stream(u64::MAX);
+        let bases = primes(n_dims);
+        // Permuting the digits of each base breaks up the correlation between dimensions with
+        //  large bases. Zero is left in place, so the trailing zeros of each index stay zero.
+        let permutations = bases
+            .iter()
+            .map(|&base| {
+                let mut permutation: Vec<u64> = (0..base).collect();
+                permutation[1..].shuffle(&mut rng);
+                permutation
+            })
+            .collect();
+        let shifts = (0..n_dims).map(|_| rng.gen::<f64>()).collect();
+        PointSet::Halton {
+            bases,
+            permutations,
+            shifts,
+            first_index,
         }
-        result.push(stackup);
+    }
+
+    /// Writes the point for iteration `i` of the chunk
+    fn fill<R: Rng + ?Sized>(&mut self, i: usize, point: &mut [f64], rng: &mut R) {
+        match self {
+            PointSet::Random => {
+                for u in point.iter_mut() {
+                    *u = rng.gen::<f64>();
+                }
+            }
+            PointSet::LatinHypercube { strata } => {
+                for (u, stratum) in point.iter_mut().zip(strata.iter()) {
+                    *u = (stratum[i] as f64 + rng.gen::<f64>()) / stratum.len() as f64;
+                }
+            }
+            PointSet::Halton {
+                bases,
+                permutations,
+                shifts,
+                first_index,
+            } => {
+                let index = (*first_index + i) as u64;
+                for (d, u) in point.iter_mut().enumerate() {
+                    let radical_inverse = radical_inverse(index, bases[d], &permutations[d]);
+                    *u = (radical_inverse + shifts[d]).fract();
+                }
+            }
+        }
+    }
+}
+
+/// Reflects the digits of `index` in `base` about the decimal point, after permuting each digit.
+fn radical_inverse(mut index: u64, base: u64, permutation: &[u64]) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut factor = inverse_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += permutation[(index % base) as usize] as f64 * factor;
+        index /= base;
+        factor *= inverse_base;
     }
     result
 }
 
+/// The first `n` prime numbers
+fn primes(n: usize) -> Vec<u64> {
+    let mut primes: Vec<u64> = Vec::with_capacity(n);
+    let mut candidate = 2;
+    while primes.len() < n {
+        if primes.iter().all(|prime| candidate % prime != 0) {
+            primes.push(candidate);
+        }
+        candidate += 1;
+    }
+    primes
+}
+
 #[allow(dead_code)]
 impl Tolerance {
     fn compute_multiplier(&mut self) {
         match self {
             Tolerance::Linear(tol) => tol.compute_multiplier(),
             Tolerance::Float(tol) => tol.compute_multiplier(),
+            Tolerance::Compound(tol) => tol.compute_multiplier(),
+            Tolerance::Angular(tol) => tol.compute_multiplier(),
         }
     }
 }
 
 pub trait MonteCarlo: Send + Sync + 'static {
-    fn mc_tolerance(&self) -> f64;
+    fn mc_tolerance<R: Rng + ?Sized>(&self, rng: &mut R) -> f64;
+    /// Sample the tolerance at a point in the unit hypercube, instead of at random.
+    fn mc_tolerance_at(&self, point: &[f64]) -> f64;
+    /// Number of independent variables sampled, which is the length of the point.
+    fn dimensions(&self) -> usize;
     fn compute_multiplier(&mut self);
     //fn get_name(&self) -> &str;
 }
 impl MonteCarlo for LinearTL {
-    fn mc_tolerance(&self) -> f64 {
-        self.distance.dim
-            + self
-                .distance
-                .sample_mc(DistributionParam::Normal, BoundingParam::KeepAll)
+    fn mc_tolerance<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        self.distance.dim + self.distance.sample_mc(rng)
+    }
+    fn mc_tolerance_at(&self, point: &[f64]) -> f64 {
+        self.distance.dim + self.distance.quantile(point[0])
+    }
+    fn dimensions(&self) -> usize {
+        1
     }
     //fn get_name(&self) -> &str {
     //    &self.name
@@ -190,21 +559,23 @@ impl MonteCarlo for LinearTL {
     }
 }
 impl MonteCarlo for FloatTL {
-    fn mc_tolerance(&self) -> f64 {
-        let hole_sample = self
-            .hole
-            .sample_mc(DistributionParam::Normal, BoundingParam::KeepAll);
-        let pin_sample = self
-            .pin
-            .sample_mc(DistributionParam::Normal, BoundingParam::KeepAll);
+    fn mc_tolerance<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        let hole_sample = self.hole.dim + self.hole.sample_mc(rng);
+        let pin_sample = self.pin.dim + self.pin.sample_mc(rng);
         let hole_pin_slop = (hole_sample - pin_sample) / 2.0;
         if hole_pin_slop <= 0.0 {
             0.0
         } else {
-            DimTol::new_normal(0.0, hole_pin_slop, hole_pin_slop, self.sigma)
-                .sample_mc(DistributionParam::Uniform, BoundingParam::KeepAll)
+            // The pin is equally likely to sit anywhere within the clearance.
+            hole_pin_slop * (2.0 * rng.sample(Uniform::new_inclusive(0.0, 1.0)) - 1.0)
         }
     }
+    fn mc_tolerance_at(&self, point: &[f64]) -> f64 {
+        float_at(&self.hole, &self.pin, point[0], point[1], point[2])
+    }
+    fn dimensions(&self) -> usize {
+        3
+    }
     fn compute_multiplier(&mut self) {
         self.hole.compute_multiplier();
         self.pin.compute_multiplier();
@@ -213,64 +584,171 @@ impl MonteCarlo for FloatTL {
     //    &self.name
     //}
 }
-
-pub enum DistributionParam {
-    Normal,
-    Uniform,
+impl MonteCarlo for CompoundFloatTL {
+    fn mc_tolerance<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        let mut point = [0.0; 6];
+        for u in point.iter_mut() {
+            *u = rng.gen();
+        }
+        self.mc_tolerance_at(&point)
+    }
+    /// The slot pin position is sampled before the round hole pin position, so the part is
+    /// correlated with other contributors through its position in the round hole.
+    fn mc_tolerance_at(&self, point: &[f64]) -> f64 {
+        let (along, across) = self.projection();
+        let slot_float = float_at(&self.slot, &self.slot_pin, point[2], point[3], point[4]);
+        let hole_float = float_at(&self.hole, &self.hole_pin, point[0], point[1], point[5]);
+        along * hole_float + across * slot_float
+    }
+    fn dimensions(&self) -> usize {
+        6
+    }
+    fn compute_multiplier(&mut self) {
+        self.hole.compute_multiplier();
+        self.hole_pin.compute_multiplier();
+        self.slot.compute_multiplier();
+        self.slot_pin.compute_multiplier();
+    }
+}
+impl MonteCarlo for AngularTL {
+    fn mc_tolerance<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        self.offset(self.angle.dim + self.angle.sample_mc(rng))
+    }
+    fn mc_tolerance_at(&self, point: &[f64]) -> f64 {
+        self.offset(self.angle.dim + self.angle.quantile(point[0]))
+    }
+    fn dimensions(&self) -> usize {
+        1
+    }
+    fn compute_multiplier(&mut self) {
+        self.angle.compute_multiplier();
+    }
 }
 
-#[allow(dead_code)]
-pub enum BoundingParam {
-    DiscardOutOfSpec,
-    KeepAll,
+/// The float of a pin in a hole, given the probabilities of the hole and pin sizes and of the
+/// position of the pin within the clearance. The pin is equally likely to sit anywhere within the
+/// clearance, and there is no float if the parts interfere.
+fn float_at(hole: &DimTol, pin: &DimTol, hole_u: f64, pin_u: f64, position_u: f64) -> f64 {
+    let hole_sample = hole.dim + hole.quantile(hole_u);
+    let pin_sample = pin.dim + pin.quantile(pin_u);
+    let hole_pin_slop = (hole_sample - pin_sample) / 2.0;
+    if hole_pin_slop <= 0.0 {
+        0.0
+    } else {
+        hole_pin_slop * (2.0 * position_u - 1.0)
+    }
 }
 
 impl DimTol {
-    /// Generate a random sample of a given dimension
-    fn sample_mc(&self, distribution: DistributionParam, bounding: BoundingParam) -> f64 {
-        match distribution {
-            DistributionParam::Normal => match bounding {
-                BoundingParam::DiscardOutOfSpec => self.rand_bound_norm(),
-                BoundingParam::KeepAll => self.rand_unbound_norm(),
-            },
-            DistributionParam::Uniform => match bounding {
-                BoundingParam::DiscardOutOfSpec => self.rand_bound_uniform(),
-                BoundingParam::KeepAll => self.rand_unbounded_uniform(),
-            },
+    /// Generate a random sample of a given dimension, using the dimension's distribution
+    fn sample_mc<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match self.dist {
+            TolDistribution::Normal => self.rand_unbound_norm(rng),
+            TolDistribution::TruncatedNormal => self.rand_bound_norm(rng),
+            TolDistribution::Uniform => self.rand_uniform(rng),
+            TolDistribution::Triangular => self.rand_triangular(rng),
+            TolDistribution::LogNormal => self.rand_lognormal(rng),
+            TolDistribution::Weibull => self.rand_weibull(rng),
+            TolDistribution::Rayleigh => self.rand_rayleigh(rng),
+            TolDistribution::FoldedNormal => self.rand_folded_norm(rng),
         }
     }
 
-    /// Generate a normally distributed random value, discarding values outside of limits
-    fn rand_bound_norm(&self) -> f64 {
-        let mut sample: f64 = thread_rng().sample(StandardNormal);
-        sample *= self.tol_multiplier;
-        // TODO: limit number of checks and error out if needed to escape infinite loop
-        while sample < -self.tol_neg || sample > self.tol_pos {
-            sample = thread_rng().sample(StandardNormal);
-            sample *= self.tol_multiplier;
+    /// The inverse of the cumulative distribution function of the dimension, as an offset from
+    /// the nominal dimension. Maps a probability `u` from 0 to 1 to the value that a sample falls
+    /// below with that probability.
+    pub fn quantile(&self, u: f64) -> f64 {
+        // Stay clear of 0 and 1, where unbounded distributions go to infinity.
+        let u = u.clamp(f64::EPSILON, 1.0 - f64::EPSILON);
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        match self.dist {
+            TolDistribution::Normal => {
+                self.process_center() + self.tol_multiplier * normal.inverse_cdf(u)
+            }
+            // A dimension without any tolerance does not vary.
+            TolDistribution::TruncatedNormal if self.tol_multiplier <= 0.0 => {
+                self.process_center().clamp(-self.tol_neg, self.tol_pos)
+            }
+            TolDistribution::TruncatedNormal => {
+                let lower =
+                    normal.cdf((-self.tol_neg - self.process_center()) / self.tol_multiplier);
+                let upper =
+                    normal.cdf((self.tol_pos - self.process_center()) / self.tol_multiplier);
+                self.process_center()
+                    + self.tol_multiplier * normal.inverse_cdf(lower + u * (upper - lower))
+            }
+            TolDistribution::Uniform => {
+                self.mean_shift + (u * (self.tol_neg + self.tol_pos)) - self.tol_neg
+            }
+            TolDistribution::Triangular => {
+                let band = self.tol_neg + self.tol_pos;
+                if u < 0.5 {
+                    self.mean_shift - self.tol_neg + band * (u / 2.0).sqrt()
+                } else {
+                    self.mean_shift + self.tol_pos - band * ((1.0 - u) / 2.0).sqrt()
+                }
+            }
+            TolDistribution::LogNormal => {
+                let (mu, shape) = self.lognormal_params();
+                self.mean_shift - self.tol_neg + (mu + shape * normal.inverse_cdf(u)).exp()
+            }
+            TolDistribution::Weibull => {
+                let (scale, shape) = self.weibull_params();
+                self.mean_shift - self.tol_neg + scale * (-(1.0 - u).ln()).powf(1.0 / shape)
+            }
+            TolDistribution::Rayleigh => {
+                self.mean_shift - self.tol_neg
+                    + self.rayleigh_scale() * (-2.0 * (1.0 - u).ln()).sqrt()
+            }
+            TolDistribution::FoldedNormal => {
+                self.mean_shift - self.tol_neg
+                    + self.folded_normal_scale() * normal.inverse_cdf((1.0 + u) / 2.0)
+            }
         }
-        sample
     }
-    fn rand_unbound_norm(&self) -> f64 {
-        let mut sample: f64 = thread_rng().sample(StandardNormal);
-        sample *= self.tol_multiplier;
-        sample
+
+    /// Offset of the process mean of a normal distribution from the nominal dimension
+    fn process_center(&self) -> f64 {
+        self.band_center() + self.mean_shift
     }
-    fn rand_bound_uniform(&self) -> f64 {
-        let mut sample: f64 = thread_rng().sample(Uniform::new_inclusive(-1.0, 1.0));
-        sample = (sample * (self.tol_neg + self.tol_pos)) - self.tol_neg;
-        // TODO: limit number of checks and error out if needed to escape infinite loop
-        while sample < -self.tol_neg || sample > self.tol_pos {
-            sample = thread_rng().sample(StandardNormal);
-            sample = (sample * (self.tol_neg + self.tol_pos)) - self.tol_neg;
-        }
-        sample
+
+    /// Generate a normally distributed value truncated at the tolerance limits. Sampling through
+    /// the quantile takes the same time however far the process mean is from the band, where
+    /// discarding values outside the limits may never finish.
+    fn rand_bound_norm<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        self.quantile(rng.sample(Uniform::new(0.0, 1.0)))
     }
-    fn rand_unbounded_uniform(&self) -> f64 {
-        let mut sample: f64 = thread_rng().sample(Uniform::new_inclusive(-1.0, 1.0));
-        sample = (sample * (self.tol_neg + self.tol_pos)) - self.tol_neg;
+    fn rand_unbound_norm<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        let mut sample: f64 = rng.sample(StandardNormal);
+        sample = self.process_center() + sample * self.tol_multiplier;
         sample
     }
+    /// Generate a value that is equally likely to fall anywhere in the tolerance band
+    fn rand_uniform<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        self.quantile(rng.sample(Uniform::new_inclusive(0.0, 1.0)))
+    }
+    /// Generate a value from a symmetric triangular distribution spanning the tolerance band,
+    /// using the inverse of its cumulative distribution function.
+    fn rand_triangular<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        self.quantile(rng.sample(Uniform::new_inclusive(0.0, 1.0)))
+    }
+
+    // The skewed distributions are sampled as an offset from the lower tolerance limit.
+    fn rand_lognormal<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        let (mu, shape) = self.lognormal_params();
+        let sample: f64 = rng.sample(StandardNormal);
+        self.mean_shift - self.tol_neg + (mu + shape * sample).exp()
+    }
+    fn rand_weibull<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        self.quantile(rng.sample(Uniform::new(0.0, 1.0)))
+    }
+    fn rand_rayleigh<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        self.quantile(rng.sample(Uniform::new(0.0, 1.0)))
+    }
+    fn rand_folded_norm<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        let sample: f64 = rng.sample(StandardNormal);
+        self.mean_shift - self.tol_neg + self.folded_normal_scale() * sample.abs()
+    }
 
     /// Precompute constant in monte carlo equation
     fn compute_multiplier(&mut self) {
@@ -280,29 +758,286 @@ impl DimTol {
 
 /// Data for testing purposes
 #[allow(dead_code)]
-pub fn test_data() -> State {
+pub fn test_data() -> Result<State, InvalidTolerance> {
     let parameters = Parameters {
-        assy_sigma: 4.0,
-        n_iterations: 10000000,
+        n_iterations: 10_000_000,
+        rss_methods: vec![RssMethod::Classic],
+        ..Parameters::default()
     };
 
     let mut model = State::new(parameters);
 
     model.add(Tolerance::Linear(LinearTL::new(DimTol::new_normal(
         65.88, 0.17, 0.17, 3.0,
-    ))));
+    )?)));
 
     model.add(Tolerance::Float(FloatTL::new(
-        DimTol::new_normal(2.50, 0.1, 0.0, 3.0),
-        DimTol::new_normal(3.0, 0.08, 0.22, 3.0),
+        DimTol::new_normal(2.50, 0.1, 0.0, 3.0)?,
+        DimTol::new_normal(3.0, 0.08, 0.22, 3.0)?,
         3.0,
     )));
 
     model.add(Tolerance::Float(FloatTL::new(
-        DimTol::new_normal(2.50, 0.1, 0.0, 3.0),
-        DimTol::new_normal(3.0, 0.08, 0.22, 3.0),
+        DimTol::new_normal(2.50, 0.1, 0.0, 3.0)?,
+        DimTol::new_normal(3.0, 0.08, 0.22, 3.0)?,
         3.0,
     )));
 
-    model
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::{float, linear, normal};
+    use super::*;
+
+    fn test_stack(n_iterations: usize) -> State {
+        let mut state = State::default();
+        state.parameters.n_iterations = n_iterations;
+        for _ in 0..10 {
+            state.add(linear(10.0, 0.3, 0.3));
+            state.add(float(3.1, 0.05, 3.0, 0.05));
+        }
+        state
+    }
+
+    #[test]
+    fn runs_exact_iteration_count() {
+        let n_iterations = 150_001;
+        let results = async_std::task::block_on(run(&test_stack(n_iterations))).unwrap();
+        assert_eq!(results.iterations, n_iterations);
+        assert_eq!(results.histogram.counts.iter().sum::<usize>(), n_iterations);
+    }
+
+    #[test]
+    fn histogram_counts_values_outside_range() {
+        let mut accumulator = StackAccumulator::new(0, 0.0, 1.0, &Parameters::default());
+        for &stack in &[0.5, -1.0, 2.0, 3.0] {
+            accumulator.push(stack, &[]);
+        }
+        let histogram = accumulator.histogram(HISTOGRAM_BINS);
+        assert_eq!(histogram.counts.iter().sum::<usize>(), 1);
+        assert_eq!((histogram.below, histogram.above), (1, 2));
+    }
+
+    #[test]
+    fn converging_without_iterations_is_an_error() {
+        let mut state = test_stack(1000);
+        state.parameters.converge = true;
+        state.parameters.max_iterations = 0;
+        let error = async_std::task::block_on(run(&state)).unwrap_err();
+        assert!(error.is::<NoIterations>());
+    }
+
+    #[test]
+    fn quantile_matches_distribution() {
+        // Averaging the quantile over evenly spaced probabilities integrates the distribution.
+        let n = 200_000;
+        for &dist in TolDistribution::ALL.iter() {
+            let dim_tol = normal(10.0, 0.2, 0.2).with_distribution(dist);
+            let samples: Vec<f64> = (0..n)
+                .map(|i| dim_tol.dim + dim_tol.quantile((i as f64 + 0.5) / n as f64))
+                .collect();
+            let mean = samples.iter().sum::<f64>() / n as f64;
+            let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n as f64;
+            assert!((mean - dim_tol.mean()).abs() < 1e-4, "{} mean", dist);
+            assert!(
+                (variance.sqrt() - dim_tol.stddev()).abs() < 1e-3 * dim_tol.stddev(),
+                "{} standard deviation",
+                dist
+            );
+        }
+    }
+
+    #[test]
+    fn importance_sampling_stops_when_cancelled() {
+        let mut state = test_stack(0);
+        state.parameters.upper_spec = Some(131.0);
+        let equation = StackEquation::new(None, &state.tolerance_loop);
+        let result = importance_sampling::fraction_out_of_spec(
+            &state.tolerance_loop,
+            &equation,
+            None,
+            &state.parameters,
+            &AtomicBool::new(true),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn truncated_normal_with_mean_shift_outside_band() {
+        // The process mean is far outside the band, where discarding samples would never finish.
+        let dim_tol = normal(10.0, 0.1, 0.1)
+            .with_distribution(TolDistribution::TruncatedNormal)
+            .with_mean_shift(0.3);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        for _ in 0..10_000 {
+            let sample = dim_tol.sample_mc(&mut rng);
+            assert!(
+                (-0.1..=0.1).contains(&sample),
+                "sample {} outside the band",
+                sample
+            );
+        }
+    }
+
+    #[test]
+    fn cpk_rejects_mean_shift_outside_band() {
+        let dim_tol = normal(10.0, 0.1, 0.1);
+        assert!(dim_tol.with_mean_shift(0.05).with_cpk(1.0).is_ok());
+        assert!(dim_tol.with_mean_shift(0.1).with_cpk(1.0).is_err());
+        assert!(dim_tol.with_mean_shift(-0.3).with_cpk(1.0).is_err());
+        assert!(!dim_tol.with_mean_shift(0.3).is_mean_shift_valid());
+    }
+
+    #[test]
+    fn samples_match_distribution_moments() {
+        // Every distribution, shifted off the center of its band, sampled by every strategy has
+        //  the mean and standard deviation of the distribution, within the sampling error.
+        let n = 100_000;
+        for &sampling in SamplingStrategy::ALL.iter() {
+            for &dist in TolDistribution::ALL.iter() {
+                let dim_tol = normal(10.0, 0.2, 0.2)
+                    .with_distribution(dist)
+                    .with_mean_shift(0.05);
+                let tolerance_loop = vec![Tolerance::Linear(LinearTL::new(dim_tol))];
+                let equation = StackEquation::new(None, &tolerance_loop);
+                let (lower, upper) = histogram_range(&tolerance_loop, &[], &equation);
+                let mut accumulator =
+                    StackAccumulator::new(1, lower, upper, &Parameters::default());
+                compute_stackup(
+                    &tolerance_loop,
+                    &equation,
+                    sampling,
+                    None,
+                    n,
+                    42,
+                    0,
+                    &mut accumulator,
+                );
+                let moments = accumulator.moments();
+                let stddev = dim_tol.stddev();
+                assert!(
+                    (moments.mean() - dim_tol.mean()).abs() < 5.0 * stddev / (n as f64).sqrt(),
+                    "{} {} mean {} is not {}",
+                    sampling,
+                    dist,
+                    moments.mean(),
+                    dim_tol.mean()
+                );
+                assert!(
+                    (moments.variance().sqrt() / stddev - 1.0).abs() < 0.02,
+                    "{} {} standard deviation {} is not {}",
+                    sampling,
+                    dist,
+                    moments.variance().sqrt(),
+                    stddev
+                );
+            }
+        }
+    }
+
+    /// Runs the same small simulation with many seeds, and returns the variance of the estimates
+    /// of the mean and standard deviation of the stack between the runs.
+    fn estimator_variance(sampling: SamplingStrategy) -> (f64, f64) {
+        let state = test_stack(0);
+        let (lower, upper) = histogram_range(
+            &state.tolerance_loop,
+            &state.correlations,
+            &StackEquation::new(None, &state.tolerance_loop),
+        );
+        let (means, stddevs): (Vec<f64>, Vec<f64>) = (0..50)
+            .map(|seed| {
+                let mut accumulator = StackAccumulator::new(
+                    state.tolerance_loop.len(),
+                    lower,
+                    upper,
+                    &state.parameters,
+                );
+                compute_stackup(
+                    &state.tolerance_loop,
+                    &StackEquation::new(None, &state.tolerance_loop),
+                    sampling,
+                    None,
+                    2_000,
+                    seed,
+                    0,
+                    &mut accumulator,
+                );
+                let moments = accumulator.moments();
+                (moments.mean(), moments.variance().sqrt())
+            })
+            .unzip();
+        let variance = |estimates: &[f64]| {
+            let mean = estimates.iter().sum::<f64>() / estimates.len() as f64;
+            estimates.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / estimates.len() as f64
+        };
+        (variance(&means), variance(&stddevs))
+    }
+
+    #[test]
+    fn quasi_random_sampling_reduces_variance() {
+        let (random_mean, random_stddev) = estimator_variance(SamplingStrategy::Random);
+        for &sampling in &[SamplingStrategy::LatinHypercube, SamplingStrategy::Halton] {
+            let (mean, stddev) = estimator_variance(sampling);
+            assert!(
+                mean < random_mean / 4.0,
+                "{} mean variance is {:.2}x that of random sampling",
+                sampling,
+                mean / random_mean
+            );
+            assert!(
+                stddev < random_stddev,
+                "{} standard deviation variance is {:.2}x that of random sampling",
+                sampling,
+                stddev / random_stddev
+            );
+        }
+    }
+
+    /// Compares the worker pool against simulating every chunk on the current thread. Run with
+    /// `cargo test --release -- --ignored --nocapture` to see the speedup.
+    #[test]
+    #[ignore]
+    fn benchmark_worker_pool() {
+        use std::time::Instant;
+        let state = test_stack(4_000_000);
+        let (lower, upper) = histogram_range(
+            &state.tolerance_loop,
+            &state.correlations,
+            &StackEquation::new(None, &state.tolerance_loop),
+        );
+        let mut accumulator =
+            StackAccumulator::new(state.tolerance_loop.len(), lower, upper, &state.parameters);
+
+        let time_start = Instant::now();
+        for chunk in 0..40 {
+            compute_stackup(
+                &state.tolerance_loop,
+                &StackEquation::new(None, &state.tolerance_loop),
+                SamplingStrategy::Random,
+                None,
+                100_000,
+                0,
+                chunk,
+                &mut accumulator,
+            );
+        }
+        let single_thread = time_start.elapsed();
+
+        // Start the pool before timing it, so thread creation is not counted.
+        WorkerPool::global();
+        let time_start = Instant::now();
+        let results = async_std::task::block_on(run(&state)).unwrap();
+        let pool = time_start.elapsed();
+
+        assert_eq!(results.iterations, 4_000_000);
+        println!(
+            "Single thread: {:.3?}, {} workers: {:.3?}, speedup: {:.2}x",
+            single_thread,
+            WorkerPool::global().n_workers(),
+            pool,
+            single_thread.as_secs_f64() / pool.as_secs_f64()
+        );
+    }
 }
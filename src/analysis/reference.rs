@@ -0,0 +1,403 @@
+//! Canonical stacks with known analytic answers. Every simulation is run with a fixed seed, so the
+//! results are repeatable, and the bounds are wide enough that a correct implementation passes
+//! with any seed.
+use super::expression::Expression;
+use super::fixtures::{float, linear, normal};
+use super::monte_carlo::{self, test_data};
+use super::root_sum_square;
+use super::structures::*;
+use super::worst_case;
+use async_std::task::block_on;
+use statrs::distribution::{ContinuousCDF, Normal};
+
+const N_ITERATIONS: usize = 200_000;
+const SEED: u64 = 42;
+
+fn state(tolerance_loop: Vec<Tolerance>) -> State {
+    let mut state = State::default();
+    state.parameters.n_iterations = N_ITERATIONS;
+    state.parameters.seed = SEED;
+    state.tolerance_loop = tolerance_loop;
+    state
+}
+
+/// The mean and standard deviation of the stack found by classic RSS.
+fn rss_stddev(state: &State) -> (f64, f64) {
+    let rss = block_on(root_sum_square::run(state, RssMethod::Classic)).unwrap();
+    (
+        rss.mean(),
+        rss.tolerance_pos() / state.parameters.assy_sigma,
+    )
+}
+
+/// Asserts the simulated mean and standard deviation are within four standard errors of the
+/// expected values.
+fn assert_matches(results: &McResults, mean: f64, stddev: f64) {
+    let n = results.iterations as f64;
+    let mean_error = 4.0 * stddev / n.sqrt();
+    let stddev_error = 4.0 * stddev / (2.0 * n).sqrt();
+    assert!(
+        (results.mean - mean).abs() <= mean_error,
+        "mean {} is not within {} of {}",
+        results.mean,
+        mean_error,
+        mean
+    );
+    for &simulated in &[results.stddev_pos, results.stddev_neg] {
+        assert!(
+            (simulated - stddev).abs() <= stddev_error,
+            "standard deviation {} is not within {} of {}",
+            simulated,
+            stddev_error,
+            stddev
+        );
+    }
+}
+
+#[test]
+fn linear_normal_stack_matches_rss() {
+    let mut state = state(vec![
+        linear(10.0, 0.3, 0.3),
+        linear(25.0, 0.12, 0.12),
+        linear(4.0, 0.5, 0.1),
+        linear(7.5, 0.2, 0.0),
+    ]);
+    // Hand calculation: the asymmetric and unilateral tolerances are centered in their bands.
+    let mean = 10.0 + 25.0 + 4.2 + 7.6;
+    let stddev = (0.3f64.powi(2) + 0.12f64.powi(2) + 0.3f64.powi(2) + 0.1f64.powi(2)).sqrt() / 3.0;
+    let (rss_mean, rss_stddev) = rss_stddev(&state);
+    assert!((rss_mean - mean).abs() < 1e-12);
+    assert!((rss_stddev - stddev).abs() < 1e-12);
+
+    for &sampling in SamplingStrategy::ALL.iter() {
+        state.parameters.sampling = sampling;
+        let results = block_on(monte_carlo::run(&state)).unwrap();
+        assert_eq!(results.iterations, N_ITERATIONS);
+        assert_matches(&results, mean, stddev);
+        // A sum of normal dimensions is normal.
+        assert!(results.skewness.abs() < 0.05, "{} skewness", sampling);
+        assert!(results.kurtosis.abs() < 0.1, "{} kurtosis", sampling);
+    }
+}
+
+#[test]
+fn negative_dimensions() {
+    // A closed loop, measured in the opposite direction by the negative dimensions.
+    let state = state(vec![
+        linear(50.0, 0.1, 0.1),
+        linear(-20.0, 0.2, 0.2),
+        linear(-29.5, 0.1, 0.3),
+    ]);
+    let mean = 50.0 - 20.0 - 29.6;
+    let stddev = (0.1f64.powi(2) + 0.2f64.powi(2) + 0.2f64.powi(2)).sqrt() / 3.0;
+    let (rss_mean, rss_stddev) = rss_stddev(&state);
+    assert!((rss_mean - mean).abs() < 1e-12);
+    assert!((rss_stddev - stddev).abs() < 1e-12);
+
+    let worst_case = block_on(worst_case::run(&state));
+    assert!((worst_case.lower() - (49.9 - 20.2 - 29.8)).abs() < 1e-12);
+    assert!((worst_case.upper() - (50.1 - 19.8 - 29.4)).abs() < 1e-12);
+
+    let results = block_on(monte_carlo::run(&state)).unwrap();
+    assert_matches(&results, mean, stddev);
+}
+
+#[test]
+fn float_without_variation_is_uniform() {
+    // The pin floats uniformly by +/-0.1 in the hole, which has a standard deviation of 0.1/sqrt(3).
+    let state = state(vec![float(3.2, 0.0, 3.0, 0.0)]);
+    let stddev = 0.1 / 3f64.sqrt();
+    let (rss_mean, rss_stddev) = rss_stddev(&state);
+    assert!(rss_mean.abs() < 1e-12);
+    assert!((rss_stddev - stddev).abs() < 1e-12);
+
+    let results = block_on(monte_carlo::run(&state)).unwrap();
+    assert_matches(&results, 0.0, stddev);
+    // A uniform distribution has an excess kurtosis of -1.2.
+    assert!((results.kurtosis + 1.2).abs() < 0.05);
+}
+
+#[test]
+fn float_only_stack_matches_rss() {
+    let state = state(vec![
+        float(3.1, 0.05, 3.0, 0.05),
+        float(6.2, 0.1, 6.0, 0.05),
+        float(2.55, 0.05, 2.5, 0.0),
+    ]);
+    let (rss_mean, rss_stddev) = rss_stddev(&state);
+    assert!(rss_mean.abs() < 1e-12);
+
+    let results = block_on(monte_carlo::run(&state)).unwrap();
+    assert_matches(&results, 0.0, rss_stddev);
+    let worst_case = block_on(worst_case::run(&state));
+    assert!(results.percentiles.p0_135 >= worst_case.lower());
+    assert!(results.percentiles.p99_865 <= worst_case.upper());
+}
+
+#[test]
+fn interfering_float_does_not_move() {
+    // The pin is always larger than the hole, so there is no clearance to float in.
+    let state = state(vec![linear(10.0, 0.3, 0.3), float(3.0, 0.01, 3.2, 0.01)]);
+    let (rss_mean, rss_stddev) = rss_stddev(&state);
+    assert!((rss_mean - 10.0).abs() < 1e-12);
+    assert!((rss_stddev - 0.1).abs() < 1e-12);
+
+    let results = block_on(monte_carlo::run(&state)).unwrap();
+    assert_matches(&results, 10.0, 0.1);
+}
+
+#[test]
+fn test_data_matches_rss() {
+    let mut state = test_data().unwrap();
+    state.parameters.n_iterations = N_ITERATIONS;
+    state.parameters.seed = SEED;
+    let (rss_mean, rss_stddev) = rss_stddev(&state);
+    assert!((rss_mean - 65.88).abs() < 1e-12);
+
+    let results = block_on(monte_carlo::run(&state)).unwrap();
+    assert_matches(&results, rss_mean, rss_stddev);
+}
+
+#[test]
+fn fixed_seed_is_repeatable() {
+    let state = test_data().unwrap();
+    let mut state = State {
+        parameters: Parameters {
+            n_iterations: N_ITERATIONS,
+            seed: SEED,
+            ..state.parameters
+        },
+        ..state
+    };
+    let first = block_on(monte_carlo::run(&state)).unwrap();
+    let second = block_on(monte_carlo::run(&state)).unwrap();
+    assert_eq!(first.mean, second.mean);
+    assert_eq!(first.stddev_pos, second.stddev_pos);
+    assert_eq!(first.histogram.counts, second.histogram.counts);
+
+    state.parameters.seed = SEED + 1;
+    let other = block_on(monte_carlo::run(&state)).unwrap();
+    assert_ne!(first.mean, other.mean);
+}
+
+/// Asserts the fraction out of spec is within `relative` of the expected value, and within about
+/// four standard errors, or twice the width of its 95% confidence interval.
+fn assert_fraction_out(results: &McResults, expected: f64, relative: f64) {
+    let spec = results.spec.unwrap();
+    let interval = spec.fraction_out_interval().unwrap();
+    let error = (spec.fraction_out() - expected).abs();
+    assert!(
+        error <= relative * expected,
+        "fraction out {} is not within {} of {}",
+        spec.fraction_out(),
+        relative * expected,
+        expected
+    );
+    assert!(
+        error <= interval.upper - interval.lower,
+        "fraction out {} is outside of twice {:?} from {}",
+        spec.fraction_out(),
+        interval,
+        expected
+    );
+}
+
+#[test]
+fn importance_sampling_rare_upper_tail() {
+    // Six sigma quality with a 1.5 sigma drift, at 3.4 PPM
+    let mut state = state(vec![
+        linear(10.0, 0.3, 0.3),
+        linear(25.0, 0.12, 0.12),
+        linear(-4.0, 0.5, 0.1),
+    ]);
+    let mean = 10.0 + 25.0 - 3.8;
+    let stddev = (0.3f64.powi(2) + 0.12f64.powi(2) + 0.3f64.powi(2)).sqrt() / 3.0;
+    state.parameters.n_iterations = 10_000;
+    state.parameters.upper_spec = Some(mean + 4.5 * stddev);
+    state.parameters.importance_sampling = true;
+    let normal = Normal::new(0.0, 1.0).unwrap();
+    let expected = 1.0 - normal.cdf(4.5);
+
+    let results = block_on(monte_carlo::run(&state)).unwrap();
+    assert_fraction_out(&results, expected, 0.02);
+}
+
+#[test]
+fn importance_sampling_correlated_two_sided() {
+    let mut state = state(vec![
+        linear(10.0, 0.3, 0.3),
+        linear(5.0, 0.15, 0.15),
+        linear(-12.0, 0.6, 0.0),
+    ]);
+    state.correlate(Correlation {
+        first: 0,
+        second: 1,
+        coefficient: 0.5,
+    });
+    let mean = 10.0 + 5.0 - 11.7;
+    let stddev = (0.01 + 0.0025 + 0.01 + 2.0 * 0.5 * 0.1 * 0.05f64).sqrt();
+    state.parameters.n_iterations = 10_000;
+    state.parameters.lower_spec = Some(mean - 5.0 * stddev);
+    state.parameters.upper_spec = Some(mean + 4.0 * stddev);
+    state.parameters.importance_sampling = true;
+    let normal = Normal::new(0.0, 1.0).unwrap();
+    let expected = normal.cdf(-5.0) + (1.0 - normal.cdf(4.0));
+
+    let results = block_on(monte_carlo::run(&state)).unwrap();
+    assert_fraction_out(&results, expected, 0.02);
+}
+
+#[test]
+fn importance_sampling_matches_simulation_of_floats() {
+    // The stack is far from normal, so the answer is found by plain Monte Carlo, at a fraction
+    //  out of spec that it can resolve.
+    let mut state = state(vec![
+        linear(10.0, 0.05, 0.05),
+        float(3.1, 0.05, 3.0, 0.05),
+        float(6.2, 0.1, 6.0, 0.05),
+    ]);
+    state.parameters.n_iterations = 2_000_000;
+    state.parameters.lower_spec = Some(9.82);
+    state.parameters.upper_spec = Some(10.2);
+    let simulated = block_on(monte_carlo::run(&state)).unwrap().spec.unwrap();
+    let simulated_interval = simulated.fraction_out_interval().unwrap();
+
+    state.parameters.n_iterations = 10_000;
+    state.parameters.importance_sampling = true;
+    let sampled = block_on(monte_carlo::run(&state)).unwrap().spec.unwrap();
+    let sampled_interval = sampled.fraction_out_interval().unwrap();
+    let compared = format!(
+        "simulated {:e} {:?}, importance sampled {:e} {:?}",
+        simulated.fraction_out(),
+        simulated_interval,
+        sampled.fraction_out(),
+        sampled_interval
+    );
+    assert!(simulated.fraction_out() > 5e-4, "{}", compared);
+    assert!(
+        sampled_interval.lower <= simulated_interval.upper,
+        "{}",
+        compared
+    );
+    assert!(
+        simulated_interval.lower <= sampled_interval.upper,
+        "{}",
+        compared
+    );
+}
+
+#[test]
+fn sensitivity_coefficients_scale_contributors() {
+    // A lever doubles the first dimension, and the second is measured against the stack. They are
+    //  positively correlated, so their contributions to the stack cancel in part.
+    let mut state = state(vec![
+        linear(10.0, 0.3, 0.3).with_sensitivity(2.0),
+        linear(5.0, 0.15, 0.15).with_sensitivity(-1.0),
+    ]);
+    state.correlate(Correlation {
+        first: 0,
+        second: 1,
+        coefficient: 0.5,
+    });
+    let mean = 2.0 * 10.0 - 5.0;
+    let stddev = (0.04 + 0.0025 - 2.0 * 0.5 * 0.2 * 0.05f64).sqrt();
+    let (rss_mean, rss_stddev) = rss_stddev(&state);
+    assert!((rss_mean - mean).abs() < 1e-12);
+    assert!((rss_stddev - stddev).abs() < 1e-12);
+
+    let worst_case = block_on(worst_case::run(&state));
+    assert!((worst_case.lower() - (2.0 * 9.7 - 5.15)).abs() < 1e-12);
+    assert!((worst_case.upper() - (2.0 * 10.3 - 4.85)).abs() < 1e-12);
+
+    let results = block_on(monte_carlo::run(&state)).unwrap();
+    assert_matches(&results, mean, stddev);
+}
+
+#[test]
+fn stack_expression_matches_linearization() {
+    // A dimension projected through an angle. The angle varies little, so the stack is close to
+    //  its linearization about the mean.
+    let mut state = state(vec![
+        linear(10.0, 0.1, 0.1),
+        linear(20.0, 0.3, 0.3),
+        linear(30.0, 0.6, 0.6),
+    ]);
+    state.expression = Some(Expression::parse("a + b*cos(theta)", &["a", "b", "theta"]).unwrap());
+    let (sin, cos) = 30f64.to_radians().sin_cos();
+    let mean = 10.0 + 20.0 * cos;
+    let gradient = [1.0, cos, -20.0 * sin * 1f64.to_radians()];
+    let stddev = (gradient[0] * 0.1 / 3.0)
+        .hypot(gradient[1] * 0.1)
+        .hypot(gradient[2] * 0.2);
+    let (rss_mean, rss_stddev) = rss_stddev(&state);
+    assert!((rss_mean - mean).abs() < 1e-9);
+    assert!((rss_stddev - stddev).abs() < 1e-6);
+
+    let worst_case = block_on(worst_case::run(&state));
+    let spread = 0.1 + 0.3 * gradient[1] - 0.6 * gradient[2];
+    assert!((worst_case.lower() - (mean - spread)).abs() < 1e-6);
+    assert!((worst_case.upper() - (mean + spread)).abs() < 1e-6);
+
+    let results = block_on(monte_carlo::run(&state)).unwrap();
+    assert_matches(&results, mean, stddev);
+}
+
+#[test]
+fn angular_tolerance_matches_linearization() {
+    // A part tilted by 30° about a pivot 20 away moves the stack by `20·sin(θ)`.
+    let state = state(vec![
+        linear(10.0, 0.1, 0.1),
+        Tolerance::Angular(AngularTL::new(normal(30.0, 0.6, 0.6), 20.0)),
+    ]);
+    let (sin, cos) = 30f64.to_radians().sin_cos();
+    let mean = 10.0 + 20.0 * sin;
+    let stddev = (0.1f64 / 3.0).hypot(20.0 * cos * 0.2f64.to_radians());
+    let (rss_mean, rss_stddev) = rss_stddev(&state);
+    assert!((rss_mean - mean).abs() < 1e-9);
+    assert!((rss_stddev - stddev).abs() < 1e-9);
+
+    let worst_case = block_on(worst_case::run(&state));
+    let lower = 9.9 + 20.0 * 29.4f64.to_radians().sin();
+    let upper = 10.1 + 20.0 * 30.6f64.to_radians().sin();
+    assert!((worst_case.lower() - lower).abs() < 1e-9);
+    assert!((worst_case.upper() - upper).abs() < 1e-9);
+
+    let results = block_on(monte_carlo::run(&state)).unwrap();
+    assert_matches(&results, mean, stddev);
+}
+
+#[test]
+fn vector_loop_reports_each_axis() {
+    // Out along X, up along Y, and back along X. The loop closes 6 along X and 5 along Y.
+    let mut state = state(vec![
+        linear(10.0, 0.3, 0.3),
+        linear(5.0, 0.15, 0.15).with_direction(90.0),
+        linear(4.0, 0.06, 0.06).with_direction(180.0),
+    ]);
+    state.parameters.vector_loop = true;
+    let stddev_x = 0.1f64.hypot(0.02);
+    let (rss_mean, rss_stddev) = rss_stddev(&state);
+    assert!((rss_mean - 6.0).abs() < 1e-9);
+    assert!((rss_stddev - stddev_x).abs() < 1e-9);
+
+    let worst_case = block_on(worst_case::run(&state));
+    assert!((worst_case.lower() - (9.7 - 4.06)).abs() < 1e-9);
+    assert!((worst_case.upper() - (10.3 - 3.94)).abs() < 1e-9);
+
+    let results = block_on(monte_carlo::run(&state)).unwrap();
+    assert_matches(&results, 6.0, stddev_x);
+    let axes: Vec<Axis> = results.axes.iter().map(|axis| axis.axis).collect();
+    assert_eq!(axes, vec![Axis::X, Axis::Y]);
+    let n = results.iterations as f64;
+    for (axis, mean, stddev) in &[
+        (&results.axes[0], 6.0, stddev_x),
+        (&results.axes[1], 5.0, 0.05),
+    ] {
+        assert!((axis.mean - mean).abs() <= 4.0 * stddev / n.sqrt());
+        for &simulated in &[axis.stddev_pos, axis.stddev_neg] {
+            assert!((simulated - stddev).abs() <= 4.0 * stddev / (2.0 * n).sqrt());
+        }
+        assert!((axis.lower_percentile - (mean - 3.0 * stddev)).abs() < 0.1 * stddev);
+        assert!((axis.upper_percentile - (mean + 3.0 * stddev)).abs() < 0.1 * stddev);
+    }
+}
@@ -1,51 +1,239 @@
+use super::correlation;
+use super::expression::StackEquation;
 use super::structures::*;
+use statrs::distribution::{ContinuousCDF, Normal};
 use std::error::Error;
 
-pub async fn run(state: &State) -> Result<RssResults, Box<dyn Error>> {
-    let mean: f64 = state
-        .tolerance_loop
+pub async fn run(state: &State, method: RssMethod) -> Result<RssResults, Box<dyn Error>> {
+    // Only the mean-shifted method moves the mean of the stack by the mean shift of each dimension.
+    let tolerance_loop: Vec<Tolerance> = match method {
+        RssMethod::MeanShifted => state.tolerance_loop.clone(),
+        _ => state
+            .tolerance_loop
+            .iter()
+            .map(|tol| tol.centered())
+            .collect(),
+    };
+    // A stack expression is linearized about the mean of each contributor, and a vector loop is
+    //  measured along the X axis.
+    let equation = StackEquation::of(state, &tolerance_loop);
+    let tolerance_loop = equation.linearized(&tolerance_loop);
+    // The mean and variance of each contributor are found from the moments of its distribution,
+    //  so skewed and non-normal tolerances give results comparable to the Monte Carlo simulation.
+    let mean: f64 = tolerance_loop
         .iter()
-        .fold(0.0, |acc, tol| acc + tol.distance());
-    let tolerance_neg = state
-        .tolerance_loop
+        .fold(equation.constant, |acc, tol| acc + tol.mean());
+    let variances: Vec<f64> = tolerance_loop
         .iter()
-        .fold(0.0, |acc, tol| {
-            acc + match tol {
-                Tolerance::Linear(linear) => {
-                    (linear.distance.tol_neg / linear.distance.sigma).powi(2)
-                }
-                Tolerance::Float(float) => {
-                    let hole_avg = (float.hole.tol_neg + float.hole.tol_pos) / 2.0;
-                    // Divide by two because the hole dim is diametric
-                    let hole_squared = ((hole_avg / 2.0) / float.pin.sigma).powi(2);
-                    let pin_avg = (float.pin.tol_neg + float.pin.tol_pos) / 2.0;
-                    // Divide by two because the pin dim is diametric
-                    let pin_squared = ((pin_avg / 2.0) / float.pin.sigma).powi(2);
-                    hole_squared + pin_squared
-                }
-            }
+        .zip(&state.tolerance_loop)
+        .map(|(centered, tol)| match method {
+            RssMethod::DynamicRss => Ok(centered.variance() * tol.dynamic_inflation()?.powi(2)),
+            _ => Ok(centered.variance()),
         })
-        .sqrt()
-        * state.parameters.assy_sigma;
-    let tolerance_pos = state
-        .tolerance_loop
-        .iter()
-        .fold(0.0, |acc, tol| {
-            acc + match tol {
-                Tolerance::Linear(linear) => {
-                    (linear.distance.tol_pos / linear.distance.sigma).powi(2)
+        .collect::<Result<_, InvalidMeanShift>>()?;
+    // Correlated contributors add their covariance to the variance of the stack.
+    let stddevs = correlation::signed_stddevs(&tolerance_loop, &variances);
+    let variance =
+        variances.iter().sum::<f64>() + correlation::covariance_of(&stddevs, &state.correlations);
+    let rss_stddev = variance.max(0.0).sqrt();
+    let stddev = match method {
+        RssMethod::Bender => 1.5 * rss_stddev,
+        RssMethod::EstimatedMeanShift => {
+            let factor = state.parameters.mean_shift_factor;
+            let arithmetic: f64 = variances.iter().map(|variance| variance.sqrt()).sum();
+            factor * arithmetic + (1.0 - factor) * rss_stddev
+        }
+        _ => rss_stddev,
+    };
+    let tolerance = stddev * state.parameters.assy_sigma;
+
+    // The RSS stack is assumed to be normally distributed. A stack without any variation has no
+    //  capability to report.
+    let spec = match Normal::new(mean, stddev) {
+        Ok(normal) if state.parameters.has_spec() => {
+            let (lower, upper) = (state.parameters.lower_spec, state.parameters.upper_spec);
+            let fraction_out = lower.map_or(0.0, |lsl| normal.cdf(lsl))
+                + upper.map_or(0.0, |usl| 1.0 - normal.cdf(usl));
+            let spread = StackSpread {
+                mean,
+                stddev_neg: stddev,
+                stddev_pos: stddev,
+                lower_percentile: mean - 3.0 * stddev,
+                median: mean,
+                upper_percentile: mean + 3.0 * stddev,
+            };
+            SpecResults::new(&state.parameters, &spread, fraction_out)
+        }
+        _ => None,
+    };
+
+    Ok(RssResults::new(mean, tolerance, tolerance)
+        .with_method(method)
+        .with_spec(spec))
+}
+
+impl Tolerance {
+    /// How much dynamic RSS widens the spread of this tolerance, so that a centered process keeps
+    /// the Cpk it has with its mean shift. Floats are not given a mean shift. A mean shift that puts
+    /// the process mean outside the tolerance limits has no Cpk to keep, and is an error.
+    pub fn dynamic_inflation(&self) -> Result<f64, InvalidMeanShift> {
+        let inflation = |dim: &DimTol| {
+            let half_band = (dim.tol_pos + dim.tol_neg) / 2.0;
+            if dim.mean_shift == 0.0 {
+                Ok(1.0)
+            } else if dim.is_mean_shift_valid() {
+                Ok(half_band / (half_band - dim.mean_shift.abs()))
+            } else {
+                Err(InvalidMeanShift)
+            }
+        };
+        match self {
+            Tolerance::Linear(linear) => inflation(&linear.distance),
+            Tolerance::Angular(angular) => inflation(&angular.angle),
+            Tolerance::Float(_) | Tolerance::Compound(_) => Ok(1.0),
+        }
+    }
+
+    /// The variance this tolerance adds to the stack.
+    pub fn variance(&self) -> f64 {
+        self.sensitivity().powi(2)
+            * match self {
+                Tolerance::Linear(linear) => linear.distance.stddev().powi(2),
+                Tolerance::Float(float) => float_variance(&float.hole, &float.pin),
+                Tolerance::Compound(compound) => {
+                    let (along, across) = compound.projection();
+                    along.powi(2) * float_variance(&compound.hole, &compound.hole_pin)
+                        + across.powi(2) * float_variance(&compound.slot, &compound.slot_pin)
                 }
-                Tolerance::Float(float) => {
-                    let hole_avg = (float.hole.tol_neg + float.hole.tol_pos) / 2.0;
-                    let hole_squared = ((hole_avg / 2.0) / float.pin.sigma).powi(2);
-                    let pin_avg = (float.pin.tol_neg + float.pin.tol_pos) / 2.0;
-                    let pin_squared = ((pin_avg / 2.0) / float.pin.sigma).powi(2);
-                    hole_squared + pin_squared
+                // Linearized about the mean angle, where the offset changes by the length times
+                //  the cosine of the angle for each radian.
+                Tolerance::Angular(angular) => {
+                    let angle = &angular.angle;
+                    (angular.length * angle.mean().to_radians().cos() * angle.stddev().to_radians())
+                        .powi(2)
                 }
             }
-        })
-        .sqrt()
-        * state.parameters.assy_sigma;
+    }
+}
+
+/// The float is uniformly distributed between +/- the radial clearance, which has a variance of
+/// E[clearance^2]/3. Divide by two because the hole and pin dims are diametric.
+fn float_variance(hole: &DimTol, pin: &DimTol) -> f64 {
+    let clearance_mean = (hole.mean() - pin.mean()) / 2.0;
+    let clearance_variance = (hole.stddev().powi(2) + pin.stddev().powi(2)) / 4.0;
+    if clearance_mean > 0.0 {
+        (clearance_mean.powi(2) + clearance_variance) / 3.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::{linear, normal};
+    use super::*;
+
+    fn shifted(dim: f64, tol: f64, mean_shift: f64) -> Tolerance {
+        Tolerance::Linear(LinearTL::new(
+            normal(dim, tol, tol).with_mean_shift(mean_shift),
+        ))
+    }
+
+    fn rss_with(
+        method: RssMethod,
+        tolerance_loop: Vec<Tolerance>,
+    ) -> Result<RssResults, Box<dyn Error>> {
+        let mut state = State::default();
+        state.parameters.assy_sigma = 3.0;
+        state.parameters.mean_shift_factor = 0.2;
+        state.tolerance_loop = tolerance_loop;
+        async_std::task::block_on(run(&state, method))
+    }
+
+    fn rss(tolerance_loop: Vec<Tolerance>) -> RssResults {
+        rss_with(RssMethod::Classic, tolerance_loop).unwrap()
+    }
+
+    #[test]
+    fn symmetric_stack() {
+        // 10 +/-0.3 and 5 +/-0.4, each at 3 sigma: sqrt(0.3^2 + 0.4^2) = 0.5
+        let results = rss(vec![linear(10.0, 0.3, 0.3), linear(5.0, 0.4, 0.4)]);
+        assert!((results.mean() - 15.0).abs() < 1e-12);
+        assert!((results.tolerance_pos() - 0.5).abs() < 1e-12);
+        assert!((results.tolerance_neg() - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn bender_stack() {
+        // Bender widens the RSS spread by half: 1.5 * 0.5 = 0.75
+        let stack = vec![linear(10.0, 0.3, 0.3), linear(5.0, 0.4, 0.4)];
+        let results = rss_with(RssMethod::Bender, stack).unwrap();
+        assert!((results.mean() - 15.0).abs() < 1e-12);
+        assert!((results.tolerance_pos() - 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn dynamic_rss_stack() {
+        // A 0.1 shift of a +/-0.3 band leaves a 0.2 margin, inflating the spread by 0.3 / 0.2 = 1.5:
+        //  sqrt(0.45^2 + 0.4^2), about the centered mean.
+        let stack = vec![shifted(10.0, 0.3, 0.1), linear(5.0, 0.4, 0.4)];
+        let results = rss_with(RssMethod::DynamicRss, stack).unwrap();
+        let expected = (0.45f64.powi(2) + 0.4f64.powi(2)).sqrt();
+        assert!((results.mean() - 15.0).abs() < 1e-12);
+        assert!((results.tolerance_pos() - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn dynamic_rss_rejects_mean_shift_outside_band() {
+        let stack = vec![shifted(10.0, 0.3, 0.3), linear(5.0, 0.4, 0.4)];
+        assert!(rss_with(RssMethod::DynamicRss, stack).is_err());
+    }
+
+    #[test]
+    fn estimated_mean_shift_stack() {
+        // A fifth of the arithmetic sum plus the rest of the RSS: 0.2 * 0.7 + 0.8 * 0.5 = 0.54
+        let stack = vec![linear(10.0, 0.3, 0.3), linear(5.0, 0.4, 0.4)];
+        let results = rss_with(RssMethod::EstimatedMeanShift, stack).unwrap();
+        assert!((results.mean() - 15.0).abs() < 1e-12);
+        assert!((results.tolerance_pos() - 0.54).abs() < 1e-12);
+    }
+
+    #[test]
+    fn compound_float() {
+        // Each float is uniform over +/- the radial clearance, with a variance of E[c^2] / 3. The
+        //  round hole is 6.05 +/-0.05 on a pin of 5.85 +/-0.05, a mean clearance of 0.1, and the
+        //  slot is 6.15 +/-0.15 on the same pin, a mean clearance of 0.15. At 60°, a quarter of the
+        //  round hole variance and three quarters of the slot variance act along the stack.
+        let compound = Tolerance::Compound(CompoundFloatTL::new(
+            normal(6.0, 0.1, 0.0),
+            normal(5.9, 0.0, 0.1),
+            normal(6.0, 0.3, 0.0),
+            normal(5.9, 0.0, 0.1),
+            60.0,
+        ));
+        let results = rss(vec![compound]);
+        let float = |clearance: f64, hole_stddev: f64, pin_stddev: f64| {
+            (clearance.powi(2) + (hole_stddev.powi(2) + pin_stddev.powi(2)) / 4.0) / 3.0
+        };
+        let variance =
+            0.25 * float(0.1, 0.05 / 3.0, 0.05 / 3.0) + 0.75 * float(0.15, 0.05, 0.05 / 3.0);
+        assert!(results.mean().abs() < 1e-12);
+        assert!((results.tolerance_pos() - 3.0 * variance.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn asymmetric_stack() {
+        // 10 +0.5/-0.1 is 10.2 +/-0.3, and 5 +0.1/-0.7 is 4.7 +/-0.4
+        let results = rss(vec![linear(10.0, 0.5, 0.1), linear(5.0, 0.1, 0.7)]);
+        assert!((results.mean() - 14.9).abs() < 1e-12);
+        assert!((results.tolerance_pos() - 0.5).abs() < 1e-12);
+    }
 
-    Ok(RssResults::new(mean, tolerance_pos, tolerance_neg))
+    #[test]
+    fn unilateral_stack() {
+        // 10 +0.6/-0 is 10.3 +/-0.3, and 5 +0/-0.8 is 4.6 +/-0.4
+        let results = rss(vec![linear(10.0, 0.6, 0.0), linear(5.0, 0.0, 0.8)]);
+        assert!((results.mean() - 14.9).abs() < 1e-12);
+        assert!((results.tolerance_pos() - 0.5).abs() < 1e-12);
+    }
 }
@@ -1,5 +1,36 @@
 /// Contains structures used to define tolerances in a tolerance loop.
+use super::cost::{ContributorCost, CostModel};
+use super::expression::Expression;
 use serde_derive::*;
+use statrs::distribution::{ContinuousCDF, Normal};
+use statrs::function::{erf::erfc, gamma::gamma};
+
+/// Error returned when a dimension is given a negative tolerance, or a sigma that is not positive.
+#[derive(Debug, Clone, Copy)]
+pub struct InvalidTolerance;
+impl std::fmt::Display for InvalidTolerance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Tolerances must not be negative, and sigma must be positive"
+        )
+    }
+}
+impl std::error::Error for InvalidTolerance {}
+
+/// Error returned when a dimension is given a mean shift that puts the process mean on or outside
+/// the tolerance limits.
+#[derive(Debug, Clone, Copy)]
+pub struct InvalidMeanShift;
+impl std::fmt::Display for InvalidMeanShift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The mean shift must be less than half of the tolerance band"
+        )
+    }
+}
+impl std::error::Error for InvalidMeanShift {}
 
 #[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
 pub struct DimTol {
@@ -8,39 +39,270 @@ pub struct DimTol {
     pub tol_neg: f64,
     pub tol_multiplier: f64,
     pub sigma: f64,
-    dist: TolDistribution,
+    #[serde(default)]
+    pub dist: TolDistribution,
+    /// Offset of the process mean from the center of the tolerance band, for suppliers that do
+    /// not run centered
+    #[serde(default)]
+    pub mean_shift: f64,
 }
 impl DimTol {
-    pub fn new_normal(dim: f64, tol_pos: f64, tol_neg: f64, sigma: f64) -> Self {
-        if tol_neg <= 0.0 || tol_pos <= 0.0 {
-            panic!("Number supplied to new_normal was negative");
-        }
-        let tol_multiplier: f64 = (tol_pos + tol_neg) / 2.0 / sigma;
-        // If the tolerances are not equal for a normally distributed tolerance, the tolerance must be normalized.
-        let (dim_norm, tol_pos_norm, tol_neg_norm) = if (tol_pos - tol_neg) > f64::EPSILON {
-            let new_dim = (tol_pos + tol_neg) / 2.0;
-            (dim + (tol_pos + tol_neg) / 2.0, new_dim, new_dim)
-        } else {
-            (dim, tol_pos, tol_neg)
-        };
-        DimTol {
-            dim: dim_norm,
-            tol_pos: tol_pos_norm,
-            tol_neg: tol_neg_norm,
-            tol_multiplier,
+    /// A normally distributed dimension, where the tolerance band spans +/- `sigma` standard
+    /// deviations. Asymmetric tolerances are re-centered, so `dim` becomes the center of the band
+    /// and both tolerances become half of its width. A tolerance may be zero, as in a unilateral
+    /// tolerance, but not negative.
+    pub fn new_normal(
+        dim: f64,
+        tol_pos: f64,
+        tol_neg: f64,
+        sigma: f64,
+    ) -> Result<Self, InvalidTolerance> {
+        // Negated comparisons also reject NaN.
+        if !(tol_pos >= 0.0 && tol_neg >= 0.0 && sigma > 0.0 && dim.is_finite()) {
+            return Err(InvalidTolerance);
+        }
+        let half_band = (tol_pos + tol_neg) / 2.0;
+        Ok(DimTol {
+            dim: dim + (tol_pos - tol_neg) / 2.0,
+            tol_pos: half_band,
+            tol_neg: half_band,
+            tol_multiplier: half_band / sigma,
             sigma,
             dist: TolDistribution::Normal,
+            mean_shift: 0.0,
+        })
+    }
+    /// Sets the distribution used to model the variation of this dimension.
+    pub fn with_distribution(mut self, dist: TolDistribution) -> Self {
+        self.dist = dist;
+        self
+    }
+    /// Offsets the process mean from the center of the tolerance band.
+    pub fn with_mean_shift(mut self, mean_shift: f64) -> Self {
+        self.mean_shift = mean_shift;
+        self
+    }
+    /// Whether the process mean is inside the tolerance limits. A centered process always is, even
+    /// if the dimension has no tolerance.
+    pub fn is_mean_shift_valid(&self) -> bool {
+        self.mean_shift == 0.0 || self.mean_shift.abs() < (self.tol_pos + self.tol_neg) / 2.0
+    }
+    /// Derives the spread of the dimension from a process capability index, which is the distance
+    /// from the process mean to the nearest tolerance limit in units of three standard deviations.
+    /// Any mean shift must be set first, as it moves the mean closer to a limit. A Cpk that is not
+    /// positive leaves the spread as it is.
+    pub fn with_cpk(mut self, cpk: f64) -> Result<Self, InvalidMeanShift> {
+        let half_band = (self.tol_pos + self.tol_neg) / 2.0;
+        let margin = half_band - self.mean_shift.abs();
+        if margin.is_nan() || margin <= 0.0 {
+            return Err(InvalidMeanShift);
+        }
+        if cpk > 0.0 {
+            self.sigma = 3.0 * cpk * half_band / margin;
+            self.tol_multiplier = half_band / self.sigma;
+        }
+        Ok(self)
+    }
+    /// The same dimension, with its process centered in the tolerance band.
+    pub fn centered(&self) -> Self {
+        self.with_mean_shift(0.0)
+    }
+    /// Offset of the center of the tolerance band from the nominal dimension
+    pub fn band_center(&self) -> f64 {
+        (self.tol_pos - self.tol_neg) / 2.0
+    }
+    /// The mean and standard deviation of a normal distribution truncated at the tolerance limits,
+    /// as offsets from the nominal dimension.
+    fn truncated_normal_moments(&self) -> (f64, f64) {
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let center = self.band_center() + self.mean_shift;
+        let scale = self.tol_multiplier;
+        // A dimension without any tolerance does not vary.
+        if scale <= 0.0 {
+            return (center.clamp(-self.tol_neg, self.tol_pos), 0.0);
+        }
+        let lower = (-self.tol_neg - center) / scale;
+        let upper = (self.tol_pos - center) / scale;
+        let pdf = |z: f64| (-z * z / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt();
+        let mass = normal.cdf(upper) - normal.cdf(lower);
+        let offset = (pdf(lower) - pdf(upper)) / mass;
+        let variance = 1.0 + (lower * pdf(lower) - upper * pdf(upper)) / mass - offset * offset;
+        (center + scale * offset, scale * variance.max(0.0).sqrt())
+    }
+    /// The mean of the dimension, given its distribution. Skewed distributions are not centered
+    /// in the tolerance band, so their mean is offset from `dim`.
+    pub fn mean(&self) -> f64 {
+        self.dim
+            + match self.dist {
+                TolDistribution::Normal
+                | TolDistribution::Uniform
+                | TolDistribution::Triangular => self.band_center() + self.mean_shift,
+                // The truncation limits stay put as the mean shifts.
+                TolDistribution::TruncatedNormal => self.truncated_normal_moments().0,
+                TolDistribution::LogNormal => {
+                    let (mu, shape) = self.lognormal_params();
+                    self.mean_shift - self.tol_neg + (mu + shape * shape / 2.0).exp()
+                }
+                TolDistribution::Weibull => {
+                    let (scale, shape) = self.weibull_params();
+                    self.mean_shift - self.tol_neg + scale * gamma(1.0 + 1.0 / shape)
+                }
+                TolDistribution::Rayleigh => {
+                    self.mean_shift - self.tol_neg
+                        + self.rayleigh_scale() * (std::f64::consts::PI / 2.0).sqrt()
+                }
+                TolDistribution::FoldedNormal => {
+                    self.mean_shift - self.tol_neg
+                        + self.folded_normal_scale() * (2.0 / std::f64::consts::PI).sqrt()
+                }
+            }
+    }
+    /// The standard deviation of the dimension, given its distribution.
+    pub fn stddev(&self) -> f64 {
+        let band = self.tol_pos + self.tol_neg;
+        match self.dist {
+            TolDistribution::Normal => self.tol_multiplier,
+            TolDistribution::Uniform => band / 12f64.sqrt(),
+            TolDistribution::Triangular => band / 24f64.sqrt(),
+            TolDistribution::TruncatedNormal => self.truncated_normal_moments().1,
+            TolDistribution::LogNormal => {
+                let (mu, shape) = self.lognormal_params();
+                ((shape * shape).exp_m1() * (2.0 * mu + shape * shape).exp()).sqrt()
+            }
+            TolDistribution::Weibull => {
+                let (scale, shape) = self.weibull_params();
+                scale * (gamma(1.0 + 2.0 / shape) - gamma(1.0 + 1.0 / shape).powi(2)).sqrt()
+            }
+            TolDistribution::Rayleigh => {
+                self.rayleigh_scale() * ((4.0 - std::f64::consts::PI) / 2.0).sqrt()
+            }
+            TolDistribution::FoldedNormal => {
+                self.folded_normal_scale() * (1.0 - 2.0 / std::f64::consts::PI).sqrt()
+            }
         }
     }
+
+    // The skewed distributions are bounded below by the lower tolerance limit, and are scaled so
+    //  that the upper tolerance limit is exceeded as often as a normal distribution exceeds
+    //  `sigma` standard deviations. The parameters below are relative to the lower limit.
+
+    /// Probability of a sample exceeding the upper limit of a skewed distribution.
+    fn upper_tail_probability(&self) -> f64 {
+        erfc(self.sigma / std::f64::consts::SQRT_2) / 2.0
+    }
+    /// Returns `(mu, shape)` of a log-normal distribution with its median in the middle of the
+    /// tolerance band.
+    pub fn lognormal_params(&self) -> (f64, f64) {
+        let band = self.tol_pos + self.tol_neg;
+        ((band / 2.0).ln(), std::f64::consts::LN_2 / self.sigma)
+    }
+    /// Returns `(scale, shape)` of a Weibull distribution with its median in the middle of the
+    /// tolerance band.
+    pub fn weibull_params(&self) -> (f64, f64) {
+        let band = self.tol_pos + self.tol_neg;
+        let tail = -self.upper_tail_probability().ln();
+        let shape = (tail / std::f64::consts::LN_2).ln() / std::f64::consts::LN_2;
+        (band / tail.powf(1.0 / shape), shape)
+    }
+    /// Returns the scale parameter of a Rayleigh distribution.
+    pub fn rayleigh_scale(&self) -> f64 {
+        let band = self.tol_pos + self.tol_neg;
+        band / (-2.0 * self.upper_tail_probability().ln()).sqrt()
+    }
+    /// Returns the scale parameter of a normal distribution centered on the lower limit and
+    /// folded about it.
+    pub fn folded_normal_scale(&self) -> f64 {
+        let band = self.tol_pos + self.tol_neg;
+        let folded_tail = 1.0 - self.upper_tail_probability() / 2.0;
+        band / Normal::new(0.0, 1.0).unwrap().inverse_cdf(folded_tail)
+    }
 }
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+/// The statistical distribution used to sample a dimension in a Monte Carlo simulation.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub enum TolDistribution {
+    /// Normally distributed about the center of the tolerance band, where the band spans
+    /// +/- `sigma` standard deviations.
+    #[default]
     Normal,
+    /// Equally likely anywhere within the tolerance band.
+    Uniform,
+    /// Symmetric triangular distribution, peaking at the center of the tolerance band.
+    Triangular,
+    /// Normal distribution with samples outside of the tolerance band discarded.
+    TruncatedNormal,
+    /// Skewed distribution bounded by the lower limit, with its median in the middle of the band.
+    LogNormal,
+    /// Skewed distribution bounded by the lower limit, with its median in the middle of the band.
+    Weibull,
+    /// Skewed distribution bounded by the lower limit, typical of radial position error.
+    Rayleigh,
+    /// Normal distribution centered on the lower limit and folded about it, typical of form
+    /// tolerances such as flatness and runout.
+    FoldedNormal,
 }
-impl Default for TolDistribution {
-    fn default() -> Self {
-        TolDistribution::Normal
+impl TolDistribution {
+    pub const ALL: [TolDistribution; 8] = [
+        TolDistribution::Normal,
+        TolDistribution::Uniform,
+        TolDistribution::Triangular,
+        TolDistribution::TruncatedNormal,
+        TolDistribution::LogNormal,
+        TolDistribution::Weibull,
+        TolDistribution::Rayleigh,
+        TolDistribution::FoldedNormal,
+    ];
+}
+impl std::fmt::Display for TolDistribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TolDistribution::Normal => "Normal",
+                TolDistribution::Uniform => "Uniform",
+                TolDistribution::Triangular => "Triangular",
+                TolDistribution::TruncatedNormal => "Truncated Normal",
+                TolDistribution::LogNormal => "Log-Normal",
+                TolDistribution::Weibull => "Weibull",
+                TolDistribution::Rayleigh => "Rayleigh",
+                TolDistribution::FoldedNormal => "Folded Normal",
+            }
+        )
+    }
+}
+
+/// How the Monte Carlo simulation chooses the points at which each contributor is sampled.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum SamplingStrategy {
+    /// Independent pseudo-random samples.
+    #[default]
+    Random,
+    /// Each chunk of iterations is split into equally likely strata for every contributor, and
+    /// every stratum is sampled exactly once.
+    LatinHypercube,
+    /// A randomly scrambled and shifted Halton sequence, which fills the space of possible
+    /// samples more evenly than random sampling.
+    Halton,
+}
+impl SamplingStrategy {
+    pub const ALL: [SamplingStrategy; 3] = [
+        SamplingStrategy::Random,
+        SamplingStrategy::LatinHypercube,
+        SamplingStrategy::Halton,
+    ];
+}
+impl std::fmt::Display for SamplingStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                SamplingStrategy::Random => "Random",
+                SamplingStrategy::LatinHypercube => "Latin Hypercube",
+                SamplingStrategy::Halton => "Halton",
+            }
+        )
     }
 }
 
@@ -48,7 +310,8 @@ impl Default for TolDistribution {
 pub enum Tolerance {
     Linear(LinearTL),
     Float(FloatTL),
-    //Compound(CompoundFloatTL),
+    Compound(CompoundFloatTL),
+    Angular(AngularTL),
 }
 impl Default for Tolerance {
     fn default() -> Self {
@@ -56,92 +319,570 @@ impl Default for Tolerance {
     }
 }
 impl Tolerance {
-    pub fn distance(&self) -> f64 {
+    /// The expected contribution of this tolerance to the stack, accounting for its distribution.
+    pub fn mean(&self) -> f64 {
+        self.sensitivity()
+            * match self {
+                Tolerance::Linear(linear) => linear.distance.mean(),
+                Tolerance::Float(_) => 0f64,
+                Tolerance::Compound(_) => 0f64,
+                // Linearized about the mean angle
+                Tolerance::Angular(angular) => angular.offset(angular.angle.mean()),
+            }
+    }
+    /// How far the stack moves for each unit this tolerance moves, such as the ratio of a lever.
+    pub fn sensitivity(&self) -> f64 {
         match self {
-            Tolerance::Linear(linear) => linear.distance.dim,
-            Tolerance::Float(_) => 0f64,
+            Tolerance::Linear(linear) => linear.sensitivity,
+            Tolerance::Float(float) => float.sensitivity,
+            Tolerance::Compound(compound) => compound.sensitivity,
+            Tolerance::Angular(angular) => angular.sensitivity,
         }
     }
+    /// The same tolerance, scaled by a different sensitivity coefficient.
+    pub fn with_sensitivity(mut self, sensitivity: f64) -> Self {
+        match &mut self {
+            Tolerance::Linear(linear) => linear.sensitivity = sensitivity,
+            Tolerance::Float(float) => float.sensitivity = sensitivity,
+            Tolerance::Compound(compound) => compound.sensitivity = sensitivity,
+            Tolerance::Angular(angular) => angular.sensitivity = sensitivity,
+        }
+        self
+    }
+    /// The direction the tolerance acts in when the loop is a vector loop, in degrees
+    /// counterclockwise from the X axis.
+    pub fn direction(&self) -> f64 {
+        match self {
+            Tolerance::Linear(linear) => linear.direction,
+            Tolerance::Float(float) => float.direction,
+            Tolerance::Compound(compound) => compound.direction,
+            Tolerance::Angular(angular) => angular.direction,
+        }
+    }
+    /// The same tolerance, acting in a different direction.
+    pub fn with_direction(mut self, direction: f64) -> Self {
+        match &mut self {
+            Tolerance::Linear(linear) => linear.direction = direction,
+            Tolerance::Float(float) => float.direction = direction,
+            Tolerance::Compound(compound) => compound.direction = direction,
+            Tolerance::Angular(angular) => angular.direction = direction,
+        }
+        self
+    }
 }
 
-#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+impl Tolerance {
+    /// The same tolerance, with the process of every dimension centered in its tolerance band.
+    pub fn centered(&self) -> Self {
+        match self {
+            Tolerance::Linear(linear) => {
+                Tolerance::Linear(LinearTL::new(linear.distance.centered()))
+            }
+            Tolerance::Float(float) => Tolerance::Float(FloatTL::new(
+                float.hole.centered(),
+                float.pin.centered(),
+                float.sigma,
+            )),
+            Tolerance::Compound(compound) => Tolerance::Compound(CompoundFloatTL::new(
+                compound.hole.centered(),
+                compound.hole_pin.centered(),
+                compound.slot.centered(),
+                compound.slot_pin.centered(),
+                compound.slot_angle,
+            )),
+            Tolerance::Angular(angular) => {
+                Tolerance::Angular(AngularTL::new(angular.angle.centered(), angular.length))
+            }
+        }
+        .with_sensitivity(self.sensitivity())
+        .with_direction(self.direction())
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct LinearTL {
     pub distance: DimTol,
+    #[serde(default = "default_sensitivity")]
+    pub sensitivity: f64,
+    #[serde(default)]
+    pub direction: f64,
 }
 impl LinearTL {
     pub fn new(distance: DimTol) -> Self {
-        LinearTL { distance }
+        LinearTL {
+            distance,
+            sensitivity: 1.0,
+            direction: 0.0,
+        }
+    }
+}
+impl Default for LinearTL {
+    fn default() -> Self {
+        LinearTL::new(DimTol::default())
     }
 }
 
-#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct FloatTL {
     pub hole: DimTol,
     pub pin: DimTol,
     pub sigma: f64,
+    #[serde(default = "default_sensitivity")]
+    pub sensitivity: f64,
+    #[serde(default)]
+    pub direction: f64,
 }
 impl FloatTL {
     pub fn new(hole: DimTol, pin: DimTol, sigma: f64) -> Self {
-        FloatTL { hole, pin, sigma }
+        FloatTL {
+            hole,
+            pin,
+            sigma,
+            sensitivity: 1.0,
+            direction: 0.0,
+        }
+    }
+}
+impl Default for FloatTL {
+    fn default() -> Self {
+        FloatTL::new(DimTol::default(), DimTol::default(), 0.0)
+    }
+}
+
+fn default_sensitivity() -> f64 {
+    1.0
+}
+
+/// A part located by a pair of pins, one in a round hole and one in a slot. The slot runs along
+/// the line between the pins, so it absorbs any error in their spacing. The round hole locates the
+/// part along the slot, and the slot clearance locates it across the slot.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct CompoundFloatTL {
+    pub hole: DimTol,
+    pub hole_pin: DimTol,
+    /// Width of the slot
+    pub slot: DimTol,
+    pub slot_pin: DimTol,
+    /// Angle between the long axis of the slot and the direction of the stack, in degrees. At 0 the
+    ///  float along the stack is set by the round hole, and at 90 by the width of the slot.
+    pub slot_angle: f64,
+    #[serde(default = "default_sensitivity")]
+    pub sensitivity: f64,
+    #[serde(default)]
+    pub direction: f64,
+}
+impl CompoundFloatTL {
+    pub fn new(
+        hole: DimTol,
+        hole_pin: DimTol,
+        slot: DimTol,
+        slot_pin: DimTol,
+        slot_angle: f64,
+    ) -> Self {
+        CompoundFloatTL {
+            hole,
+            hole_pin,
+            slot,
+            slot_pin,
+            slot_angle,
+            sensitivity: 1.0,
+            direction: 0.0,
+        }
+    }
+
+    /// The share of the round hole float and the slot float that act along the stack.
+    pub fn projection(&self) -> (f64, f64) {
+        let angle = self.slot_angle.to_radians();
+        (angle.cos().abs(), angle.sin().abs())
+    }
+}
+impl Default for CompoundFloatTL {
+    fn default() -> Self {
+        let dim = DimTol::default();
+        CompoundFloatTL::new(dim, dim, dim, dim, 0.0)
+    }
+}
+
+/// A feature of some length that is tilted by an angle, such as a part leaning against a datum.
+/// The end of the feature moves the stack by `length * sin(angle)`, where the angle is in degrees.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct AngularTL {
+    pub angle: DimTol,
+    pub length: f64,
+    #[serde(default = "default_sensitivity")]
+    pub sensitivity: f64,
+    #[serde(default)]
+    pub direction: f64,
+}
+impl AngularTL {
+    pub fn new(angle: DimTol, length: f64) -> Self {
+        AngularTL {
+            angle,
+            length,
+            sensitivity: 1.0,
+            direction: 0.0,
+        }
+    }
+
+    /// How far the end of the feature moves the stack at an angle, in degrees
+    pub fn offset(&self, angle: f64) -> f64 {
+        self.length * angle.to_radians().sin()
+    }
+}
+impl Default for AngularTL {
+    fn default() -> Self {
+        AngularTL::new(DimTol::default(), 0.0)
+    }
+}
+
+/// An axis of the plane a vector loop lies in. The stack is measured along the X axis.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Axis {
+    #[default]
+    X,
+    Y,
+}
+impl Axis {
+    /// The component along this axis of a unit vector, `direction` degrees counterclockwise from
+    /// the X axis
+    pub fn component(&self, direction: f64) -> f64 {
+        let (sin, cos) = direction.to_radians().sin_cos();
+        match self {
+            Axis::X => cos,
+            Axis::Y => sin,
+        }
+    }
+}
+impl std::fmt::Display for Axis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Axis::X => "X",
+                Axis::Y => "Y",
+            }
+        )
     }
 }
 
+/// A correlation between the variation of two contributors, such as parts cut in the same fixture
+/// or from the same lot of material. The contributors are identified by their position.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Correlation {
+    pub first: usize,
+    pub second: usize,
+    /// Correlation coefficient, from -1 to 1
+    pub coefficient: f64,
+}
+
 /// Structure used to hold simulation input parameters
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Parameters {
     pub assy_sigma: f64,
     pub n_iterations: usize,
+    /// Run the Monte Carlo simulation until the standard errors of the mean and tolerance are
+    /// below `convergence_threshold`, or `max_iterations` is reached, instead of `n_iterations`
+    pub converge: bool,
+    pub convergence_threshold: f64,
+    pub max_iterations: usize,
+    /// How the points sampled in the Monte Carlo simulation are chosen. Confidence intervals assume
+    /// random sampling, so they are conservative for the other strategies.
+    pub sampling: SamplingStrategy,
+    /// Seed for the random number generator, so a Monte Carlo simulation can be reproduced
+    pub seed: u64,
+    /// Lower specification limit of the stack, if any
+    pub lower_spec: Option<f64>,
+    /// Upper specification limit of the stack, if any
+    pub upper_spec: Option<f64>,
+    /// Minimum capability index the stack must achieve to pass
+    pub target_capability: f64,
+    /// The RSS methods to report, in the order of `RssMethod::ALL`
+    pub rss_methods: Vec<RssMethod>,
+    /// Fraction of each tolerance that is added arithmetically by the estimated mean shift method
+    pub mean_shift_factor: f64,
+    /// Estimate the fraction out of spec by importance sampling the tails of the stack, instead of
+    /// counting the simulated assemblies outside of the spec limits
+    pub importance_sampling: bool,
+    /// Treat the loop as a vector loop in a plane, where each tolerance acts along its direction.
+    /// The stack is the X component of the vector that closes the loop.
+    pub vector_loop: bool,
+}
+impl Default for Parameters {
+    fn default() -> Self {
+        Parameters {
+            assy_sigma: 4.0,
+            n_iterations: 1_000_000,
+            converge: false,
+            convergence_threshold: 0.001,
+            max_iterations: 10_000_000,
+            sampling: SamplingStrategy::default(),
+            seed: 0,
+            lower_spec: None,
+            upper_spec: None,
+            target_capability: 1.33,
+            rss_methods: vec![RssMethod::Classic, RssMethod::MeanShifted],
+            mean_shift_factor: 0.2,
+            importance_sampling: false,
+            vector_loop: false,
+        }
+    }
+}
+impl Parameters {
+    pub fn has_spec(&self) -> bool {
+        self.lower_spec.is_some() || self.upper_spec.is_some()
+    }
+    /// The most iterations the Monte Carlo simulation runs. When running until converged, the
+    /// number of iterations is only an upper limit.
+    pub fn iterations(&self) -> usize {
+        if self.converge {
+            self.max_iterations
+        } else {
+            self.n_iterations
+        }
+    }
+}
+
+/// The variant of the RSS method used to estimate the spread of a stack.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum RssMethod {
+    /// Assumes every process is centered in its tolerance band.
+    #[default]
+    Classic,
+    /// Moves the mean of the stack by the mean shift of each dimension.
+    MeanShifted,
+    /// Inflates the classic standard deviation by 1.5, to allow for processes that drift.
+    Bender,
+    /// Motorola's dynamic RSS, which widens the spread of each dimension by its mean shift
+    /// instead of moving the mean, so every dimension keeps its Cpk.
+    DynamicRss,
+    /// Greenwood and Chase's estimated mean shift, which adds a fraction of each tolerance
+    /// arithmetically, as in a worst case, and the rest by RSS.
+    EstimatedMeanShift,
+}
+impl RssMethod {
+    pub const ALL: [RssMethod; 5] = [
+        RssMethod::Classic,
+        RssMethod::MeanShifted,
+        RssMethod::Bender,
+        RssMethod::DynamicRss,
+        RssMethod::EstimatedMeanShift,
+    ];
+}
+impl std::fmt::Display for RssMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                RssMethod::Classic => "RSS",
+                RssMethod::MeanShifted => "Mean-Shifted RSS",
+                RssMethod::Bender => "Bender",
+                RssMethod::DynamicRss => "Dynamic RSS",
+                RssMethod::EstimatedMeanShift => "Estimated Mean Shift",
+            }
+        )
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AnalysisResults {
     monte_carlo: Option<McResults>,
-    rss: Option<RssResults>,
+    /// The results of each selected RSS method
+    rss: Vec<RssResults>,
+    worst_case: Option<WorstCaseResults>,
+    contributions: Vec<Contribution>,
+    /// The cost of each contributor with a cost model, in tolerance loop order
+    #[serde(default)]
+    costs: Vec<Option<ContributorCost>>,
+    /// Why the Monte Carlo simulation could not be run, if it failed
+    #[serde(default)]
+    monte_carlo_error: Option<String>,
 }
 impl AnalysisResults {
     pub fn monte_carlo(&self) -> &Option<McResults> {
         &self.monte_carlo
     }
-    pub fn rss(&self) -> &Option<RssResults> {
+    pub fn rss(&self) -> &[RssResults] {
         &self.rss
     }
-    pub fn export(&self) -> Vec<f64> {
+    pub fn worst_case(&self) -> &Option<WorstCaseResults> {
+        &self.worst_case
+    }
+    /// Contributors ranked from largest to smallest share of the stack variance
+    pub fn contributions(&self) -> &[Contribution] {
+        &self.contributions
+    }
+    pub fn with_monte_carlo(mut self, monte_carlo: Option<McResults>) -> Self {
+        self.monte_carlo = monte_carlo;
+        self
+    }
+    pub fn monte_carlo_error(&self) -> Option<&str> {
+        self.monte_carlo_error.as_deref()
+    }
+    pub fn with_monte_carlo_error(mut self, error: Option<String>) -> Self {
+        self.monte_carlo_error = error;
+        self
+    }
+    pub fn with_rss(mut self, rss: Vec<RssResults>) -> Self {
+        self.rss = rss;
+        self
+    }
+    pub fn with_worst_case(mut self, worst_case: Option<WorstCaseResults>) -> Self {
+        self.worst_case = worst_case;
+        self
+    }
+    pub fn with_contributions(mut self, contributions: Vec<Contribution>) -> Self {
+        self.contributions = contributions;
+        self
+    }
+    /// The cost of the contributor at `index` in the tolerance loop, if it has a cost model
+    pub fn cost(&self, index: usize) -> Option<&ContributorCost> {
+        self.costs.get(index).and_then(Option::as_ref)
+    }
+    /// The total cost of the contributors with a cost model, if any have one
+    pub fn total_cost(&self) -> Option<f64> {
+        self.costs
+            .iter()
+            .flatten()
+            .map(|cost| cost.cost)
+            .fold(None, |total, cost| Some(total.unwrap_or(0.0) + cost))
+    }
+    pub fn with_costs(mut self, costs: Vec<Option<ContributorCost>>) -> Self {
+        self.costs = costs;
+        self
+    }
+    /// Labelled figures for exporting to a report, with the confidence intervals of the Monte
+    /// Carlo results.
+    pub fn export(&self) -> Vec<ExportRow> {
+        // Each analysis is exported on its own, so the results of the analyses that were run are
+        //  exported even when others were disabled or failed.
+        let mut result = Vec::new();
         if let Some(mc_result) = &self.monte_carlo {
-            if let Some(rss_result) = &self.rss {
-                let mut result = Vec::new();
-                result.push(mc_result.mean);
-                result.push(mc_result.tolerance_pos);
-                result.push(mc_result.tolerance_neg);
-                result.push(mc_result.stddev_pos);
-                result.push(mc_result.stddev_neg);
-                result.push(mc_result.worst_case_lower);
-                result.push(mc_result.worst_case_upper);
-                result.push(rss_result.mean);
-                result.push(rss_result.tolerance_pos);
-                result.push(rss_result.tolerance_neg);
-                return result;
-            }
-        }
-        // If no result is generated, return an empty vec
-        Vec::new()
-    }
-}
-impl From<(McResults, RssResults)> for AnalysisResults {
-    fn from(results: (McResults, RssResults)) -> Self {
-        let (monte_carlo, rss) = results;
-        AnalysisResults {
-            monte_carlo: Some(monte_carlo),
-            rss: Some(rss),
+            result.extend(vec![
+                ExportRow::new("MC Mean", mc_result.mean).with_interval(mc_result.mean_interval),
+                ExportRow::new("MC Tolerance (+)", mc_result.tolerance_pos)
+                    .with_interval(mc_result.tolerance_pos_interval),
+                ExportRow::new("MC Tolerance (-)", mc_result.tolerance_neg)
+                    .with_interval(mc_result.tolerance_neg_interval),
+                ExportRow::new("MC Standard Deviation (+)", mc_result.stddev_pos)
+                    .with_interval(mc_result.stddev_pos_interval),
+                ExportRow::new("MC Standard Deviation (-)", mc_result.stddev_neg)
+                    .with_interval(mc_result.stddev_neg_interval),
+                ExportRow::new("MC Iterations", mc_result.iterations as f64),
+            ]);
+            let percentiles = &mc_result.percentiles;
+            let intervals = &mc_result.percentile_intervals;
+            for (label, value, interval) in &[
+                ("0.135", percentiles.p0_135, intervals.p0_135),
+                ("2.5", percentiles.p2_5, intervals.p2_5),
+                ("50", percentiles.p50, intervals.p50),
+                ("97.5", percentiles.p97_5, intervals.p97_5),
+                ("99.865", percentiles.p99_865, intervals.p99_865),
+            ] {
+                result.push(
+                    ExportRow::new(&format!("MC {}% Percentile", label), *value)
+                        .with_interval(*interval),
+                );
+            }
+            result.push(
+                ExportRow::new("MC Skewness", mc_result.skewness)
+                    .with_interval(mc_result.skewness_interval),
+            );
+            result.push(
+                ExportRow::new("MC Excess Kurtosis", mc_result.kurtosis)
+                    .with_interval(mc_result.kurtosis_interval),
+            );
+            if let Some(spec) = &mc_result.spec {
+                let mut row = ExportRow::new("MC Out of Spec (PPM)", spec.ppm());
+                if let Some(interval) = spec.fraction_out_interval() {
+                    row = row.with_interval(interval.scale(1e6));
+                }
+                result.push(row);
+                if let Some(cp) = spec.cp() {
+                    let mut row = ExportRow::new("MC Cp", cp);
+                    if let Some(interval) = spec.cp_interval() {
+                        row = row.with_interval(interval);
+                    }
+                    result.push(row);
+                }
+                let mut row = ExportRow::new("MC Cpk", spec.cpk());
+                if let Some(interval) = spec.cpk_interval() {
+                    row = row.with_interval(interval);
+                }
+                result.push(row);
+            }
+        }
+        if let Some(wc_result) = &self.worst_case {
+            result.push(ExportRow::new("Worst Case Lower", wc_result.lower()));
+            result.push(ExportRow::new("Worst Case Upper", wc_result.upper()));
+            for (index, bounds) in wc_result.contributors().iter().enumerate() {
+                let contributor = format!("Worst Case Contributor {}", index + 1);
+                result.push(ExportRow::new(&format!("{} Min", contributor), bounds.min));
+                result.push(ExportRow::new(&format!("{} Max", contributor), bounds.max));
+            }
+        }
+        for rss_result in &self.rss {
+            let method = rss_result.method;
+            result.push(ExportRow::new(&format!("{} Mean", method), rss_result.mean));
+            result.push(ExportRow::new(
+                &format!("{} Tolerance (+)", method),
+                rss_result.tolerance_pos,
+            ));
+            result.push(ExportRow::new(
+                &format!("{} Tolerance (-)", method),
+                rss_result.tolerance_neg,
+            ));
+            if let Some(spec) = &rss_result.spec {
+                result.push(ExportRow::new(
+                    &format!("{} Out of Spec (PPM)", method),
+                    spec.ppm(),
+                ));
+                result.push(ExportRow::new(&format!("{} Cpk", method), spec.cpk()));
+            }
         }
+        result
     }
 }
 impl Default for AnalysisResults {
     fn default() -> Self {
         AnalysisResults {
             monte_carlo: None,
-            rss: None,
+            rss: Vec::new(),
+            worst_case: None,
+            contributions: Vec::new(),
+            costs: Vec::new(),
+            monte_carlo_error: None,
+        }
+    }
+}
+
+/// A single figure in an exported report, with its 95% confidence interval if it has one
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportRow {
+    #[serde(rename = "Statistic")]
+    name: String,
+    #[serde(rename = "Value")]
+    value: f64,
+    #[serde(rename = "Lower 95% CI")]
+    lower: Option<f64>,
+    #[serde(rename = "Upper 95% CI")]
+    upper: Option<f64>,
+}
+impl ExportRow {
+    pub fn new(name: &str, value: f64) -> Self {
+        ExportRow {
+            name: name.to_string(),
+            value,
+            lower: None,
+            upper: None,
         }
     }
+    pub fn with_interval(mut self, interval: Interval) -> Self {
+        self.lower = Some(interval.lower);
+        self.upper = Some(interval.upper);
+        self
+    }
 }
 
 //todo remove pub and add a getter
@@ -154,26 +895,161 @@ pub struct McResults {
     pub stddev_pos: f64,
     pub stddev_neg: f64,
     pub iterations: usize,
-    pub worst_case_upper: f64,
-    pub worst_case_lower: f64,
+    /// Fraction of the stack variance attributed to each contributor, in tolerance loop order
+    pub contributions: Vec<f64>,
+    pub spec: Option<SpecResults>,
+    pub histogram: Histogram,
+    pub percentiles: Percentiles,
+    pub skewness: f64,
+    /// Excess kurtosis, which is zero for a normal distribution
+    pub kurtosis: f64,
+    pub mean_interval: Interval,
+    pub tolerance_pos_interval: Interval,
+    pub tolerance_neg_interval: Interval,
+    pub stddev_pos_interval: Interval,
+    pub stddev_neg_interval: Interval,
+    pub percentile_intervals: Percentiles<Interval>,
+    pub skewness_interval: Interval,
+    pub kurtosis_interval: Interval,
+    /// Whether the simulation converged, if it was run until converged
+    pub converged: Option<bool>,
+    /// The spread of each component of the vector that closes the loop, if it is a vector loop
+    pub axes: Vec<AxisResults>,
 }
 impl McResults {}
 
+/// The simulated spread of one component of the vector that closes a vector loop
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct AxisResults {
+    pub axis: Axis,
+    pub mean: f64,
+    pub stddev_neg: f64,
+    pub stddev_pos: f64,
+    /// The 0.135th percentile, equivalent to -3 sigma for a normal distribution
+    pub lower_percentile: f64,
+    /// The 99.865th percentile, equivalent to +3 sigma for a normal distribution
+    pub upper_percentile: f64,
+}
+
+/// A range that is expected to contain the true value of an estimate, at 95% confidence
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct Interval {
+    pub lower: f64,
+    pub upper: f64,
+}
+impl Interval {
+    /// A 95% confidence interval, assuming the estimate is normally distributed
+    pub fn from_standard_error(estimate: f64, standard_error: f64) -> Self {
+        Interval {
+            lower: estimate - 1.96 * standard_error,
+            upper: estimate + 1.96 * standard_error,
+        }
+    }
+    /// The Wilson score interval of a fraction of `n` trials. Unlike the normal approximation, this
+    /// stays within 0 and 1, and is not zero wide when none or all of the trials were counted.
+    pub fn wilson(fraction: f64, n: usize) -> Self {
+        if n == 0 {
+            return Interval {
+                lower: 0.0,
+                upper: 1.0,
+            };
+        }
+        let n = n as f64;
+        let z2 = 1.96 * 1.96;
+        let center = (fraction + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let half_width =
+            1.96 / (1.0 + z2 / n) * (fraction * (1.0 - fraction) / n + z2 / (4.0 * n * n)).sqrt();
+        Interval {
+            lower: (center - half_width).max(0.0),
+            upper: (center + half_width).min(1.0),
+        }
+    }
+    pub fn scale(&self, factor: f64) -> Self {
+        Interval {
+            lower: self.lower * factor,
+            upper: self.upper * factor,
+        }
+    }
+}
+
+/// Empirical percentiles of the simulated stack, or their confidence intervals
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct Percentiles<T = f64> {
+    pub p0_135: T,
+    pub p2_5: T,
+    pub p50: T,
+    pub p97_5: T,
+    pub p99_865: T,
+}
+impl<T> Percentiles<T> {
+    /// The probabilities of each percentile
+    pub const PROBABILITIES: [f64; 5] = [0.00135, 0.025, 0.5, 0.975, 0.99865];
+
+    /// Finds each percentile from its probability
+    pub fn from_fn<F: FnMut(f64) -> T>(mut f: F) -> Self {
+        let [p0_135, p2_5, p50, p97_5, p99_865] = Self::PROBABILITIES;
+        Percentiles {
+            p0_135: f(p0_135),
+            p2_5: f(p2_5),
+            p50: f(p50),
+            p97_5: f(p97_5),
+            p99_865: f(p99_865),
+        }
+    }
+}
+
+/// Counts of simulated stack values, binned evenly between `lower` and `upper`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Histogram {
+    pub lower: f64,
+    pub upper: f64,
+    pub counts: Vec<usize>,
+    /// The number of stack values below `lower`, which are not binned
+    #[serde(default)]
+    pub below: usize,
+    /// The number of stack values above `upper`, which are not binned
+    #[serde(default)]
+    pub above: usize,
+}
+impl Histogram {
+    pub fn bin_width(&self) -> f64 {
+        (self.upper - self.lower) / self.counts.len() as f64
+    }
+}
+
 /// Structure used to hold the output of an RSS calculation
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct RssResults {
+    method: RssMethod,
     mean: f64,
     tolerance_pos: f64,
     tolerance_neg: f64,
+    spec: Option<SpecResults>,
 }
 impl RssResults {
     pub fn new(mean: f64, tolerance_pos: f64, tolerance_neg: f64) -> Self {
         RssResults {
+            method: RssMethod::default(),
             mean,
             tolerance_pos,
             tolerance_neg,
+            spec: None,
         }
     }
+    pub fn with_spec(mut self, spec: Option<SpecResults>) -> Self {
+        self.spec = spec;
+        self
+    }
+    pub fn with_method(mut self, method: RssMethod) -> Self {
+        self.method = method;
+        self
+    }
+    pub fn spec(&self) -> &Option<SpecResults> {
+        &self.spec
+    }
+    pub fn method(&self) -> RssMethod {
+        self.method
+    }
     pub fn mean(&self) -> f64 {
         self.mean
     }
@@ -185,11 +1061,210 @@ impl RssResults {
     }
 }
 
+/// The spread of a stack, used to find its capability against the spec limits
+#[derive(Debug, Clone, Copy)]
+pub struct StackSpread {
+    pub mean: f64,
+    pub stddev_neg: f64,
+    pub stddev_pos: f64,
+    /// The 0.135th percentile, equivalent to -3 sigma for a normal distribution
+    pub lower_percentile: f64,
+    pub median: f64,
+    /// The 99.865th percentile, equivalent to +3 sigma for a normal distribution
+    pub upper_percentile: f64,
+}
+
+/// How well a stack fits within its specification limits
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct SpecResults {
+    fraction_out: f64,
+    cp: Option<f64>,
+    cpk: f64,
+    pp: Option<f64>,
+    ppk: f64,
+    fraction_out_interval: Option<Interval>,
+    cp_interval: Option<Interval>,
+    cpk_interval: Option<Interval>,
+}
+impl SpecResults {
+    /// Cp and Cpk are found from the standard deviation, assuming a normal stack. Pp and Ppk use
+    /// the percentile method, so they remain valid when the stack is not normally distributed.
+    /// Returns `None` if the stack has no spec limits, the limits are reversed, or the stack has no
+    /// variation.
+    pub fn new(parameters: &Parameters, spread: &StackSpread, fraction_out: f64) -> Option<Self> {
+        if !parameters.has_spec() {
+            return None;
+        }
+        let lower = parameters.lower_spec;
+        let upper = parameters.upper_spec;
+        // The capability indices are undefined for reversed limits, or a stack without variation.
+        if matches!((lower, upper), (Some(lsl), Some(usl)) if lsl >= usl)
+            || spread.stddev_pos.is_nan()
+            || spread.stddev_neg.is_nan()
+            || spread.stddev_pos <= 0.0
+            || spread.stddev_neg <= 0.0
+        {
+            return None;
+        }
+        let cpk_upper = upper.map(|usl| (usl - spread.mean) / (3.0 * spread.stddev_pos));
+        let cpk_lower = lower.map(|lsl| (spread.mean - lsl) / (3.0 * spread.stddev_neg));
+        let ppk_upper =
+            upper.map(|usl| (usl - spread.median) / (spread.upper_percentile - spread.median));
+        let ppk_lower =
+            lower.map(|lsl| (spread.median - lsl) / (spread.median - spread.lower_percentile));
+        let (cp, pp) = match (lower, upper) {
+            (Some(lsl), Some(usl)) => (
+                Some((usl - lsl) / (3.0 * (spread.stddev_pos + spread.stddev_neg))),
+                Some((usl - lsl) / (spread.upper_percentile - spread.lower_percentile)),
+            ),
+            _ => (None, None),
+        };
+        Some(SpecResults {
+            fraction_out,
+            cp,
+            cpk: min_index(cpk_lower, cpk_upper),
+            pp,
+            ppk: min_index(ppk_lower, ppk_upper),
+            fraction_out_interval: None,
+            cp_interval: None,
+            cpk_interval: None,
+        })
+    }
+    /// Adds the confidence intervals of results estimated from a sample of `iterations`. The
+    /// fraction out of spec uses the Wilson score interval, and the capability indices use the
+    /// normal approximations of Bissell.
+    pub fn with_intervals(mut self, iterations: usize) -> Self {
+        let n = iterations as f64;
+        self.fraction_out_interval = Some(Interval::wilson(self.fraction_out, iterations));
+        self.cp_interval = self
+            .cp
+            .map(|cp| Interval::from_standard_error(cp, cp / (2.0 * (n - 1.0)).sqrt()));
+        let cpk_error = (1.0 / (9.0 * n) + self.cpk * self.cpk / (2.0 * (n - 1.0))).sqrt();
+        self.cpk_interval = Some(Interval::from_standard_error(self.cpk, cpk_error));
+        self
+    }
+    /// Replaces the fraction out of spec with one estimated separately, such as by importance
+    /// sampling, along with its confidence interval.
+    pub fn with_fraction_out(mut self, fraction_out: f64, interval: Interval) -> Self {
+        self.fraction_out = fraction_out;
+        self.fraction_out_interval = Some(interval);
+        self
+    }
+    /// Predicted fraction of assemblies outside of the spec limits
+    pub fn fraction_out(&self) -> f64 {
+        self.fraction_out
+    }
+    /// Predicted defective assemblies per million
+    pub fn ppm(&self) -> f64 {
+        self.fraction_out * 1e6
+    }
+    pub fn cp(&self) -> Option<f64> {
+        self.cp
+    }
+    pub fn cpk(&self) -> f64 {
+        self.cpk
+    }
+    pub fn pp(&self) -> Option<f64> {
+        self.pp
+    }
+    pub fn ppk(&self) -> f64 {
+        self.ppk
+    }
+    pub fn fraction_out_interval(&self) -> Option<Interval> {
+        self.fraction_out_interval
+    }
+    pub fn cp_interval(&self) -> Option<Interval> {
+        self.cp_interval
+    }
+    pub fn cpk_interval(&self) -> Option<Interval> {
+        self.cpk_interval
+    }
+    /// The stack passes if both the Cpk and Ppk meet the target capability
+    pub fn passes(&self, target_capability: f64) -> bool {
+        self.cpk.min(self.ppk) >= target_capability
+    }
+}
+
+/// The capability of a stack is limited by whichever spec limit it is closest to.
+fn min_index(lower: Option<f64>, upper: Option<f64>) -> f64 {
+    match (lower, upper) {
+        (Some(lower), Some(upper)) => lower.min(upper),
+        (Some(index), None) | (None, Some(index)) => index,
+        (None, None) => f64::NAN,
+    }
+}
+
+/// How much a single contributor adds to the variation of the stack
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct Contribution {
+    /// Position of the contributor in the tolerance loop
+    pub index: usize,
+    /// The contributor's variance as a fraction of the RSS stack variance
+    pub rss_share: f64,
+    /// The contributor's share of the Monte Carlo stack variance, if a simulation was run
+    pub mc_share: Option<f64>,
+}
+
+/// The limits of a single contributor's travel in a worst case analysis
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct ContributorBounds {
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Structure used to hold the output of a worst case (arithmetic) calculation
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct WorstCaseResults {
+    nominal: f64,
+    lower: f64,
+    upper: f64,
+    contributors: Vec<ContributorBounds>,
+}
+impl WorstCaseResults {
+    pub fn new(nominal: f64, contributors: Vec<ContributorBounds>) -> Self {
+        WorstCaseResults {
+            nominal,
+            lower: contributors.iter().map(|bounds| bounds.min).sum(),
+            upper: contributors.iter().map(|bounds| bounds.max).sum(),
+            contributors,
+        }
+    }
+    pub fn nominal(&self) -> f64 {
+        self.nominal
+    }
+    pub fn lower(&self) -> f64 {
+        self.lower
+    }
+    pub fn upper(&self) -> f64 {
+        self.upper
+    }
+    /// Moves the stack by a constant, such as the constant term of a stack expression.
+    pub fn with_offset(mut self, offset: f64) -> Self {
+        self.nominal += offset;
+        self.lower += offset;
+        self.upper += offset;
+        self
+    }
+    /// The bounds of each contributor, in the same order as the tolerance loop
+    pub fn contributors(&self) -> &[ContributorBounds] {
+        &self.contributors
+    }
+}
+
 /// Holds the working state of the simulation, including inputs and outputs
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct State {
     pub parameters: Parameters,
     pub tolerance_loop: Vec<Tolerance>,
+    /// Correlations between contributors, by their position in the tolerance loop
+    #[serde(default)]
+    pub correlations: Vec<Correlation>,
+    /// The cost model of each contributor, by its position in the tolerance loop, if it has one
+    #[serde(default)]
+    pub costs: Vec<Option<CostModel>>,
+    /// The stack as a function of its contributors. Without one, the contributors are summed.
+    #[serde(default)]
+    pub expression: Option<Expression>,
     pub results: AnalysisResults,
 }
 impl State {
@@ -197,22 +1272,163 @@ impl State {
         State {
             parameters,
             tolerance_loop: Vec::new(),
+            correlations: Vec::new(),
+            costs: Vec::new(),
+            expression: None,
             results: AnalysisResults::default(),
         }
     }
     pub fn add(&mut self, tolerance: Tolerance) {
         self.tolerance_loop.push(tolerance);
     }
+    /// Adds a contributor to the end of the tolerance loop, with the cost of making it.
+    pub fn add_with_cost(&mut self, tolerance: Tolerance, cost: Option<CostModel>) {
+        self.costs.resize(self.tolerance_loop.len(), None);
+        self.costs.push(cost);
+        self.add(tolerance);
+    }
+    pub fn correlate(&mut self, correlation: Correlation) {
+        self.correlations.push(correlation);
+    }
+    /// The cost model of the contributor at `index` in the tolerance loop, if it has one
+    pub fn cost(&self, index: usize) -> Option<&CostModel> {
+        self.costs.get(index).and_then(Option::as_ref)
+    }
     pub fn clear_inputs(&mut self) {
         self.tolerance_loop = Vec::new();
+        self.correlations = Vec::new();
+        self.costs = Vec::new();
+        self.expression = None;
     }
 }
 impl Default for State {
     fn default() -> Self {
-        let parameters = Parameters {
-            assy_sigma: 4.0,
-            n_iterations: 1000000,
+        State::new(Parameters::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_normal_centers_the_tolerance_band() {
+        // (dim, tol_pos, tol_neg, centered dim, half band)
+        let cases = [
+            (10.0, 0.3, 0.3, 10.0, 0.3),
+            (5.0, 0.2, 0.1, 5.05, 0.15),
+            (2.5, 0.1, 0.0, 2.55, 0.05),
+            (3.0, 0.0, 0.0, 3.0, 0.0),
+        ];
+        for &(dim, tol_pos, tol_neg, center, half_band) in cases.iter() {
+            let dim_tol = DimTol::new_normal(dim, tol_pos, tol_neg, 3.0).unwrap();
+            assert!((dim_tol.dim - center).abs() < 1e-12);
+            assert!((dim_tol.tol_pos - half_band).abs() < 1e-12);
+            assert!((dim_tol.tol_neg - half_band).abs() < 1e-12);
+            assert!((dim_tol.stddev() - half_band / 3.0).abs() < 1e-12);
+            // The limits of the band are unchanged by re-centering.
+            assert!((dim_tol.dim + dim_tol.tol_pos - (dim + tol_pos)).abs() < 1e-12);
+            assert!((dim_tol.dim - dim_tol.tol_neg - (dim - tol_neg)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn new_normal_rejects_invalid_input() {
+        assert!(DimTol::new_normal(1.0, -0.1, 0.1, 3.0).is_err());
+        assert!(DimTol::new_normal(1.0, 0.1, -0.1, 3.0).is_err());
+        assert!(DimTol::new_normal(1.0, 0.1, 0.1, 0.0).is_err());
+        assert!(DimTol::new_normal(1.0, f64::NAN, 0.1, 3.0).is_err());
+        assert!(DimTol::new_normal(f64::INFINITY, 0.1, 0.1, 3.0).is_err());
+    }
+
+    #[test]
+    fn spec_results_need_ordered_limits_and_variation() {
+        let spread = StackSpread {
+            mean: 10.0,
+            stddev_neg: 0.1,
+            stddev_pos: 0.1,
+            lower_percentile: 9.7,
+            median: 10.0,
+            upper_percentile: 10.3,
+        };
+        let mut parameters = Parameters::default();
+        parameters.lower_spec = Some(9.4);
+        parameters.upper_spec = Some(10.6);
+        let spec = SpecResults::new(&parameters, &spread, 0.0).unwrap();
+        assert!((spec.cpk() - 2.0).abs() < 1e-12);
+        let still = StackSpread {
+            stddev_neg: 0.0,
+            stddev_pos: 0.0,
+            ..spread
+        };
+        assert!(SpecResults::new(&parameters, &still, 0.0).is_none());
+        parameters.lower_spec = Some(10.6);
+        parameters.upper_spec = Some(9.4);
+        assert!(SpecResults::new(&parameters, &spread, 0.0).is_none());
+    }
+
+    #[test]
+    fn wilson_interval() {
+        // Newcombe (1998), Table I: 81 of 263 is 0.2553 to 0.3662, and 0 of 10 is 0 to 0.2775.
+        let interval = Interval::wilson(81.0 / 263.0, 263);
+        assert!((interval.lower - 0.2553).abs() < 5e-5);
+        assert!((interval.upper - 0.3662).abs() < 5e-5);
+        let interval = Interval::wilson(0.0, 10);
+        assert_eq!(interval.lower, 0.0);
+        assert!((interval.upper - 0.2775).abs() < 5e-5);
+    }
+
+    #[test]
+    fn bissell_intervals() {
+        // Montgomery, Introduction to Statistical Quality Control: a Cpk of 1.33 from 20 parts is
+        //  0.88 to 1.78. Cp has a standard error of Cp / sqrt(2(n - 1)), so 1.33 is 0.91 to 1.75.
+        let mut parameters = Parameters::default();
+        parameters.lower_spec = Some(9.4);
+        parameters.upper_spec = Some(10.6);
+        let stddev = 0.6 / (3.0 * 1.33);
+        let spread = StackSpread {
+            mean: 10.0,
+            stddev_neg: stddev,
+            stddev_pos: stddev,
+            lower_percentile: 10.0 - 3.0 * stddev,
+            median: 10.0,
+            upper_percentile: 10.0 + 3.0 * stddev,
+        };
+        let spec = SpecResults::new(&parameters, &spread, 0.0)
+            .unwrap()
+            .with_intervals(20);
+        let cpk = spec.cpk_interval().unwrap();
+        assert!((cpk.lower - 0.88).abs() < 5e-3);
+        assert!((cpk.upper - 1.78).abs() < 5e-3);
+        let cp = spec.cp_interval().unwrap();
+        assert!((cp.lower - 0.907).abs() < 5e-4);
+        assert!((cp.upper - 1.753).abs() < 5e-4);
+    }
+
+    #[test]
+    fn state_without_correlations_loads() {
+        let mut value = serde_json::to_value(State::default()).unwrap();
+        value.as_object_mut().unwrap().remove("correlations");
+        let state: State = serde_json::from_value(value).unwrap();
+        assert!(state.correlations.is_empty());
+    }
+
+    #[test]
+    fn export_without_monte_carlo() {
+        let bounds = ContributorBounds {
+            min: 9.7,
+            max: 10.3,
         };
-        State::new(parameters)
+        let results = AnalysisResults::default()
+            .with_worst_case(Some(WorstCaseResults::new(10.0, vec![bounds])))
+            .with_rss(vec![RssResults::new(10.0, 0.3, 0.3)]);
+        let rows = results.export();
+        let names: Vec<&str> = rows.iter().map(|row| row.name.as_str()).collect();
+        assert!(names.contains(&"Worst Case Lower"));
+        assert!(names.iter().any(|name| name.ends_with("Tolerance (+)")));
+        assert!(!names.iter().any(|name| name.starts_with("MC")));
+        // Without the RSS analysis, the worst case is still exported.
+        let results = results.with_rss(Vec::new());
+        assert!(!results.export().is_empty());
     }
 }
@@ -0,0 +1,60 @@
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed set of worker threads that live for the duration of the program, so simulations don't
+/// pay the cost of spawning threads every time they run. Jobs are queued, and picked up by the
+/// next idle worker.
+pub struct WorkerPool {
+    sender: Mutex<mpsc::Sender<Job>>,
+    n_workers: usize,
+}
+impl WorkerPool {
+    /// The shared pool, with one worker for each core available on this machine.
+    pub fn global() -> &'static WorkerPool {
+        static POOL: OnceLock<WorkerPool> = OnceLock::new();
+        POOL.get_or_init(|| {
+            let n_workers = thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4);
+            WorkerPool::new(n_workers)
+        })
+    }
+
+    fn new(n_workers: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..n_workers {
+            let receiver = Arc::clone(&receiver);
+            thread::spawn(move || loop {
+                // Only hold the lock while waiting for a job, not while running it.
+                let job = match receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    Err(_) => return,
+                };
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => return,
+                }
+            });
+        }
+        WorkerPool {
+            sender: Mutex::new(sender),
+            n_workers,
+        }
+    }
+
+    pub fn n_workers(&self) -> usize {
+        self.n_workers
+    }
+
+    /// Queues a job to be run on the next available worker.
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        self.sender
+            .lock()
+            .expect("Worker pool queue poisoned")
+            .send(Box::new(job))
+            .expect("Worker pool threads have stopped");
+    }
+}
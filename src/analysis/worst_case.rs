@@ -0,0 +1,185 @@
+use super::expression::StackEquation;
+use super::structures::*;
+
+/// Computes the arithmetic worst case of the stack, where every contributor is simultaneously at
+/// the limit of its tolerance. No sampling is needed, so this runs instantly. A stack expression
+/// is linearized about the mean of each contributor, and a vector loop is measured along the X
+/// axis.
+pub async fn run(state: &State) -> WorstCaseResults {
+    let equation = StackEquation::of(state, &state.tolerance_loop);
+    let tolerance_loop = equation.linearized(&state.tolerance_loop);
+    let nominal = tolerance_loop
+        .iter()
+        .fold(0.0, |acc, tol| acc + tol.nominal());
+    let contributors = tolerance_loop
+        .iter()
+        .map(|tol| tol.worst_case_bounds())
+        .collect();
+
+    WorstCaseResults::new(nominal, contributors).with_offset(equation.constant)
+}
+
+impl Tolerance {
+    /// The contribution of this tolerance to the stack when every dimension is at nominal.
+    pub fn nominal(&self) -> f64 {
+        match self {
+            Tolerance::Linear(linear) => linear.sensitivity * linear.distance.dim,
+            Tolerance::Angular(angular) => angular.sensitivity * angular.offset(angular.angle.dim),
+            // A pin centered in its hole does not offset the stack.
+            Tolerance::Float(_) | Tolerance::Compound(_) => 0.0,
+        }
+    }
+
+    /// The minimum and maximum contribution of this tolerance to the stack. A negative sensitivity
+    /// turns the bounds of the tolerance around.
+    pub fn worst_case_bounds(&self) -> ContributorBounds {
+        let sensitivity = self.sensitivity();
+        let bounds = match self {
+            Tolerance::Linear(linear) => ContributorBounds {
+                min: linear.distance.dim - linear.distance.tol_neg,
+                max: linear.distance.dim + linear.distance.tol_pos,
+            },
+            Tolerance::Float(float) => {
+                let clearance = max_clearance(&float.hole, &float.pin);
+                ContributorBounds {
+                    min: -clearance,
+                    max: clearance,
+                }
+            }
+            Tolerance::Compound(compound) => {
+                // The part can float fully in the round hole and across the slot at once.
+                let (along, across) = compound.projection();
+                let clearance = along * max_clearance(&compound.hole, &compound.hole_pin)
+                    + across * max_clearance(&compound.slot, &compound.slot_pin);
+                ContributorBounds {
+                    min: -clearance,
+                    max: clearance,
+                }
+            }
+            Tolerance::Angular(angular) => {
+                // The offset is largest at the ends of the angle's tolerance band, unless the
+                //  band spans a right angle, where the offset turns around.
+                let (lower, upper) = (
+                    angular.angle.dim - angular.angle.tol_neg,
+                    angular.angle.dim + angular.angle.tol_pos,
+                );
+                let turns =
+                    (lower / 180.0 - 0.5).ceil() as i64..=(upper / 180.0 - 0.5).floor() as i64;
+                turns
+                    .map(|turn| 90.0 + 180.0 * turn as f64)
+                    .chain([lower, upper].iter().cloned())
+                    .map(|angle| angular.offset(angle))
+                    .fold(
+                        ContributorBounds {
+                            min: f64::INFINITY,
+                            max: f64::NEG_INFINITY,
+                        },
+                        |bounds, offset| ContributorBounds {
+                            min: bounds.min.min(offset),
+                            max: bounds.max.max(offset),
+                        },
+                    )
+            }
+        };
+        let (first, second) = (sensitivity * bounds.min, sensitivity * bounds.max);
+        ContributorBounds {
+            min: first.min(second),
+            max: first.max(second),
+        }
+    }
+}
+
+/// The pin can float in either direction by the radial clearance between the largest hole and the
+/// smallest pin. If the parts interfere, there is no float.
+fn max_clearance(hole: &DimTol, pin: &DimTol) -> f64 {
+    let hole_max = hole.dim + hole.tol_pos;
+    let pin_min = pin.dim - pin.tol_neg;
+    f64::max(0.0, (hole_max - pin_min) / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::{linear, normal};
+    use super::*;
+
+    fn worst_case(tolerances: &[(f64, f64, f64)]) -> WorstCaseResults {
+        let mut state = State::default();
+        for &(dim, tol_pos, tol_neg) in tolerances {
+            state.add(linear(dim, tol_pos, tol_neg));
+        }
+        async_std::task::block_on(run(&state))
+    }
+
+    #[test]
+    fn symmetric_stack() {
+        let results = worst_case(&[(10.0, 0.3, 0.3), (5.0, 0.4, 0.4)]);
+        assert!((results.nominal() - 15.0).abs() < 1e-12);
+        assert!((results.lower() - 14.3).abs() < 1e-12);
+        assert!((results.upper() - 15.7).abs() < 1e-12);
+    }
+
+    #[test]
+    fn asymmetric_stack() {
+        let results = worst_case(&[(10.0, 0.5, 0.1), (5.0, 0.1, 0.7)]);
+        assert!((results.nominal() - 14.9).abs() < 1e-12);
+        assert!((results.lower() - 14.2).abs() < 1e-12);
+        assert!((results.upper() - 15.6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn unilateral_stack() {
+        let results = worst_case(&[(10.0, 0.6, 0.0), (5.0, 0.0, 0.8)]);
+        assert!((results.nominal() - 14.9).abs() < 1e-12);
+        assert!((results.lower() - 14.2).abs() < 1e-12);
+        assert!((results.upper() - 15.6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn float_with_unilateral_hole() {
+        // The largest hole is 2.6 and the smallest pin 2.4, so the pin floats +/-0.1.
+        let mut state = State::default();
+        state.add(Tolerance::Float(FloatTL::new(
+            normal(2.5, 0.1, 0.0),
+            normal(2.5, 0.0, 0.1),
+            3.0,
+        )));
+        let results = async_std::task::block_on(run(&state));
+        assert!((results.lower() + 0.1).abs() < 1e-12);
+        assert!((results.upper() - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn compound_float() {
+        // The round hole clearance is (6.1 - 5.8) / 2 = 0.15 and the slot clearance is
+        //  (6.3 - 5.8) / 2 = 0.25. At 60°, half of the first and 0.866 of the second act along the
+        //  stack.
+        let mut state = State::default();
+        state.add(Tolerance::Compound(CompoundFloatTL::new(
+            normal(6.0, 0.1, 0.0),
+            normal(5.9, 0.0, 0.1),
+            normal(6.0, 0.3, 0.0),
+            normal(5.9, 0.0, 0.1),
+            60.0,
+        )));
+        let results = async_std::task::block_on(run(&state));
+        let clearance = 0.5 * 0.15 + 60f64.to_radians().sin() * 0.25;
+        assert!(results.nominal().abs() < 1e-12);
+        assert!((results.lower() + clearance).abs() < 1e-12);
+        assert!((results.upper() - clearance).abs() < 1e-12);
+    }
+
+    #[test]
+    fn angular_band_across_right_angle() {
+        // The offset peaks at 90°, inside the band, and is smallest at the far end of the band. The
+        //  band is centered, so the nominal angle is 87.5°.
+        let mut state = State::default();
+        state.add(Tolerance::Angular(AngularTL::new(
+            normal(85.0, 10.0, 5.0),
+            2.0,
+        )));
+        let results = async_std::task::block_on(run(&state));
+        assert!((results.nominal() - 2.0 * 87.5f64.to_radians().sin()).abs() < 1e-12);
+        assert!((results.lower() - 2.0 * 80f64.to_radians().sin()).abs() < 1e-12);
+        assert!((results.upper() - 2.0).abs() < 1e-12);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::analysis::structures::ExportRow;
 use chrono::prelude::*;
 use csv::Writer;
 
@@ -9,7 +10,7 @@ pub enum SaveError {
     OpenError,
 }
 
-pub async fn serialize_csv(data: Vec<f64>) -> Result<(), SaveError> {
+pub async fn serialize_csv(data: Vec<ExportRow>) -> Result<(), SaveError> {
     async {
         let path = path();
         let mut wtr = Writer::from_path(path.clone()).map_err(|_| SaveError::DirectoryError)?;
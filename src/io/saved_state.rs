@@ -1,5 +1,6 @@
 use super::dialogs;
-use crate::ui::components::entry_tolerance::ToleranceEntry;
+use crate::analysis::structures::{Parameters, RssMethod, SamplingStrategy};
+use crate::ui::components::{entry_correlation::CorrelationEntry, entry_tolerance::ToleranceEntry};
 use serde_derive::*;
 use std::path::PathBuf;
 
@@ -7,8 +8,57 @@ use std::path::PathBuf;
 pub struct SavedState {
     pub name: String,
     pub tolerances: Vec<ToleranceEntry>,
+    #[serde(default)]
+    pub correlations: Vec<CorrelationEntry>,
     pub n_iteration: usize,
     pub assy_sigma: f64,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub lower_spec: Option<f64>,
+    #[serde(default)]
+    pub upper_spec: Option<f64>,
+    #[serde(default = "default_target_capability")]
+    pub target_capability: f64,
+    #[serde(default)]
+    pub converge: bool,
+    #[serde(default = "default_convergence_threshold")]
+    pub convergence_threshold: f64,
+    #[serde(default = "default_max_iterations")]
+    pub max_iterations: usize,
+    #[serde(default)]
+    pub sampling: SamplingStrategy,
+    #[serde(default = "default_rss_methods")]
+    pub rss_methods: Vec<RssMethod>,
+    #[serde(default = "default_mean_shift_factor")]
+    pub mean_shift_factor: f64,
+    #[serde(default)]
+    pub importance_sampling: bool,
+    #[serde(default)]
+    pub stack_equation: String,
+    #[serde(default)]
+    pub vector_loop: bool,
+}
+
+// Settings missing from older project files take the defaults of the analysis.
+fn default_target_capability() -> f64 {
+    Parameters::default().target_capability
+}
+
+fn default_convergence_threshold() -> f64 {
+    Parameters::default().convergence_threshold
+}
+
+fn default_max_iterations() -> usize {
+    Parameters::default().max_iterations
+}
+
+fn default_rss_methods() -> Vec<RssMethod> {
+    Parameters::default().rss_methods
+}
+
+fn default_mean_shift_factor() -> f64 {
+    Parameters::default().mean_shift_factor
 }
 
 #[derive(Debug, Clone)]
@@ -26,12 +76,29 @@ pub enum SaveError {
 }
 
 impl Default for SavedState {
+    /// A new project, with the default settings of the analysis. A new project runs fewer
+    /// iterations, so the first results come back quickly.
     fn default() -> Self {
+        let parameters = Parameters::default();
         SavedState {
             name: "New Project".into(),
             tolerances: Vec::new(),
+            correlations: Vec::new(),
             n_iteration: 100000,
-            assy_sigma: 4.0,
+            assy_sigma: parameters.assy_sigma,
+            seed: parameters.seed,
+            lower_spec: parameters.lower_spec,
+            upper_spec: parameters.upper_spec,
+            target_capability: parameters.target_capability,
+            converge: parameters.converge,
+            convergence_threshold: parameters.convergence_threshold,
+            max_iterations: parameters.max_iterations,
+            sampling: parameters.sampling,
+            rss_methods: parameters.rss_methods,
+            mean_shift_factor: parameters.mean_shift_factor,
+            importance_sampling: parameters.importance_sampling,
+            stack_equation: String::new(),
+            vector_loop: parameters.vector_loop,
         }
     }
 }
@@ -39,15 +106,7 @@ impl Default for SavedState {
 #[cfg(not(target_arch = "wasm32"))]
 impl SavedState {
     pub async fn new() -> Result<(Option<std::path::PathBuf>, SavedState), LoadError> {
-        Ok((
-            None,
-            SavedState {
-                name: "New Project".into(),
-                tolerances: Vec::new(),
-                n_iteration: 100000,
-                assy_sigma: 4.0,
-            },
-        ))
+        Ok((None, SavedState::default()))
     }
 
     pub async fn save(state: SavedState, path: PathBuf) -> Result<Option<PathBuf>, SaveError> {
@@ -8,9 +8,22 @@ mod ui {
 }
 
 mod analysis {
+    pub mod accumulator;
+    pub mod allocation;
+    pub mod contribution;
+    pub mod correlation;
+    pub mod cost;
+    pub mod expression;
+    #[cfg(test)]
+    mod fixtures;
+    pub mod importance_sampling;
     pub mod monte_carlo;
+    #[cfg(test)]
+    mod reference;
     pub mod root_sum_square;
     pub mod structures;
+    pub mod worker_pool;
+    pub mod worst_case;
 }
 
 mod io {
@@ -95,6 +108,36 @@ impl State {
             .count()
             > 0
     }
+    /// Collects everything that is persisted in a project file
+    fn save_data(&self) -> SavedState {
+        let entry_form = &self.analysis_state.entry_form;
+        SavedState {
+            name: self.stack_editor.title.text.clone(),
+            tolerances: self.stack_editor.tolerances.clone(),
+            correlations: self.stack_editor.correlations.clone(),
+            n_iteration: entry_form.n_iteration,
+            assy_sigma: entry_form.assy_sigma,
+            seed: entry_form.seed,
+            lower_spec: entry_form.lower_spec.parse().ok(),
+            upper_spec: entry_form.upper_spec.parse().ok(),
+            target_capability: entry_form.target_capability,
+            converge: entry_form.converge,
+            // An unfinished threshold is saved as the last one that was used.
+            convergence_threshold: entry_form.threshold().unwrap_or(
+                self.analysis_state
+                    .model_state
+                    .parameters
+                    .convergence_threshold,
+            ),
+            max_iterations: entry_form.max_iterations,
+            sampling: entry_form.sampling,
+            rss_methods: entry_form.rss_methods.clone(),
+            mean_shift_factor: entry_form.mean_shift_factor,
+            importance_sampling: entry_form.importance_sampling,
+            stack_equation: entry_form.stack_equation.clone(),
+            vector_loop: entry_form.vector_loop,
+        }
+    }
 }
 
 // Messages - events for users to change the application state
@@ -190,10 +233,27 @@ impl Application for TolStack {
                         *self = TolStack::Loaded(Box::new(State {
                             stack_editor: StackEditor::new()
                                 .tolerances(state.tolerances)
-                                .title(state.name),
+                                .correlations(state.correlations)
+                                .title(state.name)
+                                .vector_loop(state.vector_loop),
                             header: Header::new(),
                             analysis_state: AnalysisState::new()
-                                .set_inputs(state.n_iteration, state.assy_sigma),
+                                .set_inputs(state.n_iteration, state.assy_sigma, state.seed)
+                                .set_spec(
+                                    state.lower_spec,
+                                    state.upper_spec,
+                                    state.target_capability,
+                                )
+                                .set_convergence(
+                                    state.converge,
+                                    state.convergence_threshold,
+                                    state.max_iterations,
+                                )
+                                .set_sampling(state.sampling)
+                                .set_importance_sampling(state.importance_sampling)
+                                .set_stack_equation(state.stack_equation)
+                                .set_vector_loop(state.vector_loop)
+                                .set_rss(state.rss_methods, state.mean_shift_factor),
                             file_path: path,
                             dirty: false,
                             saving: false,
@@ -235,90 +295,145 @@ impl Application for TolStack {
                                             button_move_up: _,
                                             button_move_down: _,
                                         } => {}
-                                        entry_tolerance::State::Editing { form_tolentry } => {
-                                            match &mut **form_tolentry {
-                                                FormState::Linear {
-                                                    button_save: _,
-                                                    button_delete: _,
-                                                    description,
-                                                    dimension,
-                                                    tolerance_pos,
-                                                    tolerance_neg,
-                                                    sigma,
-                                                } => {
-                                                    if description.is_focused() {
-                                                        *description = text_input::State::default();
-                                                        *dimension = text_input::State::focused();
-                                                    } else if dimension.is_focused() {
-                                                        *dimension = text_input::State::default();
-                                                        *tolerance_pos =
-                                                            text_input::State::focused();
-                                                    } else if tolerance_pos.is_focused() {
-                                                        *tolerance_pos =
-                                                            text_input::State::default();
-                                                        *tolerance_neg =
-                                                            text_input::State::focused();
-                                                    } else if tolerance_neg.is_focused() {
-                                                        *tolerance_neg =
-                                                            text_input::State::default();
-                                                        *sigma = text_input::State::focused();
-                                                    } else if sigma.is_focused() {
-                                                        *sigma = text_input::State::default();
-                                                        *description = text_input::State::focused();
-                                                    }
+                                        entry_tolerance::State::Editing {
+                                            form_tolentry, ..
+                                        } => match &mut **form_tolentry {
+                                            FormState::Linear {
+                                                button_save: _,
+                                                button_delete: _,
+                                                description,
+                                                dimension,
+                                                tolerance_pos,
+                                                tolerance_neg,
+                                                sigma,
+                                                cpk,
+                                                mean_shift,
+                                                mean_shift_unit: _,
+                                                distribution: _,
+                                            } => {
+                                                if description.is_focused() {
+                                                    *description = text_input::State::default();
+                                                    *dimension = text_input::State::focused();
+                                                } else if dimension.is_focused() {
+                                                    *dimension = text_input::State::default();
+                                                    *tolerance_pos = text_input::State::focused();
+                                                } else if tolerance_pos.is_focused() {
+                                                    *tolerance_pos = text_input::State::default();
+                                                    *tolerance_neg = text_input::State::focused();
+                                                } else if tolerance_neg.is_focused() {
+                                                    *tolerance_neg = text_input::State::default();
+                                                    *sigma = text_input::State::focused();
+                                                } else if sigma.is_focused() {
+                                                    *sigma = text_input::State::default();
+                                                    *cpk = text_input::State::focused();
+                                                } else if cpk.is_focused() {
+                                                    *cpk = text_input::State::default();
+                                                    *mean_shift = text_input::State::focused();
+                                                } else if mean_shift.is_focused() {
+                                                    *mean_shift = text_input::State::default();
+                                                    *description = text_input::State::focused();
                                                 }
-                                                FormState::Float {
-                                                    button_save: _,
-                                                    button_delete: _,
+                                            }
+                                            FormState::Float {
+                                                button_save: _,
+                                                button_delete: _,
+                                                description,
+                                                diameter_hole,
+                                                diameter_pin,
+                                                tolerance_hole_pos,
+                                                tolerance_hole_neg,
+                                                tolerance_pin_pos,
+                                                tolerance_pin_neg,
+                                                sigma,
+                                                distribution_hole: _,
+                                                distribution_pin: _,
+                                            } => {
+                                                if description.is_focused() {
+                                                    *description = text_input::State::default();
+                                                    *diameter_hole = text_input::State::focused();
+                                                } else if diameter_hole.is_focused() {
+                                                    *diameter_hole = text_input::State::default();
+                                                    *tolerance_hole_pos =
+                                                        text_input::State::focused();
+                                                } else if tolerance_hole_pos.is_focused() {
+                                                    *tolerance_hole_pos =
+                                                        text_input::State::default();
+                                                    *tolerance_hole_neg =
+                                                        text_input::State::focused();
+                                                } else if tolerance_hole_neg.is_focused() {
+                                                    *tolerance_hole_neg =
+                                                        text_input::State::default();
+                                                    *diameter_pin = text_input::State::focused();
+                                                } else if diameter_pin.is_focused() {
+                                                    *diameter_pin = text_input::State::default();
+                                                    *tolerance_pin_pos =
+                                                        text_input::State::focused();
+                                                } else if tolerance_pin_pos.is_focused() {
+                                                    *tolerance_pin_pos =
+                                                        text_input::State::default();
+                                                    *tolerance_pin_neg =
+                                                        text_input::State::focused();
+                                                } else if tolerance_pin_neg.is_focused() {
+                                                    *tolerance_pin_neg =
+                                                        text_input::State::default();
+                                                    *sigma = text_input::State::focused();
+                                                } else if sigma.is_focused() {
+                                                    *sigma = text_input::State::default();
+                                                    *description = text_input::State::focused();
+                                                }
+                                            }
+                                            FormState::Compound {
+                                                button_save: _,
+                                                button_delete: _,
+                                                description,
+                                                hole,
+                                                hole_pin,
+                                                slot,
+                                                slot_pin,
+                                                slot_angle,
+                                                sigma,
+                                            } => {
+                                                let mut fields = vec![description];
+                                                for part in [hole, hole_pin, slot, slot_pin] {
+                                                    fields.push(&mut part.dimension);
+                                                    fields.push(&mut part.tolerance_pos);
+                                                    fields.push(&mut part.tolerance_neg);
+                                                }
+                                                fields.push(slot_angle);
+                                                fields.push(sigma);
+                                                if let Some(focused) =
+                                                    fields.iter().position(|f| f.is_focused())
+                                                {
+                                                    *fields[focused] = text_input::State::default();
+                                                    let next = (focused + 1) % fields.len();
+                                                    *fields[next] = text_input::State::focused();
+                                                }
+                                            }
+                                            FormState::Angular {
+                                                button_save: _,
+                                                button_delete: _,
+                                                description,
+                                                angle,
+                                                length,
+                                                sigma,
+                                            } => {
+                                                let fields = [
                                                     description,
-                                                    diameter_hole,
-                                                    diameter_pin,
-                                                    tolerance_hole_pos,
-                                                    tolerance_hole_neg,
-                                                    tolerance_pin_pos,
-                                                    tolerance_pin_neg,
+                                                    &mut angle.dimension,
+                                                    &mut angle.tolerance_pos,
+                                                    &mut angle.tolerance_neg,
+                                                    length,
                                                     sigma,
-                                                } => {
-                                                    if description.is_focused() {
-                                                        *description = text_input::State::default();
-                                                        *diameter_hole =
-                                                            text_input::State::focused();
-                                                    } else if diameter_hole.is_focused() {
-                                                        *diameter_hole =
-                                                            text_input::State::default();
-                                                        *tolerance_hole_pos =
-                                                            text_input::State::focused();
-                                                    } else if tolerance_hole_pos.is_focused() {
-                                                        *tolerance_hole_pos =
-                                                            text_input::State::default();
-                                                        *tolerance_hole_neg =
-                                                            text_input::State::focused();
-                                                    } else if tolerance_hole_neg.is_focused() {
-                                                        *tolerance_hole_neg =
-                                                            text_input::State::default();
-                                                        *diameter_pin =
-                                                            text_input::State::focused();
-                                                    } else if diameter_pin.is_focused() {
-                                                        *diameter_pin =
-                                                            text_input::State::default();
-                                                        *tolerance_pin_pos =
-                                                            text_input::State::focused();
-                                                    } else if tolerance_pin_pos.is_focused() {
-                                                        *tolerance_pin_pos =
-                                                            text_input::State::default();
-                                                        *tolerance_pin_neg =
-                                                            text_input::State::focused();
-                                                    } else if tolerance_pin_neg.is_focused() {
-                                                        *tolerance_pin_neg =
-                                                            text_input::State::default();
-                                                        *sigma = text_input::State::focused();
-                                                    } else if sigma.is_focused() {
-                                                        *sigma = text_input::State::default();
-                                                        *description = text_input::State::focused();
-                                                    }
+                                                ];
+                                                if let Some(focused) =
+                                                    fields.iter().position(|f| f.is_focused())
+                                                {
+                                                    *fields[focused] = text_input::State::default();
+                                                    let next = (focused + 1) % fields.len();
+                                                    *fields[next] = text_input::State::focused();
                                                 }
                                             }
-                                        }
+                                        },
                                     }
                                 }
                             } else {
@@ -329,12 +444,7 @@ impl Application for TolStack {
                     Message::AutoSave => {
                         if let Some(path) = &state.file_path {
                             state.saving = true;
-                            let save_data = SavedState {
-                                name: state.stack_editor.title.text.clone(),
-                                tolerances: state.stack_editor.tolerances.clone(),
-                                n_iteration: state.analysis_state.entry_form.n_iteration,
-                                assy_sigma: state.analysis_state.entry_form.assy_sigma,
-                            };
+                            let save_data = state.save_data();
                             return Command::perform(
                                 SavedState::save(save_data, path.clone()),
                                 Message::Saved,
@@ -351,12 +461,7 @@ impl Application for TolStack {
                     }
 
                     Message::Header(area_header::HeaderAreaMessage::SaveFile) => {
-                        let save_data = SavedState {
-                            name: state.stack_editor.title.text.clone(),
-                            tolerances: state.stack_editor.tolerances.clone(),
-                            n_iteration: state.analysis_state.entry_form.n_iteration,
-                            assy_sigma: state.analysis_state.entry_form.assy_sigma,
-                        };
+                        let save_data = state.save_data();
 
                         match &state.file_path {
                             Some(path) => {
@@ -375,12 +480,7 @@ impl Application for TolStack {
                     }
 
                     Message::Header(area_header::HeaderAreaMessage::SaveAsFile) => {
-                        let save_data = SavedState {
-                            name: state.stack_editor.title.text.clone(),
-                            tolerances: state.stack_editor.tolerances.clone(),
-                            n_iteration: state.analysis_state.entry_form.n_iteration,
-                            assy_sigma: state.analysis_state.entry_form.assy_sigma,
-                        };
+                        let save_data = state.save_data();
 
                         return Command::perform(SavedState::save_as(save_data), Message::Saved);
                     }
@@ -414,6 +514,26 @@ impl Application for TolStack {
                         )
                     }
 
+                    Message::Header(area_header::HeaderAreaMessage::AddTolCompound) => {
+                        state.mark_unsaved_changes();
+                        state.stack_editor.update(
+                            area_stack_editor::StackEditorAreaMessage::NewEntryMessage((
+                                String::from("New Compound Float Tolerance"),
+                                Tolerance::Compound(CompoundFloatTL::default()),
+                            )),
+                        )
+                    }
+
+                    Message::Header(area_header::HeaderAreaMessage::AddTolAngular) => {
+                        state.mark_unsaved_changes();
+                        state.stack_editor.update(
+                            area_stack_editor::StackEditorAreaMessage::NewEntryMessage((
+                                String::from("New Angular Tolerance"),
+                                Tolerance::Angular(AngularTL::default()),
+                            )),
+                        )
+                    }
+
                     Message::Header(area_header::HeaderAreaMessage::Help) => {
                         return Command::perform(help(), |_| Message::HelpOpened);
                     }
@@ -423,9 +543,18 @@ impl Application for TolStack {
                     Message::ExportComplete(_) => {}
 
                     Message::StackEditor(message) => {
-                        let recompute = matches!(message, area_stack_editor::StackEditorAreaMessage::LabelMessage(
-                            editable_label::Message::FinishEditing,
-                            ) | area_stack_editor::StackEditorAreaMessage::EntryMessage(_, _));
+                        let recompute = matches!(
+                            message,
+                            area_stack_editor::StackEditorAreaMessage::LabelMessage(
+                                editable_label::Message::FinishEditing,
+                            ) | area_stack_editor::StackEditorAreaMessage::EntryMessage(_, _)
+                                | area_stack_editor::StackEditorAreaMessage::CorrelationMessage(
+                                    _,
+                                    _
+                                )
+                                | area_stack_editor::StackEditorAreaMessage::NewCorrelation
+                                | area_stack_editor::StackEditorAreaMessage::ScaleTolerances(_)
+                        );
                         state.stack_editor.update(message);
                         if recompute {
                             state.mark_unsaved_changes();
@@ -449,6 +578,8 @@ impl Application for TolStack {
                             // carlo simulation's input tolerance list.
                             state.analysis_state.input_stack =
                                 state.stack_editor.tolerances.clone();
+                            state.analysis_state.input_correlations =
+                                state.stack_editor.correlation_inputs();
                             // Pass this message into the child so the computation gets kicked off.
                             let calculate_message =
                                 area_mc_analysis::AnalysisAreaMessage::NewMcAnalysisMessage(
@@ -461,6 +592,38 @@ impl Application for TolStack {
                         }
                     }
 
+                    Message::Analysis(
+                        area_mc_analysis::AnalysisAreaMessage::AllocationMessage(
+                            form_allocation::Message::Accept,
+                        ),
+                    ) => {
+                        // The proposed tolerances are written into the stack editor, which then
+                        //  recomputes the analysis like any other edit.
+                        let factors = state.analysis_state.take_allocation();
+                        return Command::perform(do_nothing(), move |_| {
+                            Message::StackEditor(
+                                area_stack_editor::StackEditorAreaMessage::ScaleTolerances(
+                                    factors.clone(),
+                                ),
+                            )
+                        });
+                    }
+
+                    Message::Analysis(
+                        area_mc_analysis::AnalysisAreaMessage::NewMcAnalysisMessage(
+                            form_new_mc_analysis::Message::VectorLoopToggled(vector_loop),
+                        ),
+                    ) => {
+                        // The editor draws the loop in 2D while it is analyzed as a vector loop.
+                        state.stack_editor.vector_loop = vector_loop;
+                        return state
+                            .analysis_state
+                            .update(area_mc_analysis::AnalysisAreaMessage::NewMcAnalysisMessage(
+                                form_new_mc_analysis::Message::VectorLoopToggled(vector_loop),
+                            ))
+                            .map(Message::Analysis);
+                    }
+
                     Message::Analysis(message) => {
                         // TODO collect commands and run at end instead of breaking at match arm.
                         return state.analysis_state.update(message).map(Message::Analysis);
@@ -514,10 +677,31 @@ impl Application for TolStack {
                         *state = Box::new(State {
                             stack_editor: StackEditor::new()
                                 .tolerances(save_state.tolerances)
-                                .title(save_state.name),
+                                .correlations(save_state.correlations)
+                                .title(save_state.name)
+                                .vector_loop(save_state.vector_loop),
                             header: Header::new(),
                             analysis_state: AnalysisState::new()
-                                .set_inputs(save_state.n_iteration, save_state.assy_sigma),
+                                .set_inputs(
+                                    save_state.n_iteration,
+                                    save_state.assy_sigma,
+                                    save_state.seed,
+                                )
+                                .set_spec(
+                                    save_state.lower_spec,
+                                    save_state.upper_spec,
+                                    save_state.target_capability,
+                                )
+                                .set_convergence(
+                                    save_state.converge,
+                                    save_state.convergence_threshold,
+                                    save_state.max_iterations,
+                                )
+                                .set_sampling(save_state.sampling)
+                                .set_importance_sampling(save_state.importance_sampling)
+                                .set_stack_equation(save_state.stack_equation)
+                                .set_vector_loop(save_state.vector_loop)
+                                .set_rss(save_state.rss_methods, save_state.mean_shift_factor),
                             file_path: path,
                             dirty: false,
                             saving: false,
@@ -546,7 +730,7 @@ impl Application for TolStack {
                     iss,
                     header: _,
                     stack_editor: _,
-                    analysis_state: _,
+                    analysis_state,
                     dirty,
                     saving,
                     file_path,
@@ -564,8 +748,9 @@ impl Application for TolStack {
                     Subscription::none()
                 };
                 let tab_field = iced_native::subscription::events().map(Message::EventOccurred);
+                let analysis = analysis_state.subscription().map(Message::Analysis);
 
-                Subscription::batch(vec![auto_save, style_reload, tab_field])
+                Subscription::batch(vec![auto_save, style_reload, tab_field, analysis])
             }
         }
     }
@@ -3,8 +3,10 @@ pub mod area_header;
 pub mod area_mc_analysis;
 pub mod area_stack_editor;
 pub mod editable_label;
+pub mod entry_correlation;
 pub mod entry_tolerance;
 pub mod filter_tolerance;
+pub mod form_allocation;
 pub mod form_new_mc_analysis;
 //pub mod form_new_tolerance;
 
@@ -13,7 +15,9 @@ pub use area_header::*;
 pub use area_mc_analysis::*;
 pub use area_stack_editor::*;
 pub use editable_label::*;
+pub use entry_correlation::CorrelationEntry;
 pub use entry_tolerance::*;
 pub use filter_tolerance::*;
+pub use form_allocation::ToleranceAllocation;
 pub use form_new_mc_analysis::*;
 //pub use form_new_tolerance::*;
@@ -13,6 +13,8 @@ pub enum HeaderAreaMessage {
     ExportCSV,
     AddTolLinear,
     AddTolFloat,
+    AddTolCompound,
+    AddTolAngular,
     Help,
 }
 
@@ -25,6 +27,8 @@ pub struct Header {
     button_save_as: button::State,
     button_add_tol_linear: button::State,
     button_add_tol_float: button::State,
+    button_add_tol_compound: button::State,
+    button_add_tol_angular: button::State,
     button_help: button::State,
 }
 impl Header {
@@ -37,6 +41,8 @@ impl Header {
             button_save_as: button::State::new(),
             button_add_tol_linear: button::State::new(),
             button_add_tol_float: button::State::new(),
+            button_add_tol_compound: button::State::new(),
+            button_add_tol_angular: button::State::new(),
             button_help: button::State::new(),
         }
     }
@@ -50,6 +56,8 @@ impl Header {
             button_save_as: _,
             button_add_tol_linear: _,
             button_add_tol_float: _,
+            button_add_tol_compound: _,
+            button_add_tol_angular: _,
             button_help: _,
         } = self;
         match message {
@@ -66,6 +74,8 @@ impl Header {
             button_save_as,
             button_add_tol_linear,
             button_add_tol_float,
+            button_add_tol_compound,
+            button_add_tol_angular,
             button_help,
         } = self;
 
@@ -92,6 +102,14 @@ impl Header {
             header_button(button_add_tol_float, "Add Float\n", icons::add(), iss)
                 .on_press(HeaderAreaMessage::AddTolFloat);
 
+        let button_add_tol_compound =
+            header_button(button_add_tol_compound, "Add Compound", icons::add(), iss)
+                .on_press(HeaderAreaMessage::AddTolCompound);
+
+        let button_add_tol_angular =
+            header_button(button_add_tol_angular, "Add Angular\n", icons::add(), iss)
+                .on_press(HeaderAreaMessage::AddTolAngular);
+
         let button_help = header_button(button_help, "Help\n", icons::help(), iss)
             .on_press(HeaderAreaMessage::Help);
 
@@ -104,6 +122,8 @@ impl Header {
                 .push(button_export)
                 .push(button_add_tol_linear)
                 .push(button_add_tol_float)
+                .push(button_add_tol_compound)
+                .push(button_add_tol_angular)
                 .push(button_help)
                 .width(Length::Fill)
                 .spacing(iss.spacing(&iss.header_button_external_spacing)),
@@ -1,11 +1,23 @@
-use crate::analysis::{monte_carlo, root_sum_square, structures};
+use crate::analysis::{
+    allocation, contribution, cost, expression::Expression, monte_carlo, root_sum_square,
+    structures, worst_case,
+};
 use crate::ui::{components::*, style};
-use iced::{Column, Command, Container, Element, Length, Row, Text};
+use histogram_chart::HistogramChart;
+use iced::{
+    button, futures, scrollable, Align, Button, Column, Command, Container, Element, Length,
+    ProgressBar, Row, Scrollable, Subscription, Text,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub enum AnalysisAreaMessage {
     NewMcAnalysisMessage(form_new_mc_analysis::Message),
-    CalculateComplete(Option<structures::AnalysisResults>),
+    Progress(u64, monte_carlo::Progress),
+    CalculateComplete(u64, Option<Box<structures::AnalysisResults>>),
+    CancelCalculation,
+    AllocationMessage(form_allocation::Message),
     //RunRssCalcs(form_new_mc_analysis::Message),
     //RunMonteCarloCalcs(form_new_mc_analysis::Message),
     //RssCalcComplete(Option<structures::RssResults>),
@@ -17,6 +29,17 @@ pub struct AnalysisState {
     pub entry_form: NewMonteCarloAnalysis,
     pub model_state: structures::State,
     pub input_stack: Vec<entry_tolerance::ToleranceEntry>,
+    /// Correlations between entries, by their position in `input_stack`
+    pub input_correlations: Vec<structures::Correlation>,
+    scroll_state: scrollable::State,
+    /// Incremented for every calculation, so results from a superseded run can be ignored
+    run_id: u64,
+    running: Option<AnalysisRun>,
+    progress: Option<monte_carlo::Progress>,
+    cancel_button: button::State,
+    allocation_form: ToleranceAllocation,
+    /// Tolerances proposed for the stack as it was when they were proposed
+    allocation: Option<Result<allocation::Allocation, allocation::AllocationError>>,
 }
 impl AnalysisState {
     pub fn new() -> Self {
@@ -27,149 +50,387 @@ impl AnalysisState {
             entry_form,
             model_state,
             input_stack: _,
+            input_correlations: _,
+            scroll_state: _,
+            run_id,
+            running,
+            progress,
+            cancel_button: _,
+            allocation_form,
+            allocation: _,
         } = self;
         match message {
             AnalysisAreaMessage::NewMcAnalysisMessage(form_new_mc_analysis::Message::Calculate) => {
+                // The stack may have changed, so any proposed tolerances are out of date.
+                self.allocation = None;
                 let simulation_input = self.build_stack();
                 if let Some(stack) = simulation_input {
-                    return Command::perform(
-                        AnalysisState::compute(stack),
-                        AnalysisAreaMessage::CalculateComplete,
-                    );
+                    // Replacing the running calculation drops its subscription, cancelling it.
+                    self.run_id += 1;
+                    self.running = Some(AnalysisRun {
+                        id: self.run_id,
+                        simulation: stack,
+                    });
+                    self.progress = None;
                 }
             }
             AnalysisAreaMessage::NewMcAnalysisMessage(message) => {
                 entry_form.update(message);
             }
-            AnalysisAreaMessage::CalculateComplete(result) => {
-                if let Some(result) = result {
-                    model_state.results = result
+            AnalysisAreaMessage::Progress(id, update) => {
+                if id == *run_id {
+                    *progress = Some(update);
+                }
+            }
+            AnalysisAreaMessage::CalculateComplete(id, result) => {
+                if id == *run_id {
+                    *running = None;
+                    *progress = None;
+                    if let Some(result) = result {
+                        model_state.results = *result
+                    }
+                }
+            }
+            AnalysisAreaMessage::CancelCalculation => {
+                *running = None;
+                *progress = None;
+            }
+            AnalysisAreaMessage::AllocationMessage(form_allocation::Message::Propose) => {
+                let target = match self.allocation_form.target {
+                    form_allocation::TargetKind::Capability => Some(
+                        allocation::AllocationTarget::Capability(self.entry_form.target_capability),
+                    ),
+                    form_allocation::TargetKind::Yield => self
+                        .allocation_form
+                        .yield_fraction()
+                        .map(allocation::AllocationTarget::Yield),
+                };
+                let method = self.allocation_form.method;
+                if let (Some(target), Some(stack)) = (target, self.build_stack()) {
+                    self.allocation = Some(allocation::run(&stack, method, target));
                 }
             }
+            AnalysisAreaMessage::AllocationMessage(message) => {
+                allocation_form.update(message);
+            }
         }
         Command::none()
     }
+
+    /// Runs the current calculation, if there is one, reporting its progress as it goes.
+    pub fn subscription(&self) -> Subscription<AnalysisAreaMessage> {
+        match &self.running {
+            Some(run) => Subscription::from_recipe(run.clone()),
+            None => Subscription::none(),
+        }
+    }
+
     pub fn view(&mut self, iss: &style::IcedStyleSheet) -> Element<AnalysisAreaMessage> {
         let AnalysisState {
             entry_form,
             model_state,
-            input_stack: _,
+            input_stack,
+            input_correlations: _,
+            scroll_state,
+            run_id: _,
+            running,
+            progress,
+            cancel_button,
+            allocation_form,
+            allocation,
         } = self;
-        let mc_default = structures::McResults::default();
-        let rss_default = structures::RssResults::default();
+        let wc_default = structures::WorstCaseResults::default();
 
-        let mc_results = match model_state.results.monte_carlo() {
-            Some(mc_result) => mc_result,
-            None => &mc_default,
+        let wc_results = match model_state.results.worst_case() {
+            Some(wc_results) => wc_results,
+            None => &wc_default,
         };
 
-        let rss_results = match model_state.results.rss() {
-            Some(rss_results) => rss_results,
-            None => &rss_default,
-        };
+        let target = model_state.parameters.target_capability;
 
-        let results_body = Column::new()
-            .push(
-                Row::new()
-                    .push(Text::new("Mean:").size(iss.text_size(&iss.results)))
-                    .push(
-                        Text::new(format!("{:.2}", mc_results.mean))
-                            .size(iss.text_size(&iss.results)),
-                    )
-                    .spacing(iss.spacing(&iss.mc_results_row_spacing)),
-            )
-            .push(
-                Row::new()
-                    .push(Text::new("Tolerance (+):").size(iss.text_size(&iss.results)))
-                    .push(
-                        Text::new(format!("{:.2}", mc_results.tolerance_pos))
-                            .size(iss.text_size(&iss.results)),
-                    )
-                    .spacing(iss.spacing(&iss.mc_results_row_spacing)),
-            )
-            .push(
-                Row::new()
-                    .push(Text::new("Tolerance (-):").size(iss.text_size(&iss.results)))
-                    .push(
-                        Text::new(format!("{:.2}", mc_results.tolerance_neg))
-                            .size(iss.text_size(&iss.results)),
-                    )
-                    .spacing(iss.spacing(&iss.mc_results_row_spacing)),
-            )
-            .push(
-                Row::new()
-                    .push(Text::new("Standard Deviation (+):").size(iss.text_size(&iss.results)))
-                    .push(
-                        Text::new(format!("{:.2}", mc_results.stddev_pos))
-                            .size(iss.text_size(&iss.results)),
-                    )
-                    .spacing(iss.spacing(&iss.mc_results_row_spacing)),
-            )
-            .push(
-                Row::new()
-                    .push(Text::new("Standard Deviation (-):").size(iss.text_size(&iss.results)))
-                    .push(
-                        Text::new(format!("{:.2}", mc_results.stddev_neg))
-                            .size(iss.text_size(&iss.results)),
-                    )
-                    .spacing(iss.spacing(&iss.mc_results_row_spacing)),
-            )
-            .push(
-                Row::new()
-                    .push(Text::new("Iterations:").size(iss.text_size(&iss.results)))
-                    .push(
-                        Text::new(format!("{}", mc_results.iterations))
-                            .size(iss.text_size(&iss.results)),
-                    )
-                    .spacing(iss.spacing(&iss.mc_results_row_spacing)),
-            )
-            .push(
-                Row::new()
-                    .push(Text::new("Worst Case Lower:").size(iss.text_size(&iss.results)))
-                    .push(
-                        Text::new(format!("{:.2}", mc_results.worst_case_lower))
-                            .size(iss.text_size(&iss.results)),
-                    )
-                    .spacing(iss.spacing(&iss.mc_results_row_spacing)),
-            )
-            .push(
-                Row::new()
-                    .push(Text::new("Worst Case Upper:").size(iss.text_size(&iss.results)))
-                    .push(
-                        Text::new(format!("{:.2}", mc_results.worst_case_upper))
-                            .size(iss.text_size(&iss.results)),
+        // Plot the simulated distribution against the other analyses and the spec limits
+        let chart: Element<AnalysisAreaMessage> = match model_state.results.monte_carlo() {
+            Some(mc_results) => {
+                let mut chart = HistogramChart::new(
+                    160,
+                    2,
+                    &mc_results.histogram,
+                    iss.color(&iss.chart_bar_color),
+                );
+                if let Some(rss_results) = model_state.results.rss().first() {
+                    chart = chart.push_band(
+                        rss_results.mean() - rss_results.tolerance_neg(),
+                        rss_results.mean() + rss_results.tolerance_pos(),
+                        iss.color(&iss.chart_rss_band_color),
+                    );
+                }
+                if let Some(wc_results) = model_state.results.worst_case() {
+                    chart = chart
+                        .push_line(wc_results.lower(), iss.color(&iss.chart_worst_case_color))
+                        .push_line(wc_results.upper(), iss.color(&iss.chart_worst_case_color));
+                }
+                let parameters = &model_state.parameters;
+                for limit in [parameters.lower_spec, parameters.upper_spec]
+                    .iter()
+                    .flatten()
+                {
+                    chart = chart.push_line(*limit, iss.color(&iss.chart_spec_color));
+                }
+                chart = chart.push_line(mc_results.mean, iss.color(&iss.chart_mean_color));
+
+                let legend = [
+                    ("Mean", &iss.chart_mean_color),
+                    ("RSS", &iss.chart_rss_band_color),
+                    ("Worst Case", &iss.chart_worst_case_color),
+                    ("Spec Limits", &iss.chart_spec_color),
+                ]
+                .iter()
+                .fold(Row::new(), |row, (label, color)| {
+                    row.push(
+                        Text::new(*label)
+                            .size(iss.text_size(&iss.results))
+                            .color(iss.color(color)),
                     )
-                    .spacing(iss.spacing(&iss.mc_results_row_spacing)),
-            )
-            .push(
-                Row::new()
-                    .push(Text::new("RSS Mean:").size(iss.text_size(&iss.results)))
+                })
+                .spacing(iss.spacing(&iss.mc_results_row_spacing));
+                // Values that fell outside the binned range are not plotted, so note them.
+                let histogram = &mc_results.histogram;
+                let legend = [("below", histogram.below), ("above", histogram.above)]
+                    .iter()
+                    .filter(|(_, count)| *count > 0)
+                    .fold(legend, |row, (side, count)| {
+                        row.push(
+                            Text::new(format!("{} not plotted {} the chart", count, side))
+                                .size(iss.text_size(&iss.results)),
+                        )
+                    });
+
+                Column::new()
                     .push(
-                        Text::new(format!("{:.2}", rss_results.mean()))
-                            .size(iss.text_size(&iss.results)),
+                        Container::new(chart)
+                            .style(iss.container(&iss.chart_container))
+                            .padding(iss.padding(&iss.chart_padding))
+                            .width(Length::Fill),
                     )
-                    .spacing(iss.spacing(&iss.mc_results_row_spacing)),
-            )
-            .push(
-                Row::new()
-                    .push(Text::new("RSS Tolerance (+):").size(iss.text_size(&iss.results)))
+                    .push(legend)
+                    .spacing(iss.spacing(&iss.mc_results_row_spacing))
+                    .into()
+            }
+            None => Column::new().into(),
+        };
+
+        let results_body = match model_state.results.monte_carlo_error() {
+            Some(error) => {
+                Column::new().push(result_row("Monte Carlo Failed:", error.to_string(), iss))
+            }
+            None => Column::new(),
+        };
+        // The simulation results are only shown when the Monte Carlo simulation was run and
+        //  succeeded.
+        let results_body = match model_state.results.monte_carlo() {
+            Some(mc_results) => {
+                let results_body = results_body
+                    .push(result_row(
+                        "Mean:",
+                        estimate(mc_results.mean, mc_results.mean_interval, 2),
+                        iss,
+                    ))
+                    .push(result_row(
+                        "Tolerance (+):",
+                        estimate(
+                            mc_results.tolerance_pos,
+                            mc_results.tolerance_pos_interval,
+                            2,
+                        ),
+                        iss,
+                    ))
+                    .push(result_row(
+                        "Tolerance (-):",
+                        estimate(
+                            mc_results.tolerance_neg,
+                            mc_results.tolerance_neg_interval,
+                            2,
+                        ),
+                        iss,
+                    ))
+                    .push(result_row(
+                        "Standard Deviation (+):",
+                        estimate(mc_results.stddev_pos, mc_results.stddev_pos_interval, 2),
+                        iss,
+                    ))
+                    .push(result_row(
+                        "Standard Deviation (-):",
+                        estimate(mc_results.stddev_neg, mc_results.stddev_neg_interval, 2),
+                        iss,
+                    ))
+                    .push(result_row(
+                        "Iterations:",
+                        format!("{}", mc_results.iterations),
+                        iss,
+                    ));
+                // Only runs that were asked to converge report whether they did
+                let results_body = match mc_results.converged {
+                    Some(converged) => results_body.push(result_row(
+                        "Converged:",
+                        if converged {
+                            String::from("Yes")
+                        } else {
+                            String::from("No, iteration cap reached")
+                        },
+                        iss,
+                    )),
+                    None => results_body,
+                };
+                let percentiles = &mc_results.percentiles;
+                let intervals = &mc_results.percentile_intervals;
+                let results_body = [
+                    ("0.135", percentiles.p0_135, intervals.p0_135),
+                    ("2.5", percentiles.p2_5, intervals.p2_5),
+                    ("50", percentiles.p50, intervals.p50),
+                    ("97.5", percentiles.p97_5, intervals.p97_5),
+                    ("99.865", percentiles.p99_865, intervals.p99_865),
+                ]
+                .iter()
+                .fold(results_body, |column, (label, value, interval)| {
+                    column.push(result_row(
+                        &format!("{}% Percentile:", label),
+                        estimate(*value, *interval, 2),
+                        iss,
+                    ))
+                });
+                results_body
+                    .push(result_row(
+                        "Skewness:",
+                        estimate(mc_results.skewness, mc_results.skewness_interval, 3),
+                        iss,
+                    ))
+                    .push(result_row(
+                        "Excess Kurtosis:",
+                        estimate(mc_results.kurtosis, mc_results.kurtosis_interval, 3),
+                        iss,
+                    ))
+                    .push(spec_summary("MC", &mc_results.spec, target, iss))
+            }
+            None => results_body,
+        };
+        let results_body = results_body
+            .push(result_row(
+                "Worst Case Nominal:",
+                format!("{:.2}", wc_results.nominal()),
+                iss,
+            ))
+            .push(result_row(
+                "Worst Case Lower:",
+                format!("{:.2}", wc_results.lower()),
+                iss,
+            ))
+            .push(result_row(
+                "Worst Case Upper:",
+                format!("{:.2}", wc_results.upper()),
+                iss,
+            ));
+        let results_body = match model_state.results.rss() {
+            [] => results_body,
+            rss_results => results_body.push(rss_table(rss_results, target, iss)),
+        };
+        let results_body = match model_state.results.monte_carlo() {
+            Some(mc_results) if !mc_results.axes.is_empty() => {
+                results_body.push(axis_table(&mc_results.axes, iss))
+            }
+            _ => results_body,
+        }
+        .spacing(iss.spacing(&iss.mc_results_col_spacing));
+
+        // The contributors, ranked from largest to smallest share of the stack variation, with the
+        //  limits of their travel in the worst case, and the cost of each and how fast its cost
+        //  rises as its tolerance band is tightened
+        let total_cost = model_state.results.total_cost();
+        let active_entries: Vec<&entry_tolerance::ToleranceEntry> =
+            input_stack.iter().filter(|entry| entry.active).collect();
+        let contributions_body = model_state.results.contributions().iter().enumerate().fold(
+            Column::new()
+                .push(Text::new("Contributions:").size(iss.text_size(&iss.results)))
+                .spacing(iss.spacing(&iss.mc_results_col_spacing)),
+            |column, (rank, contribution)| {
+                let description = match active_entries.get(contribution.index) {
+                    Some(entry) => entry.description().to_string(),
+                    None => format!("Tolerance {}", contribution.index + 1),
+                };
+                let mc_share = match contribution.mc_share {
+                    Some(share) => format!("MC {:.1}%", share * 100.0),
+                    None => String::from("MC -"),
+                };
+                let bounds = match wc_results.contributors().get(contribution.index) {
+                    Some(bounds) => format!("WC {:.2} to {:.2}", bounds.min, bounds.max),
+                    None => String::from("WC -"),
+                };
+                let cost = match model_state.results.cost(contribution.index) {
+                    Some(cost) => {
+                        format!("Cost {:.2}, Marginal {:.2}", cost.cost, cost.marginal_cost)
+                    }
+                    None => String::from("Cost -"),
+                };
+                let row = Row::new()
                     .push(
-                        Text::new(format!("{:.2}", rss_results.tolerance_pos()))
-                            .size(iss.text_size(&iss.results)),
+                        Text::new(format!("{}. {}", rank + 1, description))
+                            .size(iss.text_size(&iss.results))
+                            .width(Length::Fill),
                     )
-                    .spacing(iss.spacing(&iss.mc_results_row_spacing)),
-            )
-            .push(
-                Row::new()
-                    .push(Text::new("RSS Tolerance (-):").size(iss.text_size(&iss.results)))
                     .push(
-                        Text::new(format!("{:.2}", rss_results.tolerance_neg()))
+                        Text::new(format!("RSS {:.1}%", contribution.rss_share * 100.0))
                             .size(iss.text_size(&iss.results)),
                     )
+                    .push(Text::new(mc_share).size(iss.text_size(&iss.results)))
+                    .push(Text::new(bounds).size(iss.text_size(&iss.results)));
+                column.push(
+                    match total_cost {
+                        Some(_) => row.push(Text::new(cost).size(iss.text_size(&iss.results))),
+                        None => row,
+                    }
                     .spacing(iss.spacing(&iss.mc_results_row_spacing)),
-            )
-            .spacing(iss.spacing(&iss.mc_results_col_spacing))
-            .height(Length::Fill);
+                )
+            },
+        );
+        let contributions_body = match total_cost {
+            Some(total_cost) => contributions_body.push(result_row(
+                "Total Stack Cost:",
+                format!("{:.2}", total_cost),
+                iss,
+            )),
+            None => contributions_body,
+        };
+
+        let progress_body: Element<AnalysisAreaMessage> = if running.is_some() {
+            let progress = progress.unwrap_or_default();
+            Column::new()
+                .push(
+                    Row::new()
+                        .push(
+                            Text::new(format!(
+                                "Running: {} of {} iterations, mean {:.2}, standard deviation {:.3}",
+                                progress.completed,
+                                progress.total,
+                                progress.mean,
+                                progress.stddev
+                            ))
+                            .size(iss.text_size(&iss.results))
+                            .width(Length::Fill),
+                        )
+                        .push(
+                            Button::new(cancel_button, Text::new("Cancel"))
+                                .style(iss.button(&iss.button_destructive))
+                                .padding(10)
+                                .on_press(AnalysisAreaMessage::CancelCalculation),
+                        )
+                        .align_items(Align::Center)
+                        .spacing(iss.spacing(&iss.mc_results_row_spacing)),
+                )
+                .push(ProgressBar::new(0.0..=1.0, progress.fraction()).height(Length::Units(10)))
+                .spacing(iss.spacing(&iss.mc_results_row_spacing))
+                .into()
+        } else {
+            Column::new().into()
+        };
 
         let results_summary = Container::new(
             Column::new()
@@ -178,7 +439,24 @@ impl AnalysisState {
                         .view(&iss)
                         .map(AnalysisAreaMessage::NewMcAnalysisMessage),
                 )
-                .push(results_body)
+                .push(progress_body)
+                .push(
+                    Scrollable::new(scroll_state)
+                        .push(chart)
+                        .push(results_body)
+                        .push(contributions_body)
+                        .push(
+                            allocation_form
+                                .view(
+                                    allocation_proposal(allocation, input_stack, iss),
+                                    matches!(allocation, Some(Ok(_))),
+                                    iss,
+                                )
+                                .map(AnalysisAreaMessage::AllocationMessage),
+                        )
+                        .spacing(iss.spacing(&iss.mc_results_col_spacing))
+                        .height(Length::Fill),
+                )
                 .height(Length::Fill)
                 .spacing(iss.spacing(&iss.mc_results_col_spacing)),
         )
@@ -203,9 +481,24 @@ impl AnalysisState {
         self.model_state.clear_inputs();
         // Copy over the input parameterscalculate_message
         self.model_state.parameters.n_iterations = self.entry_form.n_iteration;
+        self.model_state.parameters.sampling = self.entry_form.sampling;
         self.model_state.parameters.assy_sigma = self.entry_form.assy_sigma;
-        // Make sure all active entries are valid
-        let mut valid = true;
+        self.model_state.parameters.seed = self.entry_form.seed;
+        self.model_state.parameters.lower_spec = self.entry_form.lower_spec.parse().ok();
+        self.model_state.parameters.upper_spec = self.entry_form.upper_spec.parse().ok();
+        self.model_state.parameters.target_capability = self.entry_form.target_capability;
+        self.model_state.parameters.importance_sampling = self.entry_form.importance_sampling;
+        self.model_state.parameters.vector_loop = self.entry_form.vector_loop;
+        self.model_state.parameters.converge = self.entry_form.converge;
+        if let Some(threshold) = self.entry_form.threshold() {
+            self.model_state.parameters.convergence_threshold = threshold;
+        }
+        self.model_state.parameters.max_iterations = self.entry_form.max_iterations;
+        self.model_state.parameters.rss_methods = self.entry_form.rss_methods.clone();
+        self.model_state.parameters.mean_shift_factor = self.entry_form.mean_shift_factor;
+        // Make sure the spec limits, the convergence threshold, and all active entries are valid
+        let mut valid = self.entry_form.spec_error().is_none()
+            && (!self.entry_form.converge || self.entry_form.threshold().is_some());
         for entry in &self.input_stack {
             if entry.active && !entry.valid {
                 valid = false;
@@ -213,11 +506,49 @@ impl AnalysisState {
         }
         // Build the tolerance stack
         if valid {
+            // Inactive entries are left out, so find the position of each entry in the loop.
+            let mut positions = Vec::with_capacity(self.input_stack.len());
             for entry in &self.input_stack {
                 if entry.active {
-                    self.model_state.add(entry.analysis_model)
+                    positions.push(Some(self.model_state.tolerance_loop.len()));
+                    self.model_state
+                        .add_with_cost(entry.analysis_model, entry.cost.clone())
+                } else {
+                    positions.push(None);
+                }
+            }
+            for correlation in &self.input_correlations {
+                let first = positions.get(correlation.first).cloned().flatten();
+                let second = positions.get(correlation.second).cloned().flatten();
+                if let (Some(first), Some(second)) = (first, second) {
+                    self.model_state.correlate(structures::Correlation {
+                        first,
+                        second,
+                        ..*correlation
+                    });
+                }
+            }
+            if let Some(error) = self.entry_form.mode_conflict() {
+                self.entry_form.equation_error = Some(error);
+                return None;
+            }
+            if !self.entry_form.stack_equation.trim().is_empty() {
+                // Contributors are named by their entries, in tolerance loop order.
+                let names: Vec<&str> = self
+                    .input_stack
+                    .iter()
+                    .filter(|entry| entry.active)
+                    .map(|entry| entry.name.as_str())
+                    .collect();
+                match Expression::parse(&self.entry_form.stack_equation, &names) {
+                    Ok(expression) => self.model_state.expression = Some(expression),
+                    Err(error) => {
+                        self.entry_form.equation_error = Some(error.to_string());
+                        return None;
+                    }
                 }
             }
+            self.entry_form.equation_error = None;
             Some(self.model_state.clone())
         } else {
             None
@@ -225,23 +556,653 @@ impl AnalysisState {
     }
 
     /// Takes a monte carlo simulatio state, constructs a new tolerance model, and runs the simulation
-    async fn compute(simulation: structures::State) -> Option<structures::AnalysisResults> {
+    async fn compute<F: FnMut(monte_carlo::Progress)>(
+        simulation: structures::State,
+        cancel: Arc<AtomicBool>,
+        progress: F,
+    ) -> Option<Box<structures::AnalysisResults>> {
         use std::time::Instant;
         // Each computation contains an owned simulation state, this allows multiple
         //  computations to be spawned independently, and run asynchronously
         let time_start = Instant::now();
-        //todo - change unwrap to a match to prevent panic
-        let mc_result = monte_carlo::run(&simulation).await.unwrap();
-        let rss_result = root_sum_square::run(&simulation).await.unwrap();
+        let wc_result = worst_case::run(&simulation).await;
+        let mut rss_results = Vec::new();
+        // A method that cannot be applied to this stack, such as dynamic RSS of a dimension whose
+        //  mean is shifted outside its tolerance, is left out of the results.
+        for method in simulation.parameters.rss_methods.iter() {
+            if let Ok(rss) = root_sum_square::run(&simulation, *method).await {
+                rss_results.push(rss);
+            }
+        }
+        // The Monte Carlo simulation is disabled by setting the number of iterations to zero.
+        let (mc_result, mc_error) = if simulation.parameters.iterations() > 0 {
+            match monte_carlo::run_with_progress(&simulation, cancel, progress).await {
+                Ok(mc_result) => (Some(mc_result), None),
+                // A cancelled run has been superseded or stopped, so there are no results.
+                Err(error) if error.is::<monte_carlo::Cancelled>() => return None,
+                // The other analyses are still reported when the simulation cannot be run.
+                Err(error) => (None, Some(error.to_string())),
+            }
+        } else {
+            (None, None)
+        };
+        let contributions = contribution::run(&simulation, mc_result.as_ref());
+        let costs = cost::run(&simulation);
         let duration = time_start.elapsed();
         println!("Simulation Duration: {:.3?}", duration,);
-        let result = (mc_result, rss_result).into();
-        Some(result)
+        let result = structures::AnalysisResults::default()
+            .with_worst_case(Some(wc_result))
+            .with_rss(rss_results)
+            .with_monte_carlo(mc_result)
+            .with_monte_carlo_error(mc_error)
+            .with_contributions(contributions)
+            .with_costs(costs);
+        Some(Box::new(result))
     }
 
-    pub fn set_inputs(&mut self, n_iterations: usize, assy_sigma: f64) -> Self {
+    pub fn set_inputs(&mut self, n_iterations: usize, assy_sigma: f64, seed: u64) -> Self {
         self.entry_form.n_iteration = n_iterations;
         self.entry_form.assy_sigma = assy_sigma;
+        self.entry_form.seed = seed;
         self.clone()
     }
+
+    pub fn set_spec(
+        &mut self,
+        lower_spec: Option<f64>,
+        upper_spec: Option<f64>,
+        target_capability: f64,
+    ) -> Self {
+        let to_string = |limit: Option<f64>| limit.map(|x| x.to_string()).unwrap_or_default();
+        self.entry_form.lower_spec = to_string(lower_spec);
+        self.entry_form.upper_spec = to_string(upper_spec);
+        self.entry_form.target_capability = target_capability;
+        self.clone()
+    }
+
+    pub fn set_convergence(
+        &mut self,
+        converge: bool,
+        convergence_threshold: f64,
+        max_iterations: usize,
+    ) -> Self {
+        self.entry_form.converge = converge;
+        self.entry_form.convergence_threshold = convergence_threshold.to_string();
+        self.entry_form.max_iterations = max_iterations;
+        self.clone()
+    }
+
+    pub fn set_sampling(&mut self, sampling: structures::SamplingStrategy) -> Self {
+        self.entry_form.sampling = sampling;
+        self.clone()
+    }
+
+    /// Takes the proposed tolerances, as the factor to scale each entry of the stack editor by,
+    /// by the position of the entry.
+    pub fn take_allocation(&mut self) -> Vec<(usize, f64)> {
+        match self.allocation.take() {
+            Some(Ok(allocation)) => self
+                .input_stack
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.active)
+                .zip(allocation.factors)
+                .filter_map(|((i, _), factor)| factor.map(|factor| (i, factor)))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn set_importance_sampling(&mut self, importance_sampling: bool) -> Self {
+        self.entry_form.importance_sampling = importance_sampling;
+        self.clone()
+    }
+
+    pub fn set_stack_equation(&mut self, stack_equation: String) -> Self {
+        self.entry_form.stack_equation = stack_equation;
+        self.clone()
+    }
+
+    pub fn set_vector_loop(&mut self, vector_loop: bool) -> Self {
+        self.entry_form.vector_loop = vector_loop;
+        self.clone()
+    }
+
+    pub fn set_rss(
+        &mut self,
+        rss_methods: Vec<structures::RssMethod>,
+        mean_shift_factor: f64,
+    ) -> Self {
+        self.entry_form.rss_methods = rss_methods;
+        self.entry_form.mean_shift_factor = mean_shift_factor;
+        self.clone()
+    }
+}
+
+/// A calculation that runs in the background as a subscription. The calculation is cancelled when
+/// the subscription is dropped, which happens when it is superseded or stopped by the user.
+#[derive(Debug, Clone)]
+struct AnalysisRun {
+    id: u64,
+    simulation: structures::State,
+}
+
+impl<H, I> iced_native::subscription::Recipe<H, I> for AnalysisRun
+where
+    H: std::hash::Hasher,
+{
+    type Output = AnalysisAreaMessage;
+
+    fn hash(&self, state: &mut H) {
+        use std::hash::Hash;
+
+        std::any::TypeId::of::<Self>().hash(state);
+        self.id.hash(state);
+    }
+
+    fn stream(
+        self: Box<Self>,
+        _input: futures::stream::BoxStream<'static, I>,
+    ) -> futures::stream::BoxStream<'static, Self::Output> {
+        use futures::stream::StreamExt;
+
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let id = self.id;
+        let simulation = self.simulation;
+        let thread_cancel = Arc::clone(&cancel);
+        std::thread::spawn(move || {
+            let progress_sender = sender.clone();
+            let result = async_std::task::block_on(AnalysisState::compute(
+                simulation,
+                thread_cancel,
+                move |progress| {
+                    // The receiver is gone if the run was cancelled, so there is no one to tell.
+                    let _ =
+                        progress_sender.unbounded_send(AnalysisAreaMessage::Progress(id, progress));
+                },
+            ));
+            let _ = sender.unbounded_send(AnalysisAreaMessage::CalculateComplete(id, result));
+        });
+        CancelOnDrop { receiver, cancel }.boxed()
+    }
+}
+
+/// Passes along the messages of a running calculation, and cancels it once dropped.
+struct CancelOnDrop {
+    receiver: futures::channel::mpsc::UnboundedReceiver<AnalysisAreaMessage>,
+    cancel: Arc<AtomicBool>,
+}
+impl futures::stream::Stream for CancelOnDrop {
+    type Item = AnalysisAreaMessage;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        std::pin::Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A labelled value in the results summary
+fn result_row<'a>(
+    label: &str,
+    value: String,
+    iss: &style::IcedStyleSheet,
+) -> Row<'a, AnalysisAreaMessage> {
+    Row::new()
+        .push(Text::new(label).size(iss.text_size(&iss.results)))
+        .push(Text::new(value).size(iss.text_size(&iss.results)))
+        .spacing(iss.spacing(&iss.mc_results_row_spacing))
+}
+
+/// Formats an estimate with the uncertainty given by its 95% confidence interval. Intervals that
+/// are not symmetric about the estimate show the distance to each end.
+fn estimate(value: f64, interval: structures::Interval, precision: usize) -> String {
+    let plus = interval.upper - value;
+    let minus = value - interval.lower;
+    if (plus - minus).abs() <= 1e-9 * value.abs().max(1.0) {
+        format!("{:.*} ± {:.*}", precision, value, precision + 2, plus)
+    } else {
+        format!(
+            "{:.*} +{:.*}/-{:.*}",
+            precision,
+            value,
+            precision + 2,
+            plus,
+            precision + 2,
+            minus
+        )
+    }
+}
+
+/// The tolerances proposed for each adjusted entry, next to their current values
+fn allocation_proposal<'a>(
+    allocation: &Option<Result<allocation::Allocation, allocation::AllocationError>>,
+    input_stack: &[entry_tolerance::ToleranceEntry],
+    iss: &style::IcedStyleSheet,
+) -> Element<'a, form_allocation::Message> {
+    let text = |value: String| Text::new(value).size(iss.text_size(&iss.results));
+    let allocation = match allocation {
+        Some(Ok(allocation)) => allocation,
+        Some(Err(error)) => return text(error.to_string()).into(),
+        None => return Column::new().into(),
+    };
+    let active_entries = input_stack.iter().filter(|entry| entry.active);
+    active_entries
+        .zip(&allocation.factors)
+        .filter_map(|(entry, factor)| {
+            let factor = (*factor)?;
+            let (current_dimension, current_pos, current_neg) = entry.scaled_linear(1.0)?;
+            let (dimension, proposed_pos, proposed_neg) = entry.scaled_linear(factor)?;
+            Some((
+                entry.description().to_string(),
+                format!("{} +{}/-{}", current_dimension, current_pos, current_neg),
+                format!("{} +{}/-{}", dimension, proposed_pos, proposed_neg),
+            ))
+        })
+        .fold(
+            Column::new().push(text(format!(
+                "Proposed stack: mean {:.4}, standard deviation {:.4}{}",
+                allocation.mean,
+                allocation.stddev,
+                match allocation.cost {
+                    Some(cost) => format!(", cost {:.2}", cost),
+                    None => String::new(),
+                }
+            ))),
+            |column, (description, current, proposed)| {
+                column.push(
+                    Row::new()
+                        .push(text(description).width(Length::Fill))
+                        .push(text(current))
+                        .push(text(String::from("->")))
+                        .push(text(proposed))
+                        .spacing(iss.spacing(&iss.mc_results_row_spacing)),
+                )
+            },
+        )
+        .spacing(iss.spacing(&iss.mc_results_col_spacing))
+        .into()
+}
+
+/// The results of each RSS method side by side, with a column per method
+fn rss_table<'a>(
+    rss_results: &[structures::RssResults],
+    target_capability: f64,
+    iss: &style::IcedStyleSheet,
+) -> Row<'a, AnalysisAreaMessage> {
+    let cell = |text: String| Text::new(text).size(iss.text_size(&iss.results));
+    let has_spec = rss_results.iter().any(|result| result.spec().is_some());
+    let mut labels = vec!["Method", "Mean", "Tolerance (+)", "Tolerance (-)"];
+    if has_spec {
+        labels.extend(&["Out of Spec (PPM)", "Cpk", "Spec Result"]);
+    }
+    let label_column = labels
+        .iter()
+        .fold(Column::new(), |column, label| {
+            column.push(cell(format!("{}:", label)))
+        })
+        .spacing(iss.spacing(&iss.mc_results_col_spacing));
+
+    rss_results
+        .iter()
+        .fold(Row::new().push(label_column), |row, result| {
+            let column = Column::new()
+                .push(cell(result.method().to_string()))
+                .push(cell(format!("{:.2}", result.mean())))
+                .push(cell(format!("{:.2}", result.tolerance_pos())))
+                .push(cell(format!("{:.2}", result.tolerance_neg())));
+            let column = match (has_spec, result.spec()) {
+                (true, Some(spec)) => {
+                    let (verdict, color) = if spec.passes(target_capability) {
+                        ("PASS", iss.color(&iss.spec_pass_color))
+                    } else {
+                        ("FAIL", iss.color(&iss.spec_fail_color))
+                    };
+                    column
+                        .push(cell(format!("{:.0}", spec.ppm())))
+                        .push(cell(format!("{:.2}", spec.cpk())))
+                        .push(cell(verdict.to_string()).color(color))
+                }
+                (true, None) => column
+                    .push(cell(String::from("-")))
+                    .push(cell(String::from("-")))
+                    .push(cell(String::from("-"))),
+                (false, _) => column,
+            };
+            row.push(column.spacing(iss.spacing(&iss.mc_results_col_spacing)))
+        })
+        .spacing(iss.spacing(&iss.mc_results_row_spacing) * 2)
+}
+
+/// The simulated spread of the vector that closes a vector loop, with a column per axis
+fn axis_table<'a>(
+    axes: &[structures::AxisResults],
+    iss: &style::IcedStyleSheet,
+) -> Row<'a, AnalysisAreaMessage> {
+    let cell = |text: String| Text::new(text).size(iss.text_size(&iss.results));
+    let label_column = [
+        "Closing Vector",
+        "Mean",
+        "Standard Deviation (+)",
+        "Standard Deviation (-)",
+        "0.135% Percentile",
+        "99.865% Percentile",
+    ]
+    .iter()
+    .fold(Column::new(), |column, label| {
+        column.push(cell(format!("{}:", label)))
+    })
+    .spacing(iss.spacing(&iss.mc_results_col_spacing));
+
+    axes.iter()
+        .fold(Row::new().push(label_column), |row, result| {
+            let column = Column::new()
+                .push(cell(result.axis.to_string()))
+                .push(cell(format!("{:.2}", result.mean)))
+                .push(cell(format!("{:.2}", result.stddev_pos)))
+                .push(cell(format!("{:.2}", result.stddev_neg)))
+                .push(cell(format!("{:.2}", result.lower_percentile)))
+                .push(cell(format!("{:.2}", result.upper_percentile)));
+            row.push(column.spacing(iss.spacing(&iss.mc_results_col_spacing)))
+        })
+        .spacing(iss.spacing(&iss.mc_results_row_spacing) * 2)
+}
+
+/// Summarizes how an analysis performed against the spec limits, if any were given
+fn spec_summary<'a>(
+    analysis: &str,
+    spec: &Option<structures::SpecResults>,
+    target_capability: f64,
+    iss: &style::IcedStyleSheet,
+) -> Column<'a, AnalysisAreaMessage> {
+    let spec = match spec {
+        Some(spec) => spec,
+        None => return Column::new(),
+    };
+    let optional = |index: Option<f64>| match index {
+        Some(index) => format!("{:.2}", index),
+        None => String::from("-"),
+    };
+    let (verdict, color) = if spec.passes(target_capability) {
+        ("PASS", iss.color(&iss.spec_pass_color))
+    } else {
+        ("FAIL", iss.color(&iss.spec_fail_color))
+    };
+    Column::new()
+        .push(
+            Row::new()
+                .push(
+                    Text::new(format!("{} Spec Result:", analysis))
+                        .size(iss.text_size(&iss.results)),
+                )
+                .push(
+                    Text::new(format!("{} (target {:.2})", verdict, target_capability))
+                        .size(iss.text_size(&iss.results))
+                        .color(color),
+                )
+                .spacing(iss.spacing(&iss.mc_results_row_spacing)),
+        )
+        .push(result_row(
+            &format!("{} Out of Spec:", analysis),
+            match spec.fraction_out_interval() {
+                Some(interval) => format!(
+                    "{:.4}% ({} PPM)",
+                    spec.fraction_out() * 100.0,
+                    estimate(spec.ppm(), interval.scale(1e6), 0)
+                ),
+                None => format!(
+                    "{:.4}% ({:.0} PPM)",
+                    spec.fraction_out() * 100.0,
+                    spec.ppm()
+                ),
+            },
+            iss,
+        ))
+        .push(result_row(
+            &format!("{} Cp / Cpk:", analysis),
+            match (spec.cp_interval(), spec.cpk_interval()) {
+                (cp_interval, Some(cpk_interval)) => format!(
+                    "{} / {}",
+                    match (spec.cp(), cp_interval) {
+                        (Some(cp), Some(interval)) => estimate(cp, interval, 2),
+                        _ => optional(spec.cp()),
+                    },
+                    estimate(spec.cpk(), cpk_interval, 2)
+                ),
+                _ => format!("{} / {:.2}", optional(spec.cp()), spec.cpk()),
+            },
+            iss,
+        ))
+        .push(result_row(
+            &format!("{} Pp / Ppk:", analysis),
+            format!("{} / {:.2}", optional(spec.pp()), spec.ppk()),
+            iss,
+        ))
+        .spacing(iss.spacing(&iss.mc_results_col_spacing))
+}
+
+mod histogram_chart {
+    // A custom widget, built the same way as the `Arrow` in the stack editor, that draws the
+    // distribution of the stack as a set of bars, with vertical markers and shaded bands drawn
+    // on top to compare the simulation against other analyses.
+    use crate::analysis::structures::Histogram;
+    use iced_graphics::{triangle::*, Backend, Defaults, Primitive, Renderer};
+    use iced_native::{
+        layout, mouse, Element, Hasher, Layout, Length, Point, Size, Vector, Widget,
+    };
+
+    enum Overlay {
+        Line {
+            position: f64,
+            color: [f32; 4],
+        },
+        Band {
+            lower: f64,
+            upper: f64,
+            color: [f32; 4],
+        },
+    }
+
+    pub struct HistogramChart {
+        height: u16,
+        line_width: u16,
+        histogram: Histogram,
+        bar_color: [f32; 4],
+        overlays: Vec<Overlay>,
+    }
+
+    impl HistogramChart {
+        pub fn new(
+            height: u16,
+            line_width: u16,
+            histogram: &Histogram,
+            color: iced::Color,
+        ) -> Self {
+            Self {
+                height,
+                line_width,
+                histogram: histogram.clone(),
+                bar_color: color.into_linear(),
+                overlays: Vec::new(),
+            }
+        }
+
+        /// Marks a single value on the chart with a vertical line
+        pub fn push_line(mut self, position: f64, color: iced::Color) -> Self {
+            self.overlays.push(Overlay::Line {
+                position,
+                color: color.into_linear(),
+            });
+            self
+        }
+
+        /// Shades the region between two values on the chart
+        pub fn push_band(mut self, lower: f64, upper: f64, color: iced::Color) -> Self {
+            self.overlays.push(Overlay::Band {
+                lower,
+                upper,
+                color: color.into_linear(),
+            });
+            self
+        }
+
+        /// The range of values shown on the chart, wide enough to fit the histogram and overlays.
+        fn range(&self) -> (f64, f64) {
+            let (mut lower, mut upper) = (self.histogram.lower, self.histogram.upper);
+            for overlay in &self.overlays {
+                let (min, max) = match overlay {
+                    Overlay::Line { position, .. } => (*position, *position),
+                    Overlay::Band { lower, upper, .. } => (*lower, *upper),
+                };
+                lower = lower.min(min);
+                upper = upper.max(max);
+            }
+            let margin = (upper - lower) * 0.02;
+            (lower - margin, upper + margin)
+        }
+    }
+
+    /// Adds an axis aligned rectangle to the mesh
+    fn push_rectangle(mesh: &mut Mesh2D, min: [f32; 2], max: [f32; 2], color: [f32; 4]) {
+        let first = mesh.vertices.len() as u32;
+        for position in [
+            [min[0], min[1]],
+            [max[0], min[1]],
+            [min[0], max[1]],
+            [max[0], max[1]],
+        ]
+        .iter()
+        {
+            mesh.vertices.push(Vertex2D {
+                position: *position,
+                color,
+            });
+        }
+        mesh.indices.extend_from_slice(&[
+            first,
+            first + 1,
+            first + 2,
+            first + 2,
+            first + 1,
+            first + 3,
+        ]);
+    }
+
+    impl<Message, B> Widget<Message, Renderer<B>> for HistogramChart
+    where
+        B: Backend,
+    {
+        fn width(&self) -> Length {
+            Length::Fill
+        }
+
+        fn height(&self) -> Length {
+            Length::Shrink
+        }
+
+        fn layout(&self, _renderer: &Renderer<B>, limits: &layout::Limits) -> layout::Node {
+            let size = limits.width(Length::Fill).resolve(Size::ZERO);
+            layout::Node::new(Size::new(size.width, f32::from(self.height)))
+        }
+
+        fn hash_layout(&self, state: &mut Hasher) {
+            use std::hash::Hash;
+
+            self.height.hash(state);
+        }
+
+        fn draw(
+            &self,
+            _renderer: &mut Renderer<B>,
+            _defaults: &Defaults,
+            layout: Layout<'_>,
+            _cursor_position: Point,
+            _viewport: &iced::Rectangle,
+        ) -> (Primitive, mouse::Interaction) {
+            let bounds = layout.bounds();
+            let height = self.height as f32;
+            let line_width = self.line_width as f32;
+            let (lower, upper) = self.range();
+            if !lower.is_finite() || !upper.is_finite() || upper <= lower {
+                return (Primitive::None, mouse::Interaction::default());
+            }
+            let to_x = |value: f64| ((value - lower) / (upper - lower)) as f32 * bounds.width;
+
+            let mut mesh = Mesh2D {
+                vertices: Vec::new(),
+                indices: Vec::new(),
+            };
+
+            // Bands are drawn first so the bars remain visible through them
+            for overlay in &self.overlays {
+                if let Overlay::Band {
+                    lower,
+                    upper,
+                    color,
+                } = overlay
+                {
+                    push_rectangle(
+                        &mut mesh,
+                        [to_x(*lower), 0.0],
+                        [to_x(*upper), height],
+                        *color,
+                    );
+                }
+            }
+
+            let max_count = self.histogram.counts.iter().cloned().max().unwrap_or(0);
+            if max_count > 0 {
+                let bin_width = self.histogram.bin_width();
+                for (bin, count) in self.histogram.counts.iter().enumerate() {
+                    let bin_lower = self.histogram.lower + bin as f64 * bin_width;
+                    let bar_height = *count as f32 / max_count as f32 * height;
+                    push_rectangle(
+                        &mut mesh,
+                        [to_x(bin_lower), height - bar_height],
+                        [to_x(bin_lower + bin_width), height],
+                        self.bar_color,
+                    );
+                }
+            }
+
+            for overlay in &self.overlays {
+                if let Overlay::Line { position, color } = overlay {
+                    let x = to_x(*position);
+                    push_rectangle(
+                        &mut mesh,
+                        [x - line_width / 2.0, 0.0],
+                        [x + line_width / 2.0, height],
+                        *color,
+                    );
+                }
+            }
+
+            (
+                Primitive::Translate {
+                    translation: Vector::new(bounds.x, bounds.y),
+                    content: Box::new(Primitive::Mesh2D {
+                        buffers: mesh,
+                        size: Size::new(bounds.width, height),
+                    }),
+                },
+                mouse::Interaction::default(),
+            )
+        }
+    }
+
+    impl<'a, Message, B> From<HistogramChart> for Element<'a, Message, Renderer<B>>
+    where
+        B: Backend,
+    {
+        fn from(chart: HistogramChart) -> Element<'a, Message, Renderer<B>> {
+            Element::new(chart)
+        }
+    }
 }
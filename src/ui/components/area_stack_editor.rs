@@ -1,11 +1,12 @@
-use crate::analysis::structures::*;
+use crate::analysis::{correlation, structures::*};
 use crate::ui::components::*;
-use crate::ui::style;
+use crate::ui::{icons, style};
 use arrow::Arrow;
 use iced::{
-    scrollable, Align, Column, Container, Element, HorizontalAlignment, Length, Row, Scrollable,
-    Text,
+    button, scrollable, Align, Button, Column, Container, Element, HorizontalAlignment, Length,
+    Row, Scrollable, Text,
 };
+use loop_diagram::LoopDiagram;
 
 #[derive(Debug, Clone)]
 pub enum StackEditorAreaMessage {
@@ -13,14 +14,23 @@ pub enum StackEditorAreaMessage {
     FilterMessage(filter_tolerance::Message),
     NewEntryMessage((String, Tolerance)),
     LabelMessage(editable_label::Message),
+    CorrelationMessage(usize, entry_correlation::Message),
+    NewCorrelation,
+    /// Scales the tolerance band of each linear entry by a factor, by the position of the entry
+    ScaleTolerances(Vec<(usize, f64)>),
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct StackEditor {
     filter: ToleranceFilter,
     pub tolerances: Vec<ToleranceEntry>,
+    /// Correlations between entries, by their position in `tolerances`
+    pub correlations: Vec<CorrelationEntry>,
     scroll_state: scrollable::State,
+    button_new_correlation: button::State,
     pub title: EditableLabel,
+    /// Whether the stack is analyzed as a vector loop, which is drawn in 2D below the entries
+    pub vector_loop: bool,
 }
 impl StackEditor {
     pub fn new() -> Self {
@@ -33,12 +43,19 @@ impl StackEditor {
         self.title.text = title;
         self.clone()
     }
+    pub fn vector_loop(&mut self, vector_loop: bool) -> Self {
+        self.vector_loop = vector_loop;
+        self.clone()
+    }
     pub fn update(&mut self, message: StackEditorAreaMessage) {
         let StackEditor {
             filter,
             tolerances,
+            correlations,
             scroll_state: _,
+            button_new_correlation: _,
             title,
+            vector_loop: _,
         } = self;
         match message {
             StackEditorAreaMessage::NewEntryMessage(tolerance) => {
@@ -60,15 +77,27 @@ impl StackEditor {
                 match &message {
                     entry_tolerance::Message::EntryDelete => {
                         tolerances.remove(i);
+                        // Correlations with the deleted entry go with it, and the entries after
+                        //  it move up by one.
+                        correlations.retain(|entry| entry.first != i && entry.second != i);
+                        for entry in correlations.iter_mut() {
+                            for index in [&mut entry.first, &mut entry.second] {
+                                if *index > i {
+                                    *index -= 1;
+                                }
+                            }
+                        }
                     }
                     entry_tolerance::Message::EntryMoveUp => {
                         if i > 0 {
-                            tolerances.swap(i, i - 1)
+                            tolerances.swap(i, i - 1);
+                            swap_correlations(correlations, i, i - 1);
                         }
                     }
                     entry_tolerance::Message::EntryMoveDown => {
                         if i < tolerances.len() - 1 {
-                            tolerances.swap(i, i + 1)
+                            tolerances.swap(i, i + 1);
+                            swap_correlations(correlations, i, i + 1);
                         }
                     }
                     entry_tolerance::Message::EntryFinishEditing => {
@@ -80,11 +109,17 @@ impl StackEditor {
                                     tolerance_pos,
                                     tolerance_neg,
                                     sigma,
+                                    cpk,
+                                    mean_shift,
+                                    mean_shift_unit,
+                                    distribution,
                                 } => {
                                     let mut sanitized_dimension = 0.0;
                                     let mut sanitized_tolerance_pos = 0.0;
                                     let mut sanitized_tolerance_neg = 0.0;
                                     let mut sanitized_sigma = 0.0;
+                                    let mut sanitized_cpk = None;
+                                    let mut sanitized_mean_shift = 0.0;
 
                                     entry.valid = true;
 
@@ -112,26 +147,80 @@ impl StackEditor {
                                             entry.valid = false;
                                         }
                                     }
-                                    match sigma.parse::<f64>() {
-                                        Ok(value) => {
-                                            sanitized_sigma = value;
+                                    // Sigma is derived from the Cpk when one is entered.
+                                    if !cpk.is_empty() {
+                                        match cpk.parse::<f64>() {
+                                            Ok(value) if value > 0.0 => {
+                                                sanitized_cpk = Some(value);
+                                                sanitized_sigma = 3.0;
+                                            }
+                                            _ => {
+                                                entry.valid = false;
+                                            }
                                         }
-                                        Err(_) => {
-                                            entry.valid = false;
+                                    } else {
+                                        match sigma.parse::<f64>() {
+                                            Ok(value) => {
+                                                sanitized_sigma = value;
+                                            }
+                                            Err(_) => {
+                                                entry.valid = false;
+                                            }
                                         }
                                     }
-                                    if entry.valid {
-                                        entry.active = true;
-                                        let linear = DimTol::new_normal(
-                                            sanitized_dimension,
-                                            sanitized_tolerance_pos,
-                                            sanitized_tolerance_neg,
-                                            sanitized_sigma,
-                                        );
-                                        let linear = Tolerance::Linear(LinearTL::new(linear));
-                                        entry.analysis_model = linear;
-                                    } else {
-                                        entry.active = false;
+                                    // An empty mean shift means the process is centered.
+                                    if !mean_shift.is_empty() {
+                                        match mean_shift.parse::<f64>() {
+                                            Ok(value) => {
+                                                sanitized_mean_shift = match mean_shift_unit {
+                                                    MeanShiftUnit::Absolute => value,
+                                                    MeanShiftUnit::Percent => {
+                                                        value / 100.0
+                                                            * (sanitized_tolerance_pos
+                                                                + sanitized_tolerance_neg)
+                                                            / 2.0
+                                                    }
+                                                };
+                                            }
+                                            Err(_) => {
+                                                entry.valid = false;
+                                            }
+                                        }
+                                    }
+                                    let linear = DimTol::new_normal(
+                                        sanitized_dimension,
+                                        sanitized_tolerance_pos,
+                                        sanitized_tolerance_neg,
+                                        sanitized_sigma,
+                                    );
+                                    match linear {
+                                        Ok(linear) if entry.valid => {
+                                            let linear = linear
+                                                .with_distribution(*distribution)
+                                                .with_mean_shift(sanitized_mean_shift);
+                                            let linear = match sanitized_cpk {
+                                                Some(cpk) => linear.with_cpk(cpk).ok(),
+                                                None if linear.is_mean_shift_valid() => {
+                                                    Some(linear)
+                                                }
+                                                None => None,
+                                            };
+                                            match linear {
+                                                Some(linear) => {
+                                                    entry.active = true;
+                                                    entry.analysis_model =
+                                                        Tolerance::Linear(LinearTL::new(linear));
+                                                }
+                                                None => {
+                                                    entry.valid = false;
+                                                    entry.active = false;
+                                                }
+                                            }
+                                        }
+                                        _ => {
+                                            entry.valid = false;
+                                            entry.active = false;
+                                        }
                                     }
                                 }
                                 FormValues::Float {
@@ -143,6 +232,8 @@ impl StackEditor {
                                     tolerance_pin_pos,
                                     tolerance_pin_neg,
                                     sigma,
+                                    distribution_hole,
+                                    distribution_pin,
                                 } => {
                                     let mut sanitized_diameter_hole = 0.0;
                                     let mut sanitized_diameter_pin = 0.0;
@@ -209,25 +300,136 @@ impl StackEditor {
                                             entry.valid = false;
                                         }
                                     }
-                                    if entry.valid {
-                                        entry.active = true;
-                                        let hole = DimTol::new_normal(
-                                            sanitized_diameter_hole,
-                                            sanitized_tolerance_hole_pos,
-                                            sanitized_tolerance_hole_neg,
-                                            sanitized_sigma,
-                                        );
-                                        let pin = DimTol::new_normal(
-                                            sanitized_diameter_pin,
-                                            sanitized_tolerance_pin_pos,
-                                            sanitized_tolerance_pin_neg,
-                                            sanitized_sigma,
-                                        );
-                                        let data = Tolerance::Float(FloatTL::new(hole, pin, 3.0));
-                                        //println!("{:#?}",data);
-                                        entry.analysis_model = data;
+                                    let hole = DimTol::new_normal(
+                                        sanitized_diameter_hole,
+                                        sanitized_tolerance_hole_pos,
+                                        sanitized_tolerance_hole_neg,
+                                        sanitized_sigma,
+                                    );
+                                    let pin = DimTol::new_normal(
+                                        sanitized_diameter_pin,
+                                        sanitized_tolerance_pin_pos,
+                                        sanitized_tolerance_pin_neg,
+                                        sanitized_sigma,
+                                    );
+                                    match (hole, pin) {
+                                        (Ok(hole), Ok(pin)) if entry.valid => {
+                                            entry.active = true;
+                                            let hole = hole.with_distribution(*distribution_hole);
+                                            let pin = pin.with_distribution(*distribution_pin);
+                                            let data =
+                                                Tolerance::Float(FloatTL::new(hole, pin, 3.0));
+                                            //println!("{:#?}",data);
+                                            entry.analysis_model = data;
+                                        }
+                                        _ => entry.valid = false,
                                     }
                                 }
+                                FormValues::Compound {
+                                    description: _,
+                                    hole,
+                                    hole_pin,
+                                    slot,
+                                    slot_pin,
+                                    slot_angle,
+                                    sigma,
+                                } => {
+                                    let parse = |input: &str| input.parse::<f64>().ok();
+                                    let dimension = |values: &entry_tolerance::DimensionValues| {
+                                        Some(
+                                            DimTol::new_normal(
+                                                parse(&values.dimension)?,
+                                                parse(&values.tolerance_pos)?,
+                                                parse(&values.tolerance_neg)?,
+                                                parse(sigma)?,
+                                            )
+                                            .ok()?
+                                            .with_distribution(values.distribution),
+                                        )
+                                    };
+                                    match (
+                                        dimension(hole),
+                                        dimension(hole_pin),
+                                        dimension(slot),
+                                        dimension(slot_pin),
+                                        parse(slot_angle),
+                                    ) {
+                                        (
+                                            Some(hole),
+                                            Some(hole_pin),
+                                            Some(slot),
+                                            Some(slot_pin),
+                                            Some(slot_angle),
+                                        ) => {
+                                            entry.valid = true;
+                                            entry.active = true;
+                                            entry.analysis_model =
+                                                Tolerance::Compound(CompoundFloatTL::new(
+                                                    hole, hole_pin, slot, slot_pin, slot_angle,
+                                                ));
+                                        }
+                                        _ => entry.valid = false,
+                                    }
+                                }
+                                FormValues::Angular {
+                                    description: _,
+                                    angle,
+                                    length,
+                                    sigma,
+                                } => {
+                                    let parse = |input: &str| input.parse::<f64>().ok();
+                                    let angle = (|| {
+                                        Some(
+                                            DimTol::new_normal(
+                                                parse(&angle.dimension)?,
+                                                parse(&angle.tolerance_pos)?,
+                                                parse(&angle.tolerance_neg)?,
+                                                parse(sigma)?,
+                                            )
+                                            .ok()?
+                                            .with_distribution(angle.distribution),
+                                        )
+                                    })();
+                                    match (angle, parse(length)) {
+                                        (Some(angle), Some(length)) => {
+                                            entry.valid = true;
+                                            entry.active = true;
+                                            entry.analysis_model =
+                                                Tolerance::Angular(AngularTL::new(angle, length));
+                                        }
+                                        _ => entry.valid = false,
+                                    }
+                                }
+                            }
+                            // A sensitivity or direction that cannot be read leaves the entry
+                            //  incomplete.
+                            match entry.sensitivity_value() {
+                                Some(sensitivity) => {
+                                    entry.analysis_model =
+                                        entry.analysis_model.with_sensitivity(sensitivity)
+                                }
+                                None => {
+                                    entry.valid = false;
+                                    entry.active = false;
+                                }
+                            }
+                            match entry.direction_value() {
+                                Some(direction) => {
+                                    entry.analysis_model =
+                                        entry.analysis_model.with_direction(direction)
+                                }
+                                None => {
+                                    entry.valid = false;
+                                    entry.active = false;
+                                }
+                            }
+                            // A cost curve that cannot be read leaves the entry incomplete.
+                            entry.cost = entry.cost_input.model();
+                            if entry.cost_input.kind != entry_tolerance::CostKind::None
+                                && entry.cost.is_none()
+                            {
+                                entry.valid = false;
+                                entry.active = false;
                             }
                         }
                     }
@@ -245,16 +447,57 @@ impl StackEditor {
                 // Pass the message into the title
                 title.update(label_message);
             }
+
+            StackEditorAreaMessage::ScaleTolerances(factors) => {
+                for (i, factor) in factors {
+                    if let Some(entry) = self.tolerances.get_mut(i) {
+                        entry.scale_tolerance(factor);
+                        // Finishing the edit checks the new values and rebuilds the model.
+                        self.update(StackEditorAreaMessage::EntryMessage(
+                            i,
+                            entry_tolerance::Message::EntryFinishEditing,
+                        ));
+                    }
+                }
+            }
+
+            StackEditorAreaMessage::NewCorrelation => {
+                if tolerances.len() > 1 {
+                    correlations.push(CorrelationEntry::new(0, 1));
+                }
+            }
+
+            StackEditorAreaMessage::CorrelationMessage(i, message) => {
+                if let entry_correlation::Message::Delete = message {
+                    if i < correlations.len() {
+                        correlations.remove(i);
+                    }
+                } else if let Some(entry) = correlations.get_mut(i) {
+                    entry.update(message);
+                }
+            }
         }
     }
     pub fn view(&mut self, iss: &style::IcedStyleSheet) -> Element<StackEditorAreaMessage> {
         let StackEditor {
             filter,
             tolerances,
+            correlations,
             scroll_state: _,
+            button_new_correlation,
             title,
+            vector_loop: _,
         } = self;
 
+        let choices: Vec<entry_correlation::EntryChoice> = tolerances
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| entry_correlation::EntryChoice {
+                index,
+                description: entry.description().to_string(),
+            })
+            .collect();
+
         let filtered_tols = tolerances
             .iter()
             .filter(|tol| filter.filter_value.matches(tol.analysis_model));
@@ -276,7 +519,8 @@ impl StackEditor {
             if tol.active && tol.valid {
                 stack_total += match tol.analysis_model {
                     Tolerance::Linear(linear) => linear.distance.dim as f32,
-                    Tolerance::Float(_) => 0.0,
+                    Tolerance::Angular(angular) => angular.offset(angular.angle.dim) as f32,
+                    Tolerance::Float(_) | Tolerance::Compound(_) => 0.0,
                 };
                 min = f32::min(min, stack_total);
                 max = f32::max(max, stack_total);
@@ -292,7 +536,8 @@ impl StackEditor {
                 // could apply a log scale to the length here.
                 let length = match tol.analysis_model {
                     Tolerance::Linear(linear) => linear.distance.dim as f32,
-                    Tolerance::Float(_) => 0.0,
+                    Tolerance::Angular(angular) => angular.offset(angular.angle.dim) as f32,
+                    Tolerance::Float(_) | Tolerance::Compound(_) => 0.0,
                 };
                 let mut viz_start = start;
                 // The bar needs to have a positive length
@@ -311,6 +556,20 @@ impl StackEditor {
             }
         }
 
+        // The mean vector of each entry, drawn head to tail when the stack is a vector loop
+        let loop_vectors: Vec<(f32, f32)> = tolerances
+            .iter()
+            .filter(|tol| tol.active && tol.valid)
+            .map(|tol| {
+                let length = tol.analysis_model.mean();
+                let direction = tol.analysis_model.direction().to_radians();
+                (
+                    (length * direction.cos()) as f32,
+                    (length * direction.sin()) as f32,
+                )
+            })
+            .collect();
+
         // Iterate over all tols, calling their .view() function and adding them to a column
         let tolerances: Element<_> = if filtered_tols.count() > 0 {
             self.tolerances
@@ -416,6 +675,8 @@ impl StackEditor {
                 Filter::Some(tol) => match tol {
                     Tolerance::Linear(_) => "No linear tolerances in the stack.",
                     Tolerance::Float(_) => "No float tolerances in the stack.",
+                    Tolerance::Compound(_) => "No compound float tolerances in the stack.",
+                    Tolerance::Angular(_) => "No angular tolerances in the stack.",
                 },
             })
         };
@@ -423,9 +684,28 @@ impl StackEditor {
         // For debug purposes:
         //let tolerances = tolerances.explain(iced::Color::BLACK);
 
-        let content = Column::new()
+        let mut content = Column::new()
             .spacing(iss.spacing(&iss.editor_tol_spacing))
             .push(tolerances);
+        if self.vector_loop {
+            content = content.push(
+                Container::new(LoopDiagram::new(
+                    loop_vectors,
+                    200,
+                    3,
+                    iss.color(&iss.editor_arrow_color),
+                    iss.color(&iss.chart_mean_color),
+                ))
+                .style(iss.container(&iss.tol_entry_container))
+                .padding(iss.padding(&iss.tol_entry_padding)),
+            );
+        }
+        let content = content.push(view_correlations(
+            choices,
+            correlations,
+            button_new_correlation,
+            iss,
+        ));
 
         /*
         let stack_title = Text::new("Tolerance Stack")
@@ -486,6 +766,97 @@ impl StackEditor {
         self.tolerances = tolerances;
         self.clone()
     }
+    pub fn correlations(&mut self, correlations: Vec<CorrelationEntry>) -> Self {
+        self.correlations = correlations;
+        self.clone()
+    }
+    /// The valid correlations between entries, by their position in `tolerances`
+    pub fn correlation_inputs(&self) -> Vec<Correlation> {
+        self.correlations
+            .iter()
+            .filter_map(|entry| entry.correlation())
+            .collect()
+    }
+}
+
+/// Keeps correlations attached to their entries when the entries at `a` and `b` trade places.
+fn swap_correlations(correlations: &mut [CorrelationEntry], a: usize, b: usize) {
+    for entry in correlations.iter_mut() {
+        for index in [&mut entry.first, &mut entry.second] {
+            if *index == a {
+                *index = b;
+            } else if *index == b {
+                *index = a;
+            }
+        }
+    }
+}
+
+/// Lists the correlations between entries, below the entries themselves.
+fn view_correlations<'a>(
+    choices: Vec<entry_correlation::EntryChoice>,
+    correlations: &'a mut [CorrelationEntry],
+    button_new_correlation: &'a mut button::State,
+    iss: &style::IcedStyleSheet,
+) -> Element<'a, StackEditorAreaMessage> {
+    let inputs: Vec<Correlation> = correlations
+        .iter()
+        .filter_map(|entry| entry.correlation())
+        .collect();
+    let consistent = correlation::is_consistent(choices.len(), &inputs);
+
+    let mut button_add = Button::new(
+        button_new_correlation,
+        Row::new()
+            .spacing(iss.spacing(&iss.tol_entry_button_spacing))
+            .push(icons::add().size(iss.text_size(&iss.tol_entry_button_text_size)))
+            .push(Text::new("Correlate").size(iss.text_size(&iss.tol_entry_button_text_size))),
+    )
+    .padding(iss.padding(&iss.tol_entry_button_padding))
+    .style(iss.button(&iss.button_action));
+    if choices.len() > 1 {
+        button_add = button_add.on_press(StackEditorAreaMessage::NewCorrelation);
+    }
+
+    let header = Row::new()
+        .push(
+            Text::new("Correlations")
+                .size(iss.text_size(&iss.tol_entry_summary_text_size))
+                .width(Length::Fill),
+        )
+        .push(button_add)
+        .padding(iss.padding(&iss.tol_entry_padding))
+        .align_items(Align::Center);
+
+    let column =
+        correlations.iter_mut().enumerate().fold(
+            Column::new()
+                .spacing(iss.spacing(&iss.editor_tol_spacing))
+                .push(header),
+            |column, (i, entry)| {
+                column.push(
+                    Container::new(entry.view(&choices, iss).map(move |message| {
+                        StackEditorAreaMessage::CorrelationMessage(i, message)
+                    }))
+                    .style(iss.container(&iss.tol_entry_container))
+                    .padding(5),
+                )
+            },
+        );
+    if consistent {
+        column.into()
+    } else {
+        column
+            .push(
+                Text::new(
+                    "These correlations cannot all hold at once, so the Monte Carlo simulation \
+                    will not run. Reduce the strength of some of them.",
+                )
+                .size(iss.text_size(&iss.tol_entry_summary_text_size))
+                .color(iss.color(&iss.spec_fail_color)),
+            )
+            .into()
+    }
 }
 
 fn empty_message(message: &str) -> Element<'static, StackEditorAreaMessage> {
@@ -735,3 +1106,168 @@ mod arrow {
         }
     }
 }
+
+mod loop_diagram {
+    use iced_graphics::{triangle::*, Backend, Defaults, Primitive, Renderer};
+    use iced_native::{
+        layout, mouse, Element, Hasher, Layout, Length, Point, Size, Vector, Widget,
+    };
+
+    /// Draws the nominal vectors of a vector loop head to tail, and the vector that closes the
+    /// loop back to its start.
+    pub struct LoopDiagram {
+        vectors: Vec<(f32, f32)>,
+        height: u16,
+        line_width: u16,
+        color: [f32; 4],
+        closing_color: [f32; 4],
+    }
+
+    impl LoopDiagram {
+        pub fn new(
+            vectors: Vec<(f32, f32)>,
+            height: u16,
+            line_width: u16,
+            color: iced::Color,
+            closing_color: iced::Color,
+        ) -> Self {
+            Self {
+                vectors,
+                height,
+                line_width,
+                color: color.into_linear(),
+                closing_color: closing_color.into_linear(),
+            }
+        }
+
+        /// The corners of each vector in the loop, starting from the origin
+        fn points(&self) -> Vec<(f32, f32)> {
+            let mut points = vec![(0.0, 0.0)];
+            for (x, y) in &self.vectors {
+                let (last_x, last_y) = points[points.len() - 1];
+                points.push((last_x + x, last_y + y));
+            }
+            points
+        }
+    }
+
+    /// A straight line of the given width between two points, as two triangles
+    fn line(from: [f32; 2], to: [f32; 2], width: f32, color: [f32; 4]) -> Mesh2D {
+        let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+        let length = (dx * dx + dy * dy).sqrt().max(f32::EPSILON);
+        // Half the line width, perpendicular to the line
+        let (nx, ny) = (-dy / length * width / 2.0, dx / length * width / 2.0);
+        Mesh2D {
+            vertices: vec![
+                Vertex2D {
+                    position: [from[0] + nx, from[1] + ny],
+                    color,
+                },
+                Vertex2D {
+                    position: [to[0] + nx, to[1] + ny],
+                    color,
+                },
+                Vertex2D {
+                    position: [from[0] - nx, from[1] - ny],
+                    color,
+                },
+                Vertex2D {
+                    position: [to[0] - nx, to[1] - ny],
+                    color,
+                },
+            ],
+            indices: vec![0, 1, 2, 2, 1, 3],
+        }
+    }
+
+    impl<Message, B> Widget<Message, Renderer<B>> for LoopDiagram
+    where
+        B: Backend,
+    {
+        fn width(&self) -> Length {
+            Length::Fill
+        }
+
+        fn height(&self) -> Length {
+            Length::Shrink
+        }
+
+        fn layout(&self, _renderer: &Renderer<B>, limits: &layout::Limits) -> layout::Node {
+            let size = limits.width(Length::Fill).resolve(Size::ZERO);
+            layout::Node::new(Size::new(size.width, f32::from(self.height)))
+        }
+
+        fn hash_layout(&self, state: &mut Hasher) {
+            use std::hash::Hash;
+
+            self.height.hash(state);
+            self.vectors.len().hash(state);
+        }
+
+        fn draw(
+            &self,
+            _renderer: &mut Renderer<B>,
+            _defaults: &Defaults,
+            layout: Layout<'_>,
+            _cursor_position: Point,
+            _viewport: &iced::Rectangle,
+        ) -> (Primitive, mouse::Interaction) {
+            let bounds = layout.bounds();
+            let line_width = self.line_width as f32;
+            let points = self.points();
+
+            // Scale the loop to fit the widget, keeping its aspect ratio, with the Y axis up.
+            let (mut min_x, mut max_x, mut min_y, mut max_y) = (0.0f32, 0.0f32, 0.0f32, 0.0f32);
+            for (x, y) in &points {
+                min_x = min_x.min(*x);
+                max_x = max_x.max(*x);
+                min_y = min_y.min(*y);
+                max_y = max_y.max(*y);
+            }
+            let margin = line_width * 2.0;
+            let scale_x = (bounds.width - 2.0 * margin) / (max_x - min_x).max(f32::EPSILON);
+            let scale_y = (bounds.height - 2.0 * margin) / (max_y - min_y).max(f32::EPSILON);
+            let scale = scale_x.min(scale_y).max(0.0);
+            let offset_x = (bounds.width - (max_x - min_x) * scale) / 2.0;
+            let offset_y = (bounds.height - (max_y - min_y) * scale) / 2.0;
+            let position = |(x, y): (f32, f32)| {
+                [
+                    offset_x + (x - min_x) * scale,
+                    bounds.height - offset_y - (y - min_y) * scale,
+                ]
+            };
+
+            let primitives: Vec<Primitive> = points
+                .windows(2)
+                .map(|pair| line(position(pair[0]), position(pair[1]), line_width, self.color))
+                .chain(std::iter::once(line(
+                    position(points[points.len() - 1]),
+                    position(points[0]),
+                    line_width,
+                    self.closing_color,
+                )))
+                .map(|buffers| Primitive::Mesh2D {
+                    buffers,
+                    size: Size::new(bounds.width, bounds.height),
+                })
+                .collect();
+
+            (
+                Primitive::Translate {
+                    translation: Vector::new(bounds.x, bounds.y),
+                    content: Box::new(Primitive::Group { primitives }),
+                },
+                mouse::Interaction::default(),
+            )
+        }
+    }
+
+    impl<'a, Message, B> From<LoopDiagram> for Element<'a, Message, Renderer<B>>
+    where
+        B: Backend,
+    {
+        fn from(diagram: LoopDiagram) -> Element<'a, Message, Renderer<B>> {
+            Element::new(diagram)
+        }
+    }
+}
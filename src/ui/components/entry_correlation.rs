@@ -0,0 +1,151 @@
+use crate::analysis::structures::Correlation;
+use crate::ui::{icons, style};
+use iced::{
+    button, pick_list, text_input, Align, Button, Element, Length, PickList, Row, Text, TextInput,
+};
+use serde_derive::*;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    FirstSelected(EntryChoice),
+    SecondSelected(EntryChoice),
+    EditedCoefficient(String),
+    Delete,
+}
+
+/// A tolerance entry that can be picked as one side of a correlation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryChoice {
+    pub index: usize,
+    pub description: String,
+}
+impl std::fmt::Display for EntryChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}. {}", self.index + 1, self.description)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct State {
+    first: pick_list::State<EntryChoice>,
+    second: pick_list::State<EntryChoice>,
+    coefficient: text_input::State,
+    button_delete: button::State,
+}
+
+/// A correlation between two entries in the stack editor, which are identified by their position
+/// in the list of tolerance entries.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CorrelationEntry {
+    pub first: usize,
+    pub second: usize,
+    pub coefficient: String,
+
+    #[serde(skip)]
+    pub state: State,
+}
+impl CorrelationEntry {
+    pub fn new(first: usize, second: usize) -> Self {
+        CorrelationEntry {
+            first,
+            second,
+            coefficient: String::from("0.5"),
+            state: State::default(),
+        }
+    }
+
+    /// The correlation this entry describes, if its coefficient is a number from -1 to 1 and it is
+    /// between two different entries.
+    pub fn correlation(&self) -> Option<Correlation> {
+        let coefficient = self.coefficient.parse::<f64>().ok()?;
+        if self.first != self.second && (-1.0..=1.0).contains(&coefficient) {
+            Some(Correlation {
+                first: self.first,
+                second: self.second,
+                coefficient,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::FirstSelected(choice) => self.first = choice.index,
+            Message::SecondSelected(choice) => self.second = choice.index,
+            Message::EditedCoefficient(input) => self.coefficient = input,
+            Message::Delete => {}
+        }
+    }
+
+    pub fn view(
+        &mut self,
+        choices: &[EntryChoice],
+        iss: &style::IcedStyleSheet,
+    ) -> Element<'_, Message> {
+        let valid = self.correlation().is_some();
+        let CorrelationEntry {
+            first,
+            second,
+            coefficient,
+            state,
+        } = self;
+        let selected = |index: usize| choices.iter().find(|choice| choice.index == index).cloned();
+
+        let view_first = PickList::new(
+            &mut state.first,
+            choices.to_vec(),
+            selected(*first),
+            Message::FirstSelected,
+        )
+        .width(Length::Fill)
+        .padding(iss.padding(&iss.tol_edit_field_padding));
+
+        let view_second = PickList::new(
+            &mut state.second,
+            choices.to_vec(),
+            selected(*second),
+            Message::SecondSelected,
+        )
+        .width(Length::Fill)
+        .padding(iss.padding(&iss.tol_edit_field_padding));
+
+        let view_coefficient = TextInput::new(
+            &mut state.coefficient,
+            "Coefficient",
+            coefficient,
+            Message::EditedCoefficient,
+        )
+        .width(Length::Units(100))
+        .padding(iss.padding(&iss.tol_edit_field_padding))
+        .size(iss.text_size(&iss.tol_edit_field_text_size));
+
+        let view_button_delete = Button::new(
+            &mut state.button_delete,
+            icons::delete().size(iss.text_size(&iss.tol_entry_button_text_size)),
+        )
+        .on_press(Message::Delete)
+        .padding(iss.padding(&iss.tol_entry_button_padding))
+        .style(iss.button(&iss.button_destructive));
+
+        let row = Row::new()
+            .padding(iss.padding(&iss.tol_entry_padding))
+            .spacing(iss.spacing(&iss.tol_entry_spacing))
+            .align_items(Align::Center)
+            .push(view_first)
+            .push(view_second)
+            .push(view_coefficient)
+            .push(view_button_delete);
+
+        if valid {
+            row.into()
+        } else {
+            row.push(
+                Text::new("Pick two entries, and a coefficient from -1 to 1")
+                    .size(iss.text_size(&iss.tol_entry_summary_text_size))
+                    .color(iss.color(&iss.spec_fail_color)),
+            )
+            .into()
+        }
+    }
+}
@@ -1,8 +1,10 @@
+use crate::analysis::cost::{self, CostModel};
+use crate::analysis::expression;
 use crate::analysis::structures::*;
 use crate::ui::{icons, style};
 use iced::{
-    button, text_input, Align, Button, Checkbox, Column, Container, Element, HorizontalAlignment,
-    Length, Row, Text, TextInput,
+    button, pick_list, text_input, Align, Button, Checkbox, Column, Container, Element,
+    HorizontalAlignment, Length, PickList, Row, Text, TextInput,
 };
 use serde_derive::*;
 
@@ -15,6 +17,8 @@ pub enum State {
     },
     Editing {
         form_tolentry: Box<FormState>,
+        stack_fields: Box<StackFields>,
+        cost_fields: Box<CostFields>,
     },
 }
 impl Default for State {
@@ -38,6 +42,10 @@ pub enum FormState {
         tolerance_pos: text_input::State,
         tolerance_neg: text_input::State,
         sigma: text_input::State,
+        cpk: text_input::State,
+        mean_shift: text_input::State,
+        mean_shift_unit: pick_list::State<MeanShiftUnit>,
+        distribution: pick_list::State<TolDistribution>,
     },
     Float {
         button_save: button::State,
@@ -50,6 +58,27 @@ pub enum FormState {
         tolerance_pin_pos: text_input::State,
         tolerance_pin_neg: text_input::State,
         sigma: text_input::State,
+        distribution_hole: pick_list::State<TolDistribution>,
+        distribution_pin: pick_list::State<TolDistribution>,
+    },
+    Compound {
+        button_save: button::State,
+        button_delete: button::State,
+        description: text_input::State,
+        hole: DimensionFields,
+        hole_pin: DimensionFields,
+        slot: DimensionFields,
+        slot_pin: DimensionFields,
+        slot_angle: text_input::State,
+        sigma: text_input::State,
+    },
+    Angular {
+        button_save: button::State,
+        button_delete: button::State,
+        description: text_input::State,
+        angle: DimensionFields,
+        length: text_input::State,
+        sigma: text_input::State,
     },
 }
 impl FormState {
@@ -63,6 +92,10 @@ impl FormState {
                 tolerance_pos: text_input::State::new(),
                 tolerance_neg: text_input::State::new(),
                 sigma: text_input::State::new(),
+                cpk: text_input::State::new(),
+                mean_shift: text_input::State::new(),
+                mean_shift_unit: pick_list::State::default(),
+                distribution: pick_list::State::default(),
             },
             Tolerance::Float(_) => FormState::Float {
                 button_save: button::State::new(),
@@ -75,6 +108,27 @@ impl FormState {
                 tolerance_pin_pos: text_input::State::new(),
                 tolerance_pin_neg: text_input::State::new(),
                 sigma: text_input::State::new(),
+                distribution_hole: pick_list::State::default(),
+                distribution_pin: pick_list::State::default(),
+            },
+            Tolerance::Compound(_) => FormState::Compound {
+                button_save: button::State::new(),
+                button_delete: button::State::new(),
+                description: text_input::State::new(),
+                hole: DimensionFields::default(),
+                hole_pin: DimensionFields::default(),
+                slot: DimensionFields::default(),
+                slot_pin: DimensionFields::default(),
+                slot_angle: text_input::State::new(),
+                sigma: text_input::State::new(),
+            },
+            Tolerance::Angular(_) => FormState::Angular {
+                button_save: button::State::new(),
+                button_delete: button::State::new(),
+                description: text_input::State::new(),
+                angle: DimensionFields::default(),
+                length: text_input::State::new(),
+                sigma: text_input::State::new(),
             },
         }
     }
@@ -88,6 +142,10 @@ impl FormState {
                 tolerance_pos: text_input::State::new(),
                 tolerance_neg: text_input::State::new(),
                 sigma: text_input::State::new(),
+                cpk: text_input::State::new(),
+                mean_shift: text_input::State::new(),
+                mean_shift_unit: pick_list::State::default(),
+                distribution: pick_list::State::default(),
             },
             Tolerance::Float(_) => FormState::Float {
                 button_save: button::State::new(),
@@ -100,6 +158,27 @@ impl FormState {
                 tolerance_pin_pos: text_input::State::new(),
                 tolerance_pin_neg: text_input::State::new(),
                 sigma: text_input::State::new(),
+                distribution_hole: pick_list::State::default(),
+                distribution_pin: pick_list::State::default(),
+            },
+            Tolerance::Compound(_) => FormState::Compound {
+                button_save: button::State::new(),
+                button_delete: button::State::new(),
+                description: text_input::State::focused(),
+                hole: DimensionFields::default(),
+                hole_pin: DimensionFields::default(),
+                slot: DimensionFields::default(),
+                slot_pin: DimensionFields::default(),
+                slot_angle: text_input::State::new(),
+                sigma: text_input::State::new(),
+            },
+            Tolerance::Angular(_) => FormState::Angular {
+                button_save: button::State::new(),
+                button_delete: button::State::new(),
+                description: text_input::State::focused(),
+                angle: DimensionFields::default(),
+                length: text_input::State::new(),
+                sigma: text_input::State::new(),
             },
         }
     }
@@ -121,6 +200,10 @@ pub enum Message {
     EditedLinearTolerancePos(String),
     EditedLinearToleranceNeg(String),
     EditedLinearSigma(String),
+    EditedLinearCpk(String),
+    EditedLinearMeanShift(String),
+    EditedLinearMeanShiftUnit(MeanShiftUnit),
+    EditedLinearDistribution(TolDistribution),
     // Float entry messages
     EditedFloatDiameterHole(String),
     EditedFloatDiameterPin(String),
@@ -129,6 +212,192 @@ pub enum Message {
     EditedFloatTolPinPos(String),
     EditedFloatTolPinNeg(String),
     EditedFloatSigma(String),
+    EditedFloatDistributionHole(TolDistribution),
+    EditedFloatDistributionPin(TolDistribution),
+    // Compound entry messages
+    EditedCompoundDimension(CompoundPart, String),
+    EditedCompoundTolPos(CompoundPart, String),
+    EditedCompoundTolNeg(CompoundPart, String),
+    EditedCompoundDistribution(CompoundPart, TolDistribution),
+    EditedCompoundSlotAngle(String),
+    EditedCompoundSigma(String),
+    // Angular entry messages
+    EditedAngularAngle(String),
+    EditedAngularTolPos(String),
+    EditedAngularTolNeg(String),
+    EditedAngularDistribution(TolDistribution),
+    EditedAngularLength(String),
+    EditedAngularSigma(String),
+    // Stack equation messages
+    EditedName(String),
+    EditedSensitivity(String),
+    EditedDirection(String),
+    // Cost messages
+    EditedCostKind(CostKind),
+    EditedCostFixed(String),
+    EditedCostCoefficient(String),
+    EditedCostRate(String),
+    EditedCostTable(String),
+}
+
+/// How the mean shift of a linear entry is entered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MeanShiftUnit {
+    #[default]
+    Absolute,
+    /// A percentage of half the tolerance band, so 100% puts the mean on a tolerance limit
+    Percent,
+}
+impl MeanShiftUnit {
+    pub const ALL: [MeanShiftUnit; 2] = [MeanShiftUnit::Absolute, MeanShiftUnit::Percent];
+}
+impl std::fmt::Display for MeanShiftUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                MeanShiftUnit::Absolute => "Absolute",
+                MeanShiftUnit::Percent => "% of Tolerance",
+            }
+        )
+    }
+}
+
+/// One of the dimensions of a compound float entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompoundPart {
+    Hole,
+    HolePin,
+    Slot,
+    SlotPin,
+}
+
+/// The fields used to edit one dimension of a compound float entry, or the angle of an angular
+/// entry
+#[derive(Debug, Clone, Default)]
+pub struct DimensionFields {
+    pub dimension: text_input::State,
+    pub tolerance_pos: text_input::State,
+    pub tolerance_neg: text_input::State,
+    pub distribution: pick_list::State<TolDistribution>,
+}
+
+/// The values entered for one dimension of a compound float entry, or the angle of an angular
+/// entry
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DimensionValues {
+    pub dimension: String,
+    pub tolerance_pos: String,
+    pub tolerance_neg: String,
+    #[serde(default)]
+    pub distribution: TolDistribution,
+}
+
+/// The shape of the manufacturing cost curve of an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CostKind {
+    #[default]
+    None,
+    Reciprocal,
+    Exponential,
+    Piecewise,
+}
+impl CostKind {
+    pub const ALL: [CostKind; 4] = [
+        CostKind::None,
+        CostKind::Reciprocal,
+        CostKind::Exponential,
+        CostKind::Piecewise,
+    ];
+}
+impl std::fmt::Display for CostKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                CostKind::None => "No Cost",
+                CostKind::Reciprocal => "Reciprocal",
+                CostKind::Exponential => "Exponential",
+                CostKind::Piecewise => "Table",
+            }
+        )
+    }
+}
+
+/// The fields used to edit how an entry enters the stack equation
+#[derive(Debug, Clone, Default)]
+pub struct StackFields {
+    pub name: text_input::State,
+    pub sensitivity: text_input::State,
+    pub direction: text_input::State,
+}
+
+/// The fields used to edit the cost curve of an entry
+#[derive(Debug, Clone, Default)]
+pub struct CostFields {
+    pub kind: pick_list::State<CostKind>,
+    pub fixed: text_input::State,
+    pub coefficient: text_input::State,
+    pub rate: text_input::State,
+    pub table: text_input::State,
+}
+
+/// The values entered for the cost curve of an entry
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CostValues {
+    pub kind: CostKind,
+    pub fixed: String,
+    pub coefficient: String,
+    pub rate: String,
+    /// Points of the cost table, as `tolerance:cost` pairs separated by commas
+    pub table: String,
+}
+impl CostValues {
+    /// The cost model described by the values, or `None` if there is no cost curve or the values
+    /// cannot be read. An empty fixed cost is taken as zero.
+    pub fn model(&self) -> Option<CostModel> {
+        let parse = |input: &str| input.parse::<f64>().ok();
+        let fixed = if self.fixed.is_empty() {
+            Some(0.0)
+        } else {
+            parse(&self.fixed)
+        };
+        let model = match self.kind {
+            CostKind::None => return None,
+            CostKind::Reciprocal => CostModel::Reciprocal {
+                fixed: fixed?,
+                coefficient: parse(&self.coefficient)?,
+            },
+            CostKind::Exponential => CostModel::Exponential {
+                fixed: fixed?,
+                coefficient: parse(&self.coefficient)?,
+                rate: parse(&self.rate)?,
+            },
+            CostKind::Piecewise => {
+                let points = self
+                    .table
+                    .split(',')
+                    .map(|point| {
+                        let mut values = point.split(':').map(|value| parse(value.trim()));
+                        match (values.next(), values.next(), values.next()) {
+                            (Some(Some(tolerance)), Some(Some(cost)), None) => {
+                                Some((tolerance, cost))
+                            }
+                            _ => None,
+                        }
+                    })
+                    .collect::<Option<Vec<(f64, f64)>>>()?;
+                return CostModel::piecewise(points);
+            }
+        };
+        if model.is_valid() {
+            Some(model)
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -139,6 +408,15 @@ pub enum FormValues {
         tolerance_pos: String,
         tolerance_neg: String,
         sigma: String,
+        /// Process capability index, which overrides `sigma` if it is entered
+        #[serde(default)]
+        cpk: String,
+        #[serde(default)]
+        mean_shift: String,
+        #[serde(default)]
+        mean_shift_unit: MeanShiftUnit,
+        #[serde(default)]
+        distribution: TolDistribution,
     },
     Float {
         description: String,
@@ -149,8 +427,47 @@ pub enum FormValues {
         tolerance_pin_pos: String,
         tolerance_pin_neg: String,
         sigma: String,
+        #[serde(default)]
+        distribution_hole: TolDistribution,
+        #[serde(default)]
+        distribution_pin: TolDistribution,
+    },
+    Compound {
+        description: String,
+        hole: DimensionValues,
+        hole_pin: DimensionValues,
+        slot: DimensionValues,
+        slot_pin: DimensionValues,
+        slot_angle: String,
+        sigma: String,
+    },
+    Angular {
+        description: String,
+        /// The angle, in degrees
+        angle: DimensionValues,
+        length: String,
+        sigma: String,
     },
 }
+impl FormValues {
+    fn compound_part(&mut self, part: CompoundPart) -> Option<&mut DimensionValues> {
+        match self {
+            FormValues::Compound {
+                hole,
+                hole_pin,
+                slot,
+                slot_pin,
+                ..
+            } => Some(match part {
+                CompoundPart::Hole => hole,
+                CompoundPart::HolePin => hole_pin,
+                CompoundPart::Slot => slot,
+                CompoundPart::SlotPin => slot_pin,
+            }),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToleranceEntry {
@@ -158,6 +475,20 @@ pub struct ToleranceEntry {
     pub analysis_model: Tolerance,
     pub active: bool,
     pub valid: bool,
+    /// The name the entry is referred to by in a stack equation
+    #[serde(default)]
+    pub name: String,
+    /// The sensitivity coefficient of the entry, as entered. Empty is taken as one.
+    #[serde(default)]
+    pub sensitivity: String,
+    /// The direction of the entry in a vector loop, in degrees, as entered. Empty is taken as zero.
+    #[serde(default)]
+    pub direction: String,
+    /// The manufacturing cost curve, as entered
+    #[serde(default)]
+    pub cost_input: CostValues,
+    #[serde(default)]
+    pub cost: Option<CostModel>,
 
     #[serde(skip)]
     pub state: State,
@@ -172,6 +503,10 @@ impl ToleranceEntry {
                     tolerance_pos: String::from(""),
                     tolerance_neg: String::from(""),
                     sigma: String::from(""),
+                    cpk: String::from(""),
+                    mean_shift: String::from(""),
+                    mean_shift_unit: MeanShiftUnit::default(),
+                    distribution: TolDistribution::default(),
                 },
                 Tolerance::Float(_) => FormValues::Float {
                     description,
@@ -182,18 +517,125 @@ impl ToleranceEntry {
                     tolerance_pin_pos: String::from(""),
                     tolerance_pin_neg: String::from(""),
                     sigma: String::from(""),
+                    distribution_hole: TolDistribution::default(),
+                    distribution_pin: TolDistribution::default(),
+                },
+                Tolerance::Compound(_) => FormValues::Compound {
+                    description,
+                    hole: DimensionValues::default(),
+                    hole_pin: DimensionValues::default(),
+                    slot: DimensionValues::default(),
+                    slot_pin: DimensionValues::default(),
+                    slot_angle: String::from("0"),
+                    sigma: String::from(""),
+                },
+                Tolerance::Angular(_) => FormValues::Angular {
+                    description,
+                    angle: DimensionValues::default(),
+                    length: String::from(""),
+                    sigma: String::from(""),
                 },
             },
             analysis_model: tolerance,
             active: false,
             valid: false,
+            name: String::new(),
+            sensitivity: String::new(),
+            direction: String::new(),
+            cost_input: CostValues::default(),
+            cost: None,
             state: State::default(),
         }
     }
 
+    pub fn description(&self) -> &str {
+        match &self.input {
+            FormValues::Linear { description, .. } => description,
+            FormValues::Float { description, .. } => description,
+            FormValues::Compound { description, .. } => description,
+            FormValues::Angular { description, .. } => description,
+        }
+    }
+
+    /// The sensitivity coefficient entered, or `None` if it cannot be read
+    pub fn sensitivity_value(&self) -> Option<f64> {
+        parse_or(&self.sensitivity, 1.0)
+    }
+
+    /// The direction entered, in degrees, or `None` if it cannot be read
+    pub fn direction_value(&self) -> Option<f64> {
+        parse_or(&self.direction, 0.0)
+    }
+
+    /// The dimension and tolerances of a linear entry after its tolerance band is scaled by
+    /// `factor` about its center, or `None` if the entry is not a valid linear dimension. The
+    /// tolerances are rounded down to four decimal places, so the entry is never looser than asked
+    /// for. The dimension only moves if the tolerance is asymmetric.
+    pub fn scaled_linear(&self, factor: f64) -> Option<(f64, f64, f64)> {
+        match &self.input {
+            FormValues::Linear {
+                dimension,
+                tolerance_pos,
+                tolerance_neg,
+                ..
+            } => {
+                let dimension = dimension.parse::<f64>().ok()?;
+                let tolerance_pos = tolerance_pos.parse::<f64>().ok()?;
+                let tolerance_neg = tolerance_neg.parse::<f64>().ok()?;
+                let (new_pos, new_neg) = (
+                    round_down(tolerance_pos * factor),
+                    round_down(tolerance_neg * factor),
+                );
+                let center = dimension + (tolerance_pos - tolerance_neg) / 2.0;
+                let new_dimension = if tolerance_pos == tolerance_neg {
+                    dimension
+                } else {
+                    ((center - (new_pos - new_neg) / 2.0) * 1e6).round() / 1e6
+                };
+                Some((new_dimension, new_pos, new_neg))
+            }
+            FormValues::Float { .. } | FormValues::Compound { .. } | FormValues::Angular { .. } => {
+                None
+            }
+        }
+    }
+
+    /// Scales the tolerance band of a linear entry by `factor` about its center. The mean shift is
+    /// scaled with the band, as the allocation assumes, whether it is entered as a length or as a
+    /// percentage of the half band.
+    pub fn scale_tolerance(&mut self, factor: f64) {
+        if let Some((new_dimension, new_pos, new_neg)) = self.scaled_linear(factor) {
+            if let FormValues::Linear {
+                dimension,
+                tolerance_pos,
+                tolerance_neg,
+                mean_shift,
+                mean_shift_unit,
+                ..
+            } = &mut self.input
+            {
+                let band = tolerance_pos.parse::<f64>().unwrap_or(0.0)
+                    + tolerance_neg.parse::<f64>().unwrap_or(0.0);
+                if let (MeanShiftUnit::Absolute, Ok(shift)) =
+                    (*mean_shift_unit, mean_shift.parse::<f64>())
+                {
+                    if band > 0.0 {
+                        let new_shift = shift * (new_pos + new_neg) / band;
+                        *mean_shift = ((new_shift * 1e6).round() / 1e6).to_string();
+                    }
+                }
+                *dimension = new_dimension.to_string();
+                *tolerance_pos = new_pos.to_string();
+                *tolerance_neg = new_neg.to_string();
+            }
+        }
+    }
+
     pub fn with_editing(mut self) -> Self {
         self.state = State::Editing {
             form_tolentry: Box::new(FormState::new(self.analysis_model)),
+            stack_fields: Box::new(StackFields::default()),
+            cost_fields: Box::new(CostFields::default()),
         };
         self
     }
@@ -210,12 +652,16 @@ impl ToleranceEntry {
             Message::EntryEdit => {
                 self.state = State::Editing {
                     form_tolentry: Box::new(FormState::new_focused(self.analysis_model)),
+                    stack_fields: Box::new(StackFields::default()),
+                    cost_fields: Box::new(CostFields::default()),
                 };
             }
             Message::EntryFinishEditing => {
                 if match &self.input {
                     FormValues::Linear { description, .. } => !description.is_empty(),
                     FormValues::Float { description, .. } => !description.is_empty(),
+                    FormValues::Compound { description, .. } => !description.is_empty(),
+                    FormValues::Angular { description, .. } => !description.is_empty(),
                 } {
                     self.state = State::default()
                 }
@@ -227,6 +673,8 @@ impl ToleranceEntry {
                 match &mut self.input {
                     FormValues::Linear { description, .. } => *description = input,
                     FormValues::Float { description, .. } => *description = input,
+                    FormValues::Compound { description, .. } => *description = input,
+                    FormValues::Angular { description, .. } => *description = input,
                 };
             }
             Message::EditedLinearDimension(input) => {
@@ -251,6 +699,29 @@ impl ToleranceEntry {
                     *sigma = NumericString::eval(sigma, &input, NumericString::Positive)
                 };
             }
+            Message::EditedLinearCpk(input) => {
+                if let FormValues::Linear { cpk, .. } = &mut self.input {
+                    *cpk = NumericString::eval(cpk, &input, NumericString::Positive)
+                };
+            }
+            Message::EditedLinearMeanShift(input) => {
+                if let FormValues::Linear { mean_shift, .. } = &mut self.input {
+                    *mean_shift = NumericString::eval(mean_shift, &input, NumericString::Number)
+                };
+            }
+            Message::EditedLinearMeanShiftUnit(input) => {
+                if let FormValues::Linear {
+                    mean_shift_unit, ..
+                } = &mut self.input
+                {
+                    *mean_shift_unit = input
+                };
+            }
+            Message::EditedLinearDistribution(input) => {
+                if let FormValues::Linear { distribution, .. } = &mut self.input {
+                    *distribution = input
+                };
+            }
             Message::EditedFloatDiameterHole(input) => {
                 if let FormValues::Float { diameter_hole, .. } = &mut self.input {
                     *diameter_hole =
@@ -304,6 +775,121 @@ impl ToleranceEntry {
                     *sigma = NumericString::eval(sigma, &input, NumericString::Positive)
                 };
             }
+            Message::EditedFloatDistributionHole(input) => {
+                if let FormValues::Float {
+                    distribution_hole, ..
+                } = &mut self.input
+                {
+                    *distribution_hole = input
+                };
+            }
+            Message::EditedFloatDistributionPin(input) => {
+                if let FormValues::Float {
+                    distribution_pin, ..
+                } = &mut self.input
+                {
+                    *distribution_pin = input
+                };
+            }
+            Message::EditedCompoundDimension(part, input) => {
+                if let Some(values) = self.input.compound_part(part) {
+                    values.dimension =
+                        NumericString::eval(&values.dimension, &input, NumericString::Positive)
+                };
+            }
+            Message::EditedCompoundTolPos(part, input) => {
+                if let Some(values) = self.input.compound_part(part) {
+                    values.tolerance_pos =
+                        NumericString::eval(&values.tolerance_pos, &input, NumericString::Positive)
+                };
+            }
+            Message::EditedCompoundTolNeg(part, input) => {
+                if let Some(values) = self.input.compound_part(part) {
+                    values.tolerance_neg =
+                        NumericString::eval(&values.tolerance_neg, &input, NumericString::Positive)
+                };
+            }
+            Message::EditedCompoundDistribution(part, input) => {
+                if let Some(values) = self.input.compound_part(part) {
+                    values.distribution = input
+                };
+            }
+            Message::EditedCompoundSlotAngle(input) => {
+                if let FormValues::Compound { slot_angle, .. } = &mut self.input {
+                    *slot_angle = NumericString::eval(slot_angle, &input, NumericString::Number)
+                };
+            }
+            Message::EditedCompoundSigma(input) => {
+                if let FormValues::Compound { sigma, .. } = &mut self.input {
+                    *sigma = NumericString::eval(sigma, &input, NumericString::Positive)
+                };
+            }
+            Message::EditedAngularAngle(input) => {
+                if let FormValues::Angular { angle, .. } = &mut self.input {
+                    angle.dimension =
+                        NumericString::eval(&angle.dimension, &input, NumericString::Number)
+                };
+            }
+            Message::EditedAngularTolPos(input) => {
+                if let FormValues::Angular { angle, .. } = &mut self.input {
+                    angle.tolerance_pos =
+                        NumericString::eval(&angle.tolerance_pos, &input, NumericString::Positive)
+                };
+            }
+            Message::EditedAngularTolNeg(input) => {
+                if let FormValues::Angular { angle, .. } = &mut self.input {
+                    angle.tolerance_neg =
+                        NumericString::eval(&angle.tolerance_neg, &input, NumericString::Positive)
+                };
+            }
+            Message::EditedAngularDistribution(input) => {
+                if let FormValues::Angular { angle, .. } = &mut self.input {
+                    angle.distribution = input
+                };
+            }
+            Message::EditedAngularLength(input) => {
+                if let FormValues::Angular { length, .. } = &mut self.input {
+                    *length = NumericString::eval(length, &input, NumericString::Number)
+                };
+            }
+            Message::EditedAngularSigma(input) => {
+                if let FormValues::Angular { sigma, .. } = &mut self.input {
+                    *sigma = NumericString::eval(sigma, &input, NumericString::Positive)
+                };
+            }
+            Message::EditedName(input) => {
+                let mut chars = input.chars();
+                let is_name = match chars.next() {
+                    Some(first) => {
+                        expression::is_name_start(first) && chars.all(expression::is_name_char)
+                    }
+                    None => true,
+                };
+                if is_name {
+                    self.name = input
+                }
+            }
+            Message::EditedSensitivity(input) => {
+                self.sensitivity =
+                    NumericString::eval(&self.sensitivity, &input, NumericString::Number)
+            }
+            Message::EditedDirection(input) => {
+                self.direction = NumericString::eval(&self.direction, &input, NumericString::Number)
+            }
+            Message::EditedCostKind(input) => self.cost_input.kind = input,
+            Message::EditedCostFixed(input) => {
+                let fixed = &mut self.cost_input.fixed;
+                *fixed = NumericString::eval(fixed, &input, NumericString::Positive)
+            }
+            Message::EditedCostCoefficient(input) => {
+                let coefficient = &mut self.cost_input.coefficient;
+                *coefficient = NumericString::eval(coefficient, &input, NumericString::Positive)
+            }
+            Message::EditedCostRate(input) => {
+                let rate = &mut self.cost_input.rate;
+                *rate = NumericString::eval(rate, &input, NumericString::Positive)
+            }
+            Message::EditedCostTable(input) => self.cost_input.table = input,
         }
     }
 
@@ -319,6 +905,8 @@ impl ToleranceEntry {
                     match &self.input {
                         FormValues::Linear { description, .. } => description,
                         FormValues::Float { description, .. } => description,
+                        FormValues::Compound { description, .. } => description,
+                        FormValues::Angular { description, .. } => description,
                     },
                     Message::EntryActive,
                 )
@@ -327,34 +915,46 @@ impl ToleranceEntry {
                 let summary = Text::new(match self.valid {
                     true => match self.analysis_model {
                         Tolerance::Linear(dim) => {
-                            if (dim.distance.tol_neg - dim.distance.tol_pos).abs() < f64::EPSILON {
-                                format!("{} +/- {}", dim.distance.dim, dim.distance.tol_pos)
-                            } else {
-                                format!(
-                                    "{} +{}/-{}",
-                                    dim.distance.dim, dim.distance.tol_pos, dim.distance.tol_neg
-                                )
-                            }
-                        }
-                        Tolerance::Float(dim) => {
-                            let hole = if (dim.hole.tol_neg - dim.hole.tol_pos).abs() < f64::EPSILON
-                            {
-                                format!("{} +/- {}", dim.hole.dim, dim.hole.tol_pos)
-                            } else {
-                                format!(
-                                    "{} +{}/-{}",
-                                    dim.hole.dim, dim.hole.tol_pos, dim.hole.tol_neg
-                                )
-                            };
-                            let pin = if (dim.pin.tol_neg - dim.pin.tol_pos).abs() < f64::EPSILON {
-                                format!("{} +/- {}", dim.pin.dim, dim.pin.tol_pos)
-                            } else {
-                                format!("{} +{}/-{}", dim.pin.dim, dim.pin.tol_pos, dim.pin.tol_neg)
-                            };
-                            format!("Hole: {}\nPin: {}", hole, pin)
+                            format!(
+                                "{}{}",
+                                dim_summary(&dim.distance),
+                                dist_summary(&dim.distance)
+                            )
                         }
+                        Tolerance::Float(dim) => format!(
+                            "Hole: {}{}\nPin: {}{}",
+                            dim_summary(&dim.hole),
+                            dist_summary(&dim.hole),
+                            dim_summary(&dim.pin),
+                            dist_summary(&dim.pin)
+                        ),
+                        Tolerance::Compound(dim) => format!(
+                            "Hole: {}{}\nHole Pin: {}{}\nSlot: {}{}\nSlot Pin: {}{}\nSlot Angle: {}°",
+                            dim_summary(&dim.hole),
+                            dist_summary(&dim.hole),
+                            dim_summary(&dim.hole_pin),
+                            dist_summary(&dim.hole_pin),
+                            dim_summary(&dim.slot),
+                            dist_summary(&dim.slot),
+                            dim_summary(&dim.slot_pin),
+                            dist_summary(&dim.slot_pin),
+                            dim.slot_angle
+                        ),
+                        Tolerance::Angular(dim) => format!(
+                            "Angle: {}°{}\nLength: {}",
+                            dim_summary(&dim.angle),
+                            dist_summary(&dim.angle),
+                            dim.length
+                        ),
                     },
                     false => "Incomplete entry".to_string(),
+                } + &stack_summary(&self.name, &self.analysis_model)
+                    + &match (&self.cost, self.valid) {
+                    (Some(model), true) => format!(
+                        "\nCost: {:.2}",
+                        model.cost(cost::tolerance_band(&self.analysis_model))
+                    ),
+                    _ => String::new(),
                 })
                 .size(iss.text_size(&iss.tol_entry_summary_text_size));
 
@@ -415,7 +1015,11 @@ impl ToleranceEntry {
 
                 row_contents.into()
             }
-            State::Editing { form_tolentry } => match &mut **form_tolentry {
+            State::Editing {
+                form_tolentry,
+                stack_fields,
+                cost_fields,
+            } => match &mut **form_tolentry {
                 FormState::Linear {
                     button_save,
                     button_delete,
@@ -424,6 +1028,10 @@ impl ToleranceEntry {
                     tolerance_pos,
                     tolerance_neg,
                     sigma,
+                    cpk,
+                    mean_shift,
+                    mean_shift_unit,
+                    distribution,
                 } => {
                     let view_button_save = Button::new(
                         button_save,
@@ -512,6 +1120,58 @@ impl ToleranceEntry {
                     .padding(iss.padding(&iss.tol_edit_field_padding))
                     .size(iss.text_size(&iss.tol_edit_field_text_size));
 
+                    let view_cpk = TextInput::new(
+                        cpk,
+                        "Optional, overrides sigma",
+                        match &self.input {
+                            FormValues::Linear { cpk, .. } => cpk,
+                            _ => "Error: tolerance type mismatch",
+                        },
+                        Message::EditedLinearCpk,
+                    )
+                    .on_submit(Message::EntryFinishEditing)
+                    .padding(iss.padding(&iss.tol_edit_field_padding))
+                    .size(iss.text_size(&iss.tol_edit_field_text_size));
+
+                    let view_mean_shift = TextInput::new(
+                        mean_shift,
+                        "Centered",
+                        match &self.input {
+                            FormValues::Linear { mean_shift, .. } => mean_shift,
+                            _ => "Error: tolerance type mismatch",
+                        },
+                        Message::EditedLinearMeanShift,
+                    )
+                    .on_submit(Message::EntryFinishEditing)
+                    .padding(iss.padding(&iss.tol_edit_field_padding))
+                    .size(iss.text_size(&iss.tol_edit_field_text_size));
+
+                    let view_mean_shift_unit = PickList::new(
+                        mean_shift_unit,
+                        &MeanShiftUnit::ALL[..],
+                        match &self.input {
+                            FormValues::Linear {
+                                mean_shift_unit, ..
+                            } => Some(*mean_shift_unit),
+                            _ => None,
+                        },
+                        Message::EditedLinearMeanShiftUnit,
+                    )
+                    .padding(iss.padding(&iss.tol_edit_field_padding))
+                    .text_size(iss.text_size(&iss.tol_edit_field_text_size));
+
+                    let view_distribution = PickList::new(
+                        distribution,
+                        &TolDistribution::ALL[..],
+                        match &self.input {
+                            FormValues::Linear { distribution, .. } => Some(*distribution),
+                            _ => None,
+                        },
+                        Message::EditedLinearDistribution,
+                    )
+                    .padding(iss.padding(&iss.tol_edit_field_padding))
+                    .text_size(iss.text_size(&iss.tol_edit_field_text_size));
+
                     let row_header = Row::new()
                         .push(
                             Text::new("Editing Linear Tolerance")
@@ -571,6 +1231,34 @@ impl ToleranceEntry {
                         .spacing(iss.spacing(&iss.tol_edit_label_spacing))
                         .align_items(Align::Center);
 
+                    let row_cpk = Row::new()
+                        .push(Column::new().width(Length::Units(20)))
+                        .push(Text::new("Cpk:").size(iss.text_size(&iss.tol_edit_label_text_size)))
+                        .push(view_cpk)
+                        .spacing(iss.spacing(&iss.tol_edit_label_spacing))
+                        .align_items(Align::Center);
+
+                    let row_mean_shift = Row::new()
+                        .push(Column::new().width(Length::Units(20)))
+                        .push(
+                            Text::new("Mean Shift:")
+                                .size(iss.text_size(&iss.tol_edit_label_text_size)),
+                        )
+                        .push(view_mean_shift)
+                        .push(view_mean_shift_unit)
+                        .spacing(iss.spacing(&iss.tol_edit_label_spacing))
+                        .align_items(Align::Center);
+
+                    let row_distribution = Row::new()
+                        .push(Column::new().width(Length::Units(20)))
+                        .push(
+                            Text::new("Distribution:")
+                                .size(iss.text_size(&iss.tol_edit_label_text_size)),
+                        )
+                        .push(view_distribution)
+                        .spacing(iss.spacing(&iss.tol_edit_label_spacing))
+                        .align_items(Align::Center);
+
                     let row_buttons = Row::new()
                         .push(view_button_delete)
                         .push(view_button_save)
@@ -585,6 +1273,17 @@ impl ToleranceEntry {
                         .push(row_tolerance_pos)
                         .push(row_tolerance_neg)
                         .push(row_sigma)
+                        .push(row_cpk)
+                        .push(row_mean_shift)
+                        .push(row_distribution)
+                        .push(stack_rows(
+                            stack_fields,
+                            &self.name,
+                            &self.sensitivity,
+                            &self.direction,
+                            iss,
+                        ))
+                        .push(cost_rows(cost_fields, &self.cost_input, iss))
                         .push(Row::new().height(Length::Units(5)))
                         .push(row_buttons)
                         .spacing(iss.spacing(&iss.tol_edit_vspacing))
@@ -605,6 +1304,8 @@ impl ToleranceEntry {
                     tolerance_pin_pos,
                     tolerance_pin_neg,
                     sigma,
+                    distribution_hole,
+                    distribution_pin,
                 } => {
                     let view_button_save = Button::new(
                         button_save,
@@ -740,6 +1441,34 @@ impl ToleranceEntry {
                     .padding(iss.padding(&iss.tol_edit_field_padding))
                     .size(iss.text_size(&iss.tol_edit_field_text_size));
 
+                    let view_distribution_hole = PickList::new(
+                        distribution_hole,
+                        &TolDistribution::ALL[..],
+                        match &self.input {
+                            FormValues::Float {
+                                distribution_hole, ..
+                            } => Some(*distribution_hole),
+                            _ => None,
+                        },
+                        Message::EditedFloatDistributionHole,
+                    )
+                    .padding(iss.padding(&iss.tol_edit_field_padding))
+                    .text_size(iss.text_size(&iss.tol_edit_field_text_size));
+
+                    let view_distribution_pin = PickList::new(
+                        distribution_pin,
+                        &TolDistribution::ALL[..],
+                        match &self.input {
+                            FormValues::Float {
+                                distribution_pin, ..
+                            } => Some(*distribution_pin),
+                            _ => None,
+                        },
+                        Message::EditedFloatDistributionPin,
+                    )
+                    .padding(iss.padding(&iss.tol_edit_field_padding))
+                    .text_size(iss.text_size(&iss.tol_edit_field_text_size));
+
                     let row_header = Row::new()
                         .push(
                             Text::new("Editing Float Tolerance")
@@ -820,6 +1549,26 @@ impl ToleranceEntry {
                         .spacing(iss.spacing(&iss.tol_edit_label_spacing))
                         .align_items(Align::Center);
 
+                    let row_distribution_hole = Row::new()
+                        .push(Column::new().width(Length::Units(20)))
+                        .push(
+                            Text::new("Hole Distribution:")
+                                .size(iss.text_size(&iss.tol_edit_label_text_size)),
+                        )
+                        .push(view_distribution_hole)
+                        .spacing(iss.spacing(&iss.tol_edit_label_spacing))
+                        .align_items(Align::Center);
+
+                    let row_distribution_pin = Row::new()
+                        .push(Column::new().width(Length::Units(20)))
+                        .push(
+                            Text::new("Pin Distribution:")
+                                .size(iss.text_size(&iss.tol_edit_label_text_size)),
+                        )
+                        .push(view_distribution_pin)
+                        .spacing(iss.spacing(&iss.tol_edit_label_spacing))
+                        .align_items(Align::Center);
+
                     let row_sigma = Row::new()
                         .push(Column::new().width(Length::Units(20)))
                         .push(
@@ -843,11 +1592,335 @@ impl ToleranceEntry {
                         .push(row_diameter_hole)
                         .push(row_tolerance_hole_pos)
                         .push(row_tolerance_hole_neg)
+                        .push(row_distribution_hole)
                         .push(Text::new("Pin Dimensions"))
                         .push(row_diameter_pin)
                         .push(row_tolerance_pin_pos)
                         .push(row_tolerance_pin_neg)
+                        .push(row_distribution_pin)
                         .push(row_sigma)
+                        .push(stack_rows(
+                            stack_fields,
+                            &self.name,
+                            &self.sensitivity,
+                            &self.direction,
+                            iss,
+                        ))
+                        .push(cost_rows(cost_fields, &self.cost_input, iss))
+                        .push(Row::new().height(Length::Units(5)))
+                        .push(row_buttons)
+                        .spacing(iss.spacing(&iss.tol_edit_vspacing))
+                        .padding(iss.padding(&iss.tol_edit_padding));
+
+                    Container::new(entry_contents)
+                        .style(iss.container(&iss.tol_entry_container))
+                        .into()
+                }
+                FormState::Compound {
+                    button_save,
+                    button_delete,
+                    description,
+                    hole,
+                    hole_pin,
+                    slot,
+                    slot_pin,
+                    slot_angle,
+                    sigma,
+                } => {
+                    let values = match &self.input {
+                        FormValues::Compound {
+                            description,
+                            hole,
+                            hole_pin,
+                            slot,
+                            slot_pin,
+                            slot_angle,
+                            sigma,
+                        } => Some((
+                            description,
+                            hole,
+                            hole_pin,
+                            slot,
+                            slot_pin,
+                            slot_angle,
+                            sigma,
+                        )),
+                        _ => None,
+                    };
+                    let mismatch = String::from("Error: tolerance type mismatch");
+                    let empty = DimensionValues::default();
+                    let (
+                        value_description,
+                        value_hole,
+                        value_hole_pin,
+                        value_slot,
+                        value_slot_pin,
+                        value_slot_angle,
+                        value_sigma,
+                    ) = values.unwrap_or((
+                        &mismatch, &empty, &empty, &empty, &empty, &mismatch, &mismatch,
+                    ));
+
+                    let view_button_save = Button::new(
+                        button_save,
+                        Row::new()
+                            .spacing(10)
+                            .push(icons::check())
+                            .push(Text::new("Save")),
+                    )
+                    .on_press(Message::EntryFinishEditing)
+                    .padding(10)
+                    .style(iss.button(&iss.button_constructive));
+
+                    let view_button_delete = Button::new(
+                        button_delete,
+                        Row::new()
+                            .spacing(10)
+                            .push(icons::delete())
+                            .push(Text::new("Delete")),
+                    )
+                    .on_press(Message::EntryDelete)
+                    .padding(10)
+                    .style(iss.button(&iss.button_destructive));
+
+                    let view_description = TextInput::new(
+                        description,
+                        "Enter a description",
+                        value_description,
+                        Message::EditedDescription,
+                    )
+                    .on_submit(Message::EntryFinishEditing)
+                    .padding(iss.padding(&iss.tol_edit_field_padding))
+                    .size(iss.text_size(&iss.tol_edit_field_text_size));
+
+                    let view_slot_angle = TextInput::new(
+                        slot_angle,
+                        "Enter a value",
+                        value_slot_angle,
+                        Message::EditedCompoundSlotAngle,
+                    )
+                    .on_submit(Message::EntryFinishEditing)
+                    .padding(iss.padding(&iss.tol_edit_field_padding))
+                    .size(iss.text_size(&iss.tol_edit_field_text_size));
+
+                    let view_sigma = TextInput::new(
+                        sigma,
+                        "Enter a value",
+                        value_sigma,
+                        Message::EditedCompoundSigma,
+                    )
+                    .on_submit(Message::EntryFinishEditing)
+                    .padding(iss.padding(&iss.tol_edit_field_padding))
+                    .size(iss.text_size(&iss.tol_edit_field_text_size));
+
+                    let row_header = Row::new()
+                        .push(
+                            Text::new("Editing Compound Float Tolerance")
+                                .size(iss.text_size(&iss.tol_edit_heading_text_size))
+                                .width(Length::Fill)
+                                .horizontal_alignment(HorizontalAlignment::Left),
+                        )
+                        .spacing(iss.spacing(&iss.tol_edit_label_spacing))
+                        .align_items(Align::Center);
+
+                    let row_description = form_row("Description:", view_description, iss);
+                    let row_slot_angle = form_row("Slot Angle (deg):", view_slot_angle, iss);
+                    let row_sigma = form_row("Sigma:", view_sigma, iss);
+
+                    let row_buttons = Row::new()
+                        .push(view_button_delete)
+                        .push(view_button_save)
+                        .spacing(iss.spacing(&iss.tol_edit_label_spacing))
+                        .align_items(Align::Center);
+
+                    let entry_contents = Column::new()
+                        .push(row_header)
+                        .push(Row::new().height(Length::Units(5)))
+                        .push(row_description)
+                        .push(compound_part_rows(
+                            "Round Hole",
+                            CompoundPart::Hole,
+                            hole,
+                            value_hole,
+                            iss,
+                        ))
+                        .push(compound_part_rows(
+                            "Round Hole Pin",
+                            CompoundPart::HolePin,
+                            hole_pin,
+                            value_hole_pin,
+                            iss,
+                        ))
+                        .push(compound_part_rows(
+                            "Slot Width",
+                            CompoundPart::Slot,
+                            slot,
+                            value_slot,
+                            iss,
+                        ))
+                        .push(compound_part_rows(
+                            "Slot Pin",
+                            CompoundPart::SlotPin,
+                            slot_pin,
+                            value_slot_pin,
+                            iss,
+                        ))
+                        .push(row_slot_angle)
+                        .push(row_sigma)
+                        .push(stack_rows(
+                            stack_fields,
+                            &self.name,
+                            &self.sensitivity,
+                            &self.direction,
+                            iss,
+                        ))
+                        .push(cost_rows(cost_fields, &self.cost_input, iss))
+                        .push(Row::new().height(Length::Units(5)))
+                        .push(row_buttons)
+                        .spacing(iss.spacing(&iss.tol_edit_vspacing))
+                        .padding(iss.padding(&iss.tol_edit_padding));
+
+                    Container::new(entry_contents)
+                        .style(iss.container(&iss.tol_entry_container))
+                        .into()
+                }
+                FormState::Angular {
+                    button_save,
+                    button_delete,
+                    description,
+                    angle,
+                    length,
+                    sigma,
+                } => {
+                    let values = match &self.input {
+                        FormValues::Angular {
+                            description,
+                            angle,
+                            length,
+                            sigma,
+                        } => Some((description, angle, length, sigma)),
+                        _ => None,
+                    };
+                    let mismatch = String::from("Error: tolerance type mismatch");
+                    let empty = DimensionValues::default();
+                    let (value_description, value_angle, value_length, value_sigma) =
+                        values.unwrap_or((&mismatch, &empty, &mismatch, &mismatch));
+
+                    let view_button_save = Button::new(
+                        button_save,
+                        Row::new()
+                            .spacing(10)
+                            .push(icons::check())
+                            .push(Text::new("Save")),
+                    )
+                    .on_press(Message::EntryFinishEditing)
+                    .padding(10)
+                    .style(iss.button(&iss.button_constructive));
+
+                    let view_button_delete = Button::new(
+                        button_delete,
+                        Row::new()
+                            .spacing(10)
+                            .push(icons::delete())
+                            .push(Text::new("Delete")),
+                    )
+                    .on_press(Message::EntryDelete)
+                    .padding(10)
+                    .style(iss.button(&iss.button_destructive));
+
+                    let text_input =
+                        |state, placeholder: &str, value: &str, message: fn(String) -> Message| {
+                            TextInput::new(state, placeholder, value, message)
+                                .on_submit(Message::EntryFinishEditing)
+                                .padding(iss.padding(&iss.tol_edit_field_padding))
+                                .size(iss.text_size(&iss.tol_edit_field_text_size))
+                        };
+                    let DimensionFields {
+                        dimension,
+                        tolerance_pos,
+                        tolerance_neg,
+                        distribution,
+                    } = angle;
+
+                    let view_description = text_input(
+                        description,
+                        "Enter a description",
+                        value_description,
+                        Message::EditedDescription,
+                    );
+                    let view_angle = text_input(
+                        dimension,
+                        "Enter a value",
+                        &value_angle.dimension,
+                        Message::EditedAngularAngle,
+                    );
+                    let view_tolerance_pos = text_input(
+                        tolerance_pos,
+                        "Enter a value",
+                        &value_angle.tolerance_pos,
+                        Message::EditedAngularTolPos,
+                    );
+                    let view_tolerance_neg = text_input(
+                        tolerance_neg,
+                        "Enter a value",
+                        &value_angle.tolerance_neg,
+                        Message::EditedAngularTolNeg,
+                    );
+                    let view_distribution = PickList::new(
+                        distribution,
+                        &TolDistribution::ALL[..],
+                        Some(value_angle.distribution),
+                        Message::EditedAngularDistribution,
+                    )
+                    .padding(iss.padding(&iss.tol_edit_field_padding))
+                    .text_size(iss.text_size(&iss.tol_edit_field_text_size));
+                    let view_length = text_input(
+                        length,
+                        "Distance from the pivot",
+                        value_length,
+                        Message::EditedAngularLength,
+                    );
+                    let view_sigma = text_input(
+                        sigma,
+                        "Enter a value",
+                        value_sigma,
+                        Message::EditedAngularSigma,
+                    );
+
+                    let row_header = Row::new()
+                        .push(
+                            Text::new("Editing Angular Tolerance")
+                                .size(iss.text_size(&iss.tol_edit_heading_text_size))
+                                .width(Length::Fill)
+                                .horizontal_alignment(HorizontalAlignment::Left),
+                        )
+                        .spacing(iss.spacing(&iss.tol_edit_label_spacing))
+                        .align_items(Align::Center);
+
+                    let row_buttons = Row::new()
+                        .push(view_button_delete)
+                        .push(view_button_save)
+                        .spacing(iss.spacing(&iss.tol_edit_label_spacing))
+                        .align_items(Align::Center);
+
+                    let entry_contents = Column::new()
+                        .push(row_header)
+                        .push(Row::new().height(Length::Units(5)))
+                        .push(form_row("Description:", view_description, iss))
+                        .push(form_row("Angle (deg):", view_angle, iss))
+                        .push(form_row("+ Tolerance:", view_tolerance_pos, iss))
+                        .push(form_row("- Tolerance:", view_tolerance_neg, iss))
+                        .push(form_row("Distribution:", view_distribution, iss))
+                        .push(form_row("Length:", view_length, iss))
+                        .push(form_row("Sigma:", view_sigma, iss))
+                        .push(stack_rows(
+                            stack_fields,
+                            &self.name,
+                            &self.sensitivity,
+                            &self.direction,
+                            iss,
+                        ))
+                        .push(cost_rows(cost_fields, &self.cost_input, iss))
                         .push(Row::new().height(Length::Units(5)))
                         .push(row_buttons)
                         .spacing(iss.spacing(&iss.tol_edit_vspacing))
@@ -862,6 +1935,253 @@ impl ToleranceEntry {
     }
 }
 
+/// A labelled field of an entry form.
+fn form_row<'a>(
+    label: &str,
+    field: impl Into<Element<'a, Message>>,
+    iss: &style::IcedStyleSheet,
+) -> Row<'a, Message> {
+    Row::new()
+        .push(Column::new().width(Length::Units(20)))
+        .push(Text::new(label).size(iss.text_size(&iss.tol_edit_label_text_size)))
+        .push(field)
+        .spacing(iss.spacing(&iss.tol_edit_label_spacing))
+        .align_items(Align::Center)
+}
+
+/// The fields used to edit one dimension of a compound float entry, under a heading.
+fn compound_part_rows<'a>(
+    heading: &str,
+    part: CompoundPart,
+    fields: &'a mut DimensionFields,
+    values: &DimensionValues,
+    iss: &style::IcedStyleSheet,
+) -> Column<'a, Message> {
+    let text_input = |state, value: &str, message: fn(CompoundPart, String) -> Message| {
+        TextInput::new(state, "Enter a value", value, move |input| {
+            message(part, input)
+        })
+        .on_submit(Message::EntryFinishEditing)
+        .padding(iss.padding(&iss.tol_edit_field_padding))
+        .size(iss.text_size(&iss.tol_edit_field_text_size))
+    };
+    let DimensionFields {
+        dimension,
+        tolerance_pos,
+        tolerance_neg,
+        distribution,
+    } = fields;
+
+    let view_dimension = text_input(
+        dimension,
+        &values.dimension,
+        Message::EditedCompoundDimension,
+    );
+    let view_tolerance_pos = text_input(
+        tolerance_pos,
+        &values.tolerance_pos,
+        Message::EditedCompoundTolPos,
+    );
+    let view_tolerance_neg = text_input(
+        tolerance_neg,
+        &values.tolerance_neg,
+        Message::EditedCompoundTolNeg,
+    );
+    let view_distribution = PickList::new(
+        distribution,
+        &TolDistribution::ALL[..],
+        Some(values.distribution),
+        move |input| Message::EditedCompoundDistribution(part, input),
+    )
+    .padding(iss.padding(&iss.tol_edit_field_padding))
+    .text_size(iss.text_size(&iss.tol_edit_field_text_size));
+
+    Column::new()
+        .push(Text::new(format!("{} Dimensions", heading)))
+        .push(form_row("Dimension:", view_dimension, iss))
+        .push(form_row("+ Tolerance:", view_tolerance_pos, iss))
+        .push(form_row("- Tolerance:", view_tolerance_neg, iss))
+        .push(form_row("Distribution:", view_distribution, iss))
+        .spacing(iss.spacing(&iss.tol_edit_vspacing))
+}
+
+/// The fields used to edit the name and sensitivity the entry enters the stack equation with, and
+/// its direction in a vector loop.
+fn stack_rows<'a>(
+    fields: &'a mut StackFields,
+    name: &str,
+    sensitivity: &str,
+    direction: &str,
+    iss: &style::IcedStyleSheet,
+) -> Column<'a, Message> {
+    let text_input = |state, placeholder: &str, value: &str, message: fn(String) -> Message| {
+        TextInput::new(state, placeholder, value, message)
+            .on_submit(Message::EntryFinishEditing)
+            .padding(iss.padding(&iss.tol_edit_field_padding))
+            .size(iss.text_size(&iss.tol_edit_field_text_size))
+    };
+    let StackFields {
+        name: name_field,
+        sensitivity: sensitivity_field,
+        direction: direction_field,
+    } = fields;
+
+    Column::new()
+        .push(form_row(
+            "Name:",
+            text_input(
+                name_field,
+                "Used in the stack equation",
+                name,
+                Message::EditedName,
+            ),
+            iss,
+        ))
+        .push(form_row(
+            "Sensitivity:",
+            text_input(
+                sensitivity_field,
+                "1",
+                sensitivity,
+                Message::EditedSensitivity,
+            ),
+            iss,
+        ))
+        .push(form_row(
+            "Direction (deg):",
+            text_input(direction_field, "0", direction, Message::EditedDirection),
+            iss,
+        ))
+        .spacing(iss.spacing(&iss.tol_edit_vspacing))
+}
+
+/// The fields used to edit the cost curve of an entry, showing only those used by its kind.
+fn cost_rows<'a>(
+    fields: &'a mut CostFields,
+    values: &CostValues,
+    iss: &style::IcedStyleSheet,
+) -> Column<'a, Message> {
+    let text_input = |state, placeholder: &str, value: &str, message: fn(String) -> Message| {
+        TextInput::new(state, placeholder, value, message)
+            .on_submit(Message::EntryFinishEditing)
+            .padding(iss.padding(&iss.tol_edit_field_padding))
+            .size(iss.text_size(&iss.tol_edit_field_text_size))
+    };
+    let CostFields {
+        kind,
+        fixed,
+        coefficient,
+        rate,
+        table,
+    } = fields;
+
+    let view_kind = PickList::new(
+        kind,
+        &CostKind::ALL[..],
+        Some(values.kind),
+        Message::EditedCostKind,
+    )
+    .padding(iss.padding(&iss.tol_edit_field_padding))
+    .text_size(iss.text_size(&iss.tol_edit_field_text_size));
+    let rows = Column::new()
+        .push(form_row("Cost Curve:", view_kind, iss))
+        .spacing(iss.spacing(&iss.tol_edit_vspacing));
+    let fixed_row = |fixed| {
+        form_row(
+            "Fixed Cost:",
+            text_input(fixed, "0", &values.fixed, Message::EditedCostFixed),
+            iss,
+        )
+    };
+    let coefficient_row = |coefficient| {
+        form_row(
+            "Coefficient:",
+            text_input(
+                coefficient,
+                "Enter a value",
+                &values.coefficient,
+                Message::EditedCostCoefficient,
+            ),
+            iss,
+        )
+    };
+
+    match values.kind {
+        CostKind::None => rows,
+        CostKind::Reciprocal => rows
+            .push(Text::new("Cost = Fixed + Coefficient / Tolerance Band"))
+            .push(fixed_row(fixed))
+            .push(coefficient_row(coefficient)),
+        CostKind::Exponential => rows
+            .push(Text::new(
+                "Cost = Fixed + Coefficient * exp(-Rate * Tolerance Band)",
+            ))
+            .push(fixed_row(fixed))
+            .push(coefficient_row(coefficient))
+            .push(form_row(
+                "Rate:",
+                text_input(rate, "Enter a value", &values.rate, Message::EditedCostRate),
+                iss,
+            )),
+        CostKind::Piecewise => rows.push(form_row(
+            "Band:Cost",
+            text_input(
+                table,
+                "e.g. 0.05:12, 0.1:5, 0.4:1",
+                &values.table,
+                Message::EditedCostTable,
+            ),
+            iss,
+        )),
+    }
+}
+
+/// Formats a dimension and its tolerances for display in an entry summary.
+fn dim_summary(dim: &DimTol) -> String {
+    if (dim.tol_neg - dim.tol_pos).abs() < f64::EPSILON {
+        format!("{} +/- {}", dim.dim, dim.tol_pos)
+    } else {
+        format!("{} +{}/-{}", dim.dim, dim.tol_pos, dim.tol_neg)
+    }
+}
+
+/// Notes the name of an entry, its sensitivity and its direction in an entry summary, if they are
+/// not the default.
+fn stack_summary(name: &str, tol: &Tolerance) -> String {
+    let mut notes = Vec::new();
+    if !name.is_empty() {
+        notes.push(format!("Name: {}", name));
+    }
+    if tol.sensitivity() != 1.0 {
+        notes.push(format!("Sensitivity: {}", tol.sensitivity()));
+    }
+    if tol.direction() != 0.0 {
+        notes.push(format!("Direction: {}°", tol.direction()));
+    }
+    if notes.is_empty() {
+        String::new()
+    } else {
+        format!("\n{}", notes.join(", "))
+    }
+}
+
+/// Notes the distribution and mean shift of a dimension in an entry summary, if they are not the
+/// default.
+fn dist_summary(dim: &DimTol) -> String {
+    let mut notes = Vec::new();
+    if dim.dist != TolDistribution::default() {
+        notes.push(dim.dist.to_string());
+    }
+    if dim.mean_shift != 0.0 {
+        notes.push(format!("mean shift {:+}", dim.mean_shift));
+    }
+    if notes.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", notes.join(", "))
+    }
+}
+
 enum NumericString {
     Number,
     Positive,
@@ -898,3 +2218,18 @@ impl NumericString {
         }
     }
 }
+
+/// Reads a number entered in a field, which is `default` if the field is empty, or `None` if it
+/// cannot be read.
+fn parse_or(input: &str, default: f64) -> Option<f64> {
+    if input.is_empty() {
+        Some(default)
+    } else {
+        input.parse::<f64>().ok().filter(|value| value.is_finite())
+    }
+}
+
+/// Rounds a tolerance down to four decimal places, allowing for the rounding error of the value.
+fn round_down(value: f64) -> f64 {
+    (value * 1e4 + 1e-6).floor() / 1e4
+}
@@ -15,6 +15,7 @@ pub struct ToleranceFilter {
     linear_button: button::State,
     float_button: button::State,
     compound_button: button::State,
+    angular_button: button::State,
 }
 impl ToleranceFilter {
     pub fn update(&mut self, message: Message) {
@@ -24,6 +25,7 @@ impl ToleranceFilter {
             linear_button: _,
             float_button: _,
             compound_button: _,
+            angular_button: _,
         } = self;
         match message {
             Message::FilterChanged(filter) => {
@@ -37,7 +39,8 @@ impl ToleranceFilter {
             all_button,
             linear_button,
             float_button,
-            compound_button: _,
+            compound_button,
+            angular_button,
         } = self;
 
         let filter_button = |state, label, filter, current_filter| {
@@ -70,12 +73,25 @@ impl ToleranceFilter {
                         "Float",
                         Filter::Some(Tolerance::Float(FloatTL::default())),
                         *filter_value,
+                    ))
+                    .push(filter_button(
+                        compound_button,
+                        "Compound",
+                        Filter::Some(Tolerance::Compound(CompoundFloatTL::default())),
+                        *filter_value,
+                    ))
+                    .push(filter_button(
+                        angular_button,
+                        "Angular",
+                        Filter::Some(Tolerance::Angular(AngularTL::default())),
+                        *filter_value,
                     )),
             )
             .into()
     }
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Filter {
     All,
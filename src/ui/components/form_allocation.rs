@@ -0,0 +1,172 @@
+use crate::analysis::allocation::AllocationMethod;
+use crate::ui::style;
+use iced::{
+    button, pick_list, text_input, Align, Button, Column, Element, Length, PickList, Row, Text,
+    TextInput,
+};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    MethodSelected(AllocationMethod),
+    TargetSelected(TargetKind),
+    YieldEdited(String),
+    Propose,
+    Accept,
+}
+
+/// Whether the allocation aims for the target capability of the analysis, or for a yield.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum TargetKind {
+    #[default]
+    Capability,
+    Yield,
+}
+impl TargetKind {
+    pub const ALL: [TargetKind; 2] = [TargetKind::Capability, TargetKind::Yield];
+}
+impl std::fmt::Display for TargetKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TargetKind::Capability => "Target Capability",
+                TargetKind::Yield => "Yield",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ToleranceAllocation {
+    pub method: AllocationMethod,
+    pub target: TargetKind,
+    /// Yield in percent, kept as text so it can be typed out digit by digit
+    pub yield_percent: String,
+    state_method: pick_list::State<AllocationMethod>,
+    state_target: pick_list::State<TargetKind>,
+    state_input_yield: text_input::State,
+    state_propose_button: button::State,
+    state_accept_button: button::State,
+}
+impl Default for ToleranceAllocation {
+    fn default() -> Self {
+        ToleranceAllocation {
+            method: AllocationMethod::default(),
+            target: TargetKind::default(),
+            yield_percent: String::from("99.73"),
+            state_method: pick_list::State::default(),
+            state_target: pick_list::State::default(),
+            state_input_yield: text_input::State::default(),
+            state_propose_button: button::State::default(),
+            state_accept_button: button::State::default(),
+        }
+    }
+}
+impl ToleranceAllocation {
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::MethodSelected(method) => self.method = method,
+            Message::TargetSelected(target) => self.target = target,
+            Message::YieldEdited(input) => {
+                if input.is_empty() || input.parse::<f64>().is_ok() {
+                    self.yield_percent = input;
+                }
+            }
+            Message::Propose => {}
+            Message::Accept => {}
+        }
+    }
+
+    /// The yield to allocate for, as a fraction from 0 to 1, if a valid one was entered
+    pub fn yield_fraction(&self) -> Option<f64> {
+        match self.yield_percent.parse::<f64>() {
+            Ok(percent) if percent > 0.0 && percent < 100.0 => Some(percent / 100.0),
+            _ => None,
+        }
+    }
+
+    /// The proposal, if there is one, is shown between the controls and the accept button, which
+    /// is only enabled when the proposal can be accepted.
+    pub fn view<'a>(
+        &'a mut self,
+        proposal: Element<'a, Message>,
+        can_accept: bool,
+        iss: &style::IcedStyleSheet,
+    ) -> Element<'a, Message> {
+        let ToleranceAllocation {
+            method,
+            target,
+            yield_percent,
+            state_method,
+            state_target,
+            state_input_yield,
+            state_propose_button,
+            state_accept_button,
+        } = self;
+        let controls = Row::new()
+            .push(Text::new("Method"))
+            .push(
+                PickList::new(
+                    state_method,
+                    &AllocationMethod::ALL[..],
+                    Some(*method),
+                    Message::MethodSelected,
+                )
+                .padding(10),
+            )
+            .push(
+                PickList::new(
+                    state_target,
+                    &TargetKind::ALL[..],
+                    Some(*target),
+                    Message::TargetSelected,
+                )
+                .padding(10),
+            );
+        let controls = match target {
+            TargetKind::Capability => controls,
+            TargetKind::Yield => controls
+                .push(
+                    TextInput::new(
+                        state_input_yield,
+                        "Enter a value...",
+                        yield_percent,
+                        Message::YieldEdited,
+                    )
+                    .padding(10),
+                )
+                .push(Text::new("%")),
+        }
+        .push(Column::new().width(Length::Fill))
+        .push(
+            Button::new(state_propose_button, Text::new("Propose"))
+                .style(iss.button(&iss.button_action))
+                .padding(10)
+                .on_press(Message::Propose),
+        )
+        .align_items(Align::Center)
+        .spacing(20);
+
+        let accept = Button::new(state_accept_button, Text::new("Accept Tolerances"))
+            .style(iss.button(&iss.button_constructive))
+            .padding(10);
+        let accept = if can_accept {
+            accept.on_press(Message::Accept)
+        } else {
+            accept
+        };
+
+        Column::new()
+            .push(Text::new("Tolerance Allocation").size(24))
+            .push(controls)
+            .push(proposal)
+            .push(
+                Row::new()
+                    .push(Column::new().width(Length::Fill))
+                    .push(accept),
+            )
+            .spacing(20)
+            .into()
+    }
+}
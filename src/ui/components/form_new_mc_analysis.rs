@@ -1,11 +1,28 @@
 //use crate::analysis::*;
+use crate::analysis::structures::{RssMethod, SamplingStrategy};
 use crate::ui::style;
-use iced::{button, text_input, Align, Button, Column, Element, Length, Row, Text, TextInput};
+use iced::{
+    button, pick_list, text_input, Align, Button, Checkbox, Column, Element, Length, PickList, Row,
+    Text, TextInput,
+};
 
 #[derive(Debug, Clone)]
 pub enum Message {
     IterEdited(String),
+    SamplingSelected(SamplingStrategy),
     SigmaEdited(String),
+    SeedEdited(String),
+    LowerSpecEdited(String),
+    UpperSpecEdited(String),
+    TargetCapabilityEdited(String),
+    ImportanceSamplingToggled(bool),
+    ConvergeToggled(bool),
+    ThresholdEdited(String),
+    MaxIterationsEdited(String),
+    RssMethodToggled(RssMethod, bool),
+    MeanShiftFactorEdited(String),
+    StackEquationEdited(String),
+    VectorLoopToggled(bool),
     Calculate,
     //CalculateComplete(Option<structures::McResults>),
 }
@@ -13,10 +30,38 @@ pub enum Message {
 #[derive(Debug, Default, Clone)]
 pub struct NewMonteCarloAnalysis {
     pub n_iteration: usize,
+    pub sampling: SamplingStrategy,
     pub assy_sigma: f64,
+    pub seed: u64,
+    /// Spec limits are kept as text so they can be left empty, or start with a minus sign
+    pub lower_spec: String,
+    pub upper_spec: String,
+    pub target_capability: f64,
+    pub importance_sampling: bool,
+    pub converge: bool,
+    /// Kept as text so small values like 0.001 can be typed out digit by digit
+    pub convergence_threshold: String,
+    pub max_iterations: usize,
+    pub rss_methods: Vec<RssMethod>,
+    pub mean_shift_factor: f64,
+    /// The stack as an expression of the names of its entries. Empty sums the entries.
+    pub stack_equation: String,
+    /// Whether the entries form a vector loop in a plane, rather than a stack along a line
+    pub vector_loop: bool,
+    /// Why the stack equation could not be read, from the last attempt to run the analysis
+    pub equation_error: Option<String>,
     state_calculate_button: button::State,
+    state_sampling: pick_list::State<SamplingStrategy>,
     state_input_assy_sigma: text_input::State,
     state_input_iterations: text_input::State,
+    state_input_seed: text_input::State,
+    state_input_lower_spec: text_input::State,
+    state_input_upper_spec: text_input::State,
+    state_input_target_capability: text_input::State,
+    state_input_threshold: text_input::State,
+    state_input_max_iterations: text_input::State,
+    state_input_mean_shift_factor: text_input::State,
+    state_input_stack_equation: text_input::State,
 }
 impl NewMonteCarloAnalysis {
     pub fn update(&mut self, message: Message) {
@@ -27,22 +72,124 @@ impl NewMonteCarloAnalysis {
                     self.n_iteration = number;
                 }
             }
+            Message::SamplingSelected(sampling) => self.sampling = sampling,
             Message::SigmaEdited(input) => {
                 if input.parse::<f64>().is_ok() {
                     let number = input.parse::<f64>().unwrap();
                     self.assy_sigma = number;
                 }
             }
+            Message::SeedEdited(input) => {
+                if input.parse::<u64>().is_ok() {
+                    let number = input.parse::<u64>().unwrap();
+                    self.seed = number;
+                }
+            }
+            Message::LowerSpecEdited(input) => {
+                if is_spec_limit(&input) {
+                    self.lower_spec = input;
+                }
+            }
+            Message::UpperSpecEdited(input) => {
+                if is_spec_limit(&input) {
+                    self.upper_spec = input;
+                }
+            }
+            Message::TargetCapabilityEdited(input) => {
+                if input.parse::<f64>().is_ok() {
+                    let number = input.parse::<f64>().unwrap();
+                    self.target_capability = number;
+                }
+            }
+            Message::ImportanceSamplingToggled(importance_sampling) => {
+                self.importance_sampling = importance_sampling
+            }
+            Message::ConvergeToggled(converge) => self.converge = converge,
+            Message::ThresholdEdited(input) => {
+                // Zero is kept while typing, as it starts small thresholds like 0.001.
+                let typing = match input.parse::<f64>() {
+                    Ok(number) => number.is_finite() && number >= 0.0,
+                    Err(_) => input.is_empty(),
+                };
+                if typing {
+                    self.convergence_threshold = input;
+                }
+            }
+            Message::MaxIterationsEdited(input) => {
+                // A converging simulation needs at least one iteration to converge on.
+                if let Ok(number) = input.parse::<usize>() {
+                    if number > 0 {
+                        self.max_iterations = number;
+                    }
+                }
+            }
+            Message::RssMethodToggled(method, checked) => {
+                // Keep the methods in a consistent order, so their results line up
+                self.rss_methods = RssMethod::ALL
+                    .iter()
+                    .filter(|&&other| {
+                        if other == method {
+                            checked
+                        } else {
+                            self.rss_methods.contains(&other)
+                        }
+                    })
+                    .cloned()
+                    .collect();
+            }
+            Message::MeanShiftFactorEdited(input) => {
+                if let Ok(number) = input.parse::<f64>() {
+                    if (0.0..=1.0).contains(&number) {
+                        self.mean_shift_factor = number;
+                    }
+                }
+            }
+            Message::VectorLoopToggled(vector_loop) => {
+                self.vector_loop = vector_loop;
+                self.equation_error = self.mode_conflict();
+            }
+            Message::StackEquationEdited(input) => {
+                self.stack_equation = input;
+                self.equation_error = self.mode_conflict();
+            }
             Message::Calculate => {} //Message::CalculateComplete(_) => {}
         }
     }
     pub fn view(&mut self, iss: &style::IcedStyleSheet) -> Element<Message> {
+        let spec_error = self.spec_error();
+        let threshold_error = match self.threshold() {
+            Some(_) => "",
+            None => "Must be above zero",
+        };
         let NewMonteCarloAnalysis {
             n_iteration,
+            sampling,
             assy_sigma,
+            seed,
+            lower_spec,
+            upper_spec,
+            target_capability,
+            importance_sampling,
+            converge,
+            convergence_threshold,
+            max_iterations,
+            rss_methods,
+            mean_shift_factor,
+            stack_equation,
+            vector_loop,
+            equation_error,
             state_calculate_button,
+            state_sampling,
             state_input_assy_sigma,
             state_input_iterations,
+            state_input_seed,
+            state_input_lower_spec,
+            state_input_upper_spec,
+            state_input_target_capability,
+            state_input_threshold,
+            state_input_max_iterations,
+            state_input_mean_shift_factor,
+            state_input_stack_equation,
         } = self;
         let results_header = Column::new()
             .push(
@@ -67,6 +214,16 @@ impl NewMonteCarloAnalysis {
                         )
                         .padding(10),
                     )
+                    .push(Text::new("Sampling"))
+                    .push(
+                        PickList::new(
+                            state_sampling,
+                            &SamplingStrategy::ALL[..],
+                            Some(*sampling),
+                            Message::SamplingSelected,
+                        )
+                        .padding(10),
+                    )
                     .align_items(Align::Center)
                     .spacing(20),
             )
@@ -82,6 +239,142 @@ impl NewMonteCarloAnalysis {
                         )
                         .padding(10),
                     )
+                    .push(Text::new("Seed"))
+                    .push(
+                        TextInput::new(
+                            state_input_seed,
+                            "Enter a value...",
+                            &seed.to_string(),
+                            Message::SeedEdited,
+                        )
+                        .padding(10),
+                    )
+                    .align_items(Align::Center)
+                    .spacing(20),
+            )
+            .push(
+                Row::new()
+                    .push(Text::new("Lower Spec"))
+                    .push(
+                        TextInput::new(
+                            state_input_lower_spec,
+                            "None",
+                            lower_spec,
+                            Message::LowerSpecEdited,
+                        )
+                        .padding(10),
+                    )
+                    .push(Text::new("Upper Spec"))
+                    .push(
+                        TextInput::new(
+                            state_input_upper_spec,
+                            "None",
+                            upper_spec,
+                            Message::UpperSpecEdited,
+                        )
+                        .padding(10),
+                    )
+                    .push(Text::new(spec_error.as_deref().unwrap_or("")))
+                    .align_items(Align::Center)
+                    .spacing(20),
+            )
+            .push(
+                Row::new()
+                    .push(Text::new("Target Capability"))
+                    .push(
+                        TextInput::new(
+                            state_input_target_capability,
+                            "Enter a value...",
+                            &target_capability.to_string(),
+                            Message::TargetCapabilityEdited,
+                        )
+                        .padding(10),
+                    )
+                    .push(Checkbox::new(
+                        *importance_sampling,
+                        "Importance Sample Out of Spec",
+                        Message::ImportanceSamplingToggled,
+                    ))
+                    .align_items(Align::Center)
+                    .spacing(20),
+            )
+            .push(
+                Row::new()
+                    .push(Checkbox::new(
+                        *converge,
+                        "Run Until Converged",
+                        Message::ConvergeToggled,
+                    ))
+                    .push(Text::new("Threshold"))
+                    .push(
+                        TextInput::new(
+                            state_input_threshold,
+                            "Enter a value...",
+                            convergence_threshold,
+                            Message::ThresholdEdited,
+                        )
+                        .padding(10),
+                    )
+                    .push(Text::new(threshold_error))
+                    .push(Text::new("Max Iterations"))
+                    .push(
+                        TextInput::new(
+                            state_input_max_iterations,
+                            "Enter a value...",
+                            &max_iterations.to_string(),
+                            Message::MaxIterationsEdited,
+                        )
+                        .padding(10),
+                    )
+                    .align_items(Align::Center)
+                    .spacing(20),
+            )
+            .push(
+                RssMethod::ALL
+                    .iter()
+                    .fold(Row::new().push(Text::new("RSS Methods")), |row, &method| {
+                        row.push(Checkbox::new(
+                            rss_methods.contains(&method),
+                            method.to_string(),
+                            move |checked| Message::RssMethodToggled(method, checked),
+                        ))
+                    })
+                    .align_items(Align::Center)
+                    .spacing(20),
+            )
+            .push(
+                Row::new()
+                    .push(Text::new("Mean Shift Factor"))
+                    .push(
+                        TextInput::new(
+                            state_input_mean_shift_factor,
+                            "Enter a value...",
+                            &mean_shift_factor.to_string(),
+                            Message::MeanShiftFactorEdited,
+                        )
+                        .padding(10),
+                    )
+                    .align_items(Align::Center)
+                    .spacing(20),
+            )
+            .push(
+                Row::new()
+                    .push(Text::new("Stack Equation"))
+                    .push(
+                        TextInput::new(
+                            state_input_stack_equation,
+                            "Sum of all tolerances",
+                            stack_equation,
+                            Message::StackEquationEdited,
+                        )
+                        .padding(10),
+                    )
+                    .push(Text::new(equation_error.as_deref().unwrap_or("")))
+                    .push(Checkbox::new(
+                        *vector_loop,
+                        "2D Vector Loop",
+                        Message::VectorLoopToggled,
+                    ))
                     .align_items(Align::Center)
                     .spacing(20),
             )
@@ -103,4 +396,100 @@ impl NewMonteCarloAnalysis {
 
         results_header.into()
     }
+
+    /// Why the stack equation cannot be used with the other settings, if it cannot. A vector loop
+    /// is always the sum of its vectors, so it cannot also have a stack equation.
+    pub fn mode_conflict(&self) -> Option<String> {
+        if self.vector_loop && !self.stack_equation.trim().is_empty() {
+            Some(String::from(
+                "Clear the stack equation to analyze a vector loop",
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// The convergence threshold, if it is a finite number above zero.
+    pub fn threshold(&self) -> Option<f64> {
+        self.convergence_threshold
+            .parse::<f64>()
+            .ok()
+            .filter(|threshold| threshold.is_finite() && *threshold > 0.0)
+    }
+
+    /// Why the spec limits cannot be used, if they cannot. Either limit may be left out, but when
+    /// both are given the lower limit must be below the upper limit.
+    pub fn spec_error(&self) -> Option<String> {
+        match (
+            self.lower_spec.parse::<f64>(),
+            self.upper_spec.parse::<f64>(),
+        ) {
+            (Ok(lower), Ok(upper)) if lower >= upper => Some(String::from(
+                "The lower spec limit must be below the upper spec limit",
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// A spec limit may be empty if it is not used, or partially typed as a negative number.
+fn is_spec_limit(input: &str) -> bool {
+    input.is_empty() || input == "-" || input.parse::<f64>().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vector_loop_excludes_stack_equation() {
+        let mut form = NewMonteCarloAnalysis::default();
+        form.update(Message::StackEquationEdited(String::from("a + b")));
+        assert_eq!(form.equation_error, None);
+        form.update(Message::VectorLoopToggled(true));
+        assert!(form.mode_conflict().is_some());
+        assert_eq!(form.equation_error, form.mode_conflict());
+        // Clearing either setting resolves the conflict.
+        form.update(Message::StackEquationEdited(String::new()));
+        assert_eq!(form.equation_error, None);
+        form.update(Message::StackEquationEdited(String::from("a")));
+        assert!(form.equation_error.is_some());
+        form.update(Message::VectorLoopToggled(false));
+        assert_eq!(form.equation_error, None);
+    }
+
+    #[test]
+    fn threshold_must_be_positive() {
+        let mut form = NewMonteCarloAnalysis::default();
+        for input in &["0", "0.", "0.00"] {
+            form.update(Message::ThresholdEdited(input.to_string()));
+            assert_eq!(form.convergence_threshold, *input);
+            assert_eq!(form.threshold(), None);
+        }
+        form.update(Message::ThresholdEdited(String::from("0.001")));
+        assert_eq!(form.threshold(), Some(0.001));
+        // Values that can never become a valid threshold are not accepted.
+        for input in &["-1", "inf", "NaN", "x"] {
+            form.update(Message::ThresholdEdited(input.to_string()));
+            assert_eq!(form.threshold(), Some(0.001));
+        }
+        form.update(Message::ThresholdEdited(String::new()));
+        assert_eq!(form.threshold(), None);
+    }
+
+    #[test]
+    fn spec_limits_must_be_ordered() {
+        let mut form = NewMonteCarloAnalysis::default();
+        form.update(Message::LowerSpecEdited(String::from("2")));
+        assert_eq!(form.spec_error(), None);
+        form.update(Message::UpperSpecEdited(String::from("1")));
+        assert!(form.spec_error().is_some());
+        form.update(Message::UpperSpecEdited(String::from("2")));
+        assert!(form.spec_error().is_some());
+        form.update(Message::UpperSpecEdited(String::from("3")));
+        assert_eq!(form.spec_error(), None);
+        // A single limit is always usable.
+        form.update(Message::UpperSpecEdited(String::new()));
+        assert_eq!(form.spec_error(), None);
+    }
 }
@@ -577,6 +577,15 @@ pub struct IcedStyleSheet {
     pub mc_results_container_inner_padding: NamedPadding,
     pub mc_results_container_outer_padding: NamedPadding,
     pub results: NamedTextSize,
+    pub spec_pass_color: NamedColor,
+    pub spec_fail_color: NamedColor,
+    pub chart_container: StyledContainer,
+    pub chart_padding: NamedPadding,
+    pub chart_bar_color: NamedColor,
+    pub chart_mean_color: NamedColor,
+    pub chart_rss_band_color: NamedColor,
+    pub chart_worst_case_color: NamedColor,
+    pub chart_spec_color: NamedColor,
 
     // area_stack_editor
     pub editor_tol_spacing: NamedSpacing,
@@ -789,6 +798,24 @@ impl Default for IcedStyleSheet {
                     b: 130,
                     a: 1.0,
                 },
+            )
+            .add(
+                "viz_band",
+                SerializableColor {
+                    r: 0,
+                    g: 126,
+                    b: 167,
+                    a: 0.2,
+                },
+            )
+            .add(
+                "viz_bound",
+                SerializableColor {
+                    r: 237,
+                    g: 145,
+                    b: 33,
+                    a: 1.0,
+                },
             );
         let radius = RadiusList::new()
             .add("none", 0.0)
@@ -884,6 +911,21 @@ impl Default for IcedStyleSheet {
             mc_results_container_inner_padding: NamedPadding::new("panel_inner", &padding),
             mc_results_container_outer_padding: NamedPadding::new("panel_outer", &padding),
             results: NamedTextSize::new("p", &text_size),
+            spec_pass_color: NamedColor::new("constructive", &color),
+            spec_fail_color: NamedColor::new("destructive", &color),
+            chart_container: StyledContainer {
+                text_color: NamedColor::new("text", &color),
+                background: NamedColor::new("viz_background", &color),
+                border_color: NamedColor::new("panel_border", &color),
+                border_radius: NamedRadius::new("small", &radius),
+                border_width: NamedWidth::new("thin", &width),
+            },
+            chart_padding: NamedPadding::new("narrow", &padding),
+            chart_bar_color: NamedColor::new("viz_color", &color),
+            chart_mean_color: NamedColor::new("primary", &color),
+            chart_rss_band_color: NamedColor::new("viz_band", &color),
+            chart_worst_case_color: NamedColor::new("viz_bound", &color),
+            chart_spec_color: NamedColor::new("destructive", &color),
 
             //area_stack_editor
             editor_tol_spacing: NamedSpacing::new("near", &spacing),